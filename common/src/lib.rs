@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 pub type Vec2 = vek::vec::repr_c::Vec2<f32>;
 pub type Vec3 = vek::vec::repr_c::Vec3<f32>;
 pub type Vec4 = vek::vec::repr_c::Vec4<f32>;
@@ -10,9 +12,51 @@ pub type Mat4 = vek::mat::repr_c::Mat4<f32>;
 
 pub type Ray = vek::Ray<f32>;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quaternion,
     pub scale: Vec3,
 }
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            position: Vec3::zero(),
+            rotation: Quaternion::identity(),
+            scale: Vec3::one(),
+        }
+    }
+
+    /// Returns the transform which first applies `local` and then `self`, e.g. the world-space
+    /// transform of a child with the transform `local` relative to a parent with the world-space
+    /// transform `self`. Scaling is applied per axis before rotating, so non-uniform scales of
+    /// rotated parents are only approximated.
+    pub fn mul_transform(&self, local: &Transform) -> Transform {
+        Transform {
+            position: self.position + self.rotation * (self.scale * local.position),
+            rotation: self.rotation * local.rotation,
+            scale: self.scale * local.scale,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+ecs::impl_reflect!(Transform {
+    position,
+    rotation,
+    scale
+});
+
+/// The world-space transform of an entity. For entities without a parent this is the same as its
+/// `Transform`, otherwise it is the `Transform` relative to the `GlobalTransform` of the parent.
+/// Kept up to date by the transform propagation system of the engine.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GlobalTransform(pub Transform);
+
+ecs::impl_reflect!(GlobalTransform { 0 });
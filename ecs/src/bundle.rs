@@ -0,0 +1,139 @@
+use std::mem::{ManuallyDrop, MaybeUninit};
+
+use crate::component::{ComponentId, ComponentRegistry};
+
+/// A group of components which can be added to or removed from an entity at once, see
+/// `World::spawn_with`, `World::insert_bundle` and `World::remove_bundle`. An entity is only
+/// moved between archetypes once for the whole bundle, which makes this faster than adding the
+/// components one by one.
+///
+/// Implemented for tuples of up to 12 components and for structs with `impl_bundle!`. A bundle
+/// must not contain the same kind of component twice.
+/// # Safety
+/// `take_components` and `from_components` must pass exactly one pointer for each id returned by
+/// `component_ids`, in the same order, pointing to a valid component of that kind.
+pub unsafe trait Bundle: Send + Sync + Sized + 'static {
+    /// The ids of the components in the bundle, registering them if needed.
+    fn component_ids(registry: &mut ComponentRegistry) -> Vec<ComponentId>;
+
+    /// Calls `f` with a pointer to every component, which takes ownership of it.
+    fn take_components(self, f: &mut impl FnMut(*mut u8));
+
+    /// Builds the bundle by calling `f` with a pointer for every component, to which `f` must
+    /// move the component.
+    /// # Safety
+    /// `f` must write a valid component of the right kind to every pointer.
+    unsafe fn from_components(f: &mut impl FnMut(*mut u8)) -> Self;
+}
+
+/// Returns the id of the component returned by `_get`, registering it if needed. Used by
+/// `impl_bundle!` to find the types of the fields.
+#[doc(hidden)]
+pub fn component_id<S, T>(registry: &mut ComponentRegistry, _get: fn(&S) -> &T) -> ComponentId
+where
+    T: Send + Sync + 'static,
+{
+    registry
+        .id::<T>()
+        .unwrap_or_else(|| registry.register::<T>())
+}
+
+/// Passes ownership of `component` to `f`.
+#[doc(hidden)]
+pub fn take_component<T>(component: T, f: &mut impl FnMut(*mut u8)) {
+    let mut component = ManuallyDrop::new(component);
+    f((&mut *component as *mut T).cast());
+}
+
+/// Reads a component written by `f`.
+/// # Safety
+/// `f` must write a valid `T` to the pointer.
+#[doc(hidden)]
+pub unsafe fn read_component<T>(f: &mut impl FnMut(*mut u8)) -> T {
+    let mut component = MaybeUninit::<T>::uninit();
+    f(component.as_mut_ptr().cast());
+    component.assume_init()
+}
+
+/// Implements `Bundle` for a struct whose fields are all components. Every field must be listed.
+/// # Examples
+/// ```
+/// # use ecs::{impl_bundle, World};
+/// struct Position(f32, f32);
+/// struct Velocity(f32, f32);
+/// struct Body {
+///     position: Position,
+///     velocity: Velocity,
+/// }
+/// impl_bundle!(Body { position, velocity });
+///
+/// let mut world = World::default();
+/// let e = world.spawn_with(Body {
+///     position: Position(0.0, 0.0),
+///     velocity: Velocity(1.0, 0.0),
+/// });
+/// assert_eq!(world.get::<Velocity>(e).unwrap().0, 1.0);
+/// assert!(world.remove_bundle::<Body>(e).is_some());
+/// assert!(world.get::<Position>(e).is_none());
+/// ```
+#[macro_export]
+macro_rules! impl_bundle {
+    ($ty:ty { $($field:tt),* $(,)? }) => {
+        unsafe impl $crate::bundle::Bundle for $ty {
+            fn component_ids(
+                registry: &mut $crate::component::ComponentRegistry,
+            ) -> Vec<$crate::component::ComponentId> {
+                vec![$($crate::bundle::component_id(registry, |b: &Self| &b.$field),)*]
+            }
+
+            fn take_components(self, f: &mut impl FnMut(*mut u8)) {
+                let this = std::mem::ManuallyDrop::new(self);
+                // Every field is moved out exactly once, and `this` is never dropped
+                $($crate::bundle::take_component(
+                    unsafe { std::ptr::read(&this.$field) },
+                    f,
+                );)*
+            }
+
+            unsafe fn from_components(f: &mut impl FnMut(*mut u8)) -> Self {
+                Self {
+                    $($field: $crate::bundle::read_component(f),)*
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($t:ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($t: Send + Sync + 'static),*> Bundle for ($($t,)*) {
+            fn component_ids(registry: &mut ComponentRegistry) -> Vec<ComponentId> {
+                vec![$(registry.id::<$t>().unwrap_or_else(|| registry.register::<$t>()),)*]
+            }
+
+            fn take_components(self, f: &mut impl FnMut(*mut u8)) {
+                let ($($t,)*) = self;
+                $(take_component($t, f);)*
+            }
+
+            unsafe fn from_components(f: &mut impl FnMut(*mut u8)) -> Self {
+                ($(read_component::<$t>(f),)*)
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!();
+impl_bundle_for_tuple!(A);
+impl_bundle_for_tuple!(A, B);
+impl_bundle_for_tuple!(A, B, C);
+impl_bundle_for_tuple!(A, B, C, D);
+impl_bundle_for_tuple!(A, B, C, D, E);
+impl_bundle_for_tuple!(A, B, C, D, E, F);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
@@ -1,8 +1,18 @@
-use std::{alloc::Layout, any::TypeId, borrow::Cow, mem, ptr};
+use std::{
+    alloc::{self, Layout},
+    any::TypeId,
+    borrow::Cow,
+    mem, ptr,
+};
 
 use crate::{Entity, World};
 
-#[derive(Debug)]
+/// A closure run with exclusive access to the world, see `Commands::run`.
+pub type CommandFn = Box<dyn FnOnce(&mut World) + Send>;
+
+/// A change to the world deferred by `Commands` until its `CommandBuffer` is applied. Commands
+/// which own a value keep it behind a raw pointer together with functions to move it into the
+/// world or drop it, so a buffer which is dropped without being applied drops the value.
 pub enum Command {
     Despawn(Entity),
     AddComponent {
@@ -13,8 +23,34 @@ pub enum Command {
         layout: Layout,
         drop: unsafe fn(*mut u8),
     },
+    RemoveComponent {
+        entity: Entity,
+        remove: fn(&mut World, Entity),
+    },
+    InsertBundle {
+        entity: Entity,
+        bundle: *mut u8, // Is set to null after ownership is transferred to the world
+        insert: unsafe fn(&mut World, Entity, *mut u8),
+        drop: unsafe fn(*mut u8),
+    },
+    RemoveBundle {
+        entity: Entity,
+        remove: fn(&mut World, Entity),
+    },
+    InsertResource {
+        resource: *mut u8, // Is set to null after ownership is transferred to the world
+        insert: unsafe fn(&mut World, *mut u8),
+        drop: unsafe fn(*mut u8),
+    },
+    RemoveResource(fn(&mut World)),
+    Run(Option<CommandFn>), // Is set to `None` after it is run
 }
 
+// SAFETY: the values behind the raw pointers are only moved into the world or dropped, never
+// shared, and `Commands` only puts `Send` values there (components, bundles, resources and
+// closures), so a command can be moved to and executed or dropped on another thread.
+unsafe impl Send for Command {}
+
 impl Command {
     pub(super) fn execute(mut self, world: &mut World) {
         match &mut self {
@@ -41,22 +77,69 @@ impl Command {
                             .register_raw(*type_id, name, *layout, *drop)
                     },
                 };
-                unsafe { world.add_raw(*entity, *component, comp_id) };
+                unsafe {
+                    world.add_raw(*entity, *component, comp_id);
+                    // The world moved the component out of its allocation
+                    dealloc(*component, *layout);
+                }
                 *component = ptr::null_mut();
             }
+            &mut Command::RemoveComponent { entity, remove }
+            | &mut Command::RemoveBundle { entity, remove } => remove(world, entity),
+            Command::InsertBundle {
+                entity,
+                bundle,
+                insert,
+                ..
+            } => {
+                unsafe { insert(world, *entity, *bundle) };
+                *bundle = ptr::null_mut();
+            }
+            Command::InsertResource {
+                resource, insert, ..
+            } => {
+                unsafe { insert(world, *resource) };
+                *resource = ptr::null_mut();
+            }
+            &mut Command::RemoveResource(remove) => remove(world),
+            Command::Run(f) => {
+                if let Some(f) = f.take() {
+                    f(world);
+                }
+            }
         }
     }
 }
 
 impl Drop for Command {
     fn drop(&mut self) {
-        match self {
-            &mut Command::AddComponent {
-                component, drop, ..
+        match *self {
+            Command::AddComponent {
+                component,
+                layout,
+                drop,
+                ..
             } if !component.is_null() => unsafe {
                 drop(component);
+                dealloc(component, layout);
+            },
+            Command::InsertBundle { bundle, drop, .. } if !bundle.is_null() => unsafe {
+                drop(bundle);
+            },
+            Command::InsertResource { resource, drop, .. } if !resource.is_null() => unsafe {
+                drop(resource);
             },
             _ => {}
         }
     }
 }
+
+/// Frees the memory of a component allocated by `Commands::add`.
+/// # Safety
+/// `component` must have been allocated by a `Box` of a type with `layout`, and must not be used
+/// again.
+unsafe fn dealloc(component: *mut u8, layout: Layout) {
+    if layout.size() != 0 {
+        alloc::dealloc(component, layout);
+    }
+}
@@ -1,8 +1,10 @@
+use std::fmt;
+
 use crate::World;
 
 use super::Command;
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct CommandBuffer {
     commands: Vec<Command>,
 }
@@ -27,3 +29,11 @@ impl CommandBuffer {
         }
     }
 }
+
+impl fmt::Debug for CommandBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandBuffer")
+            .field("len", &self.commands.len())
+            .finish()
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     alloc::Layout,
     any::{self, TypeId},
     borrow::Cow,
+    mem,
 };
 
 mod command;
@@ -10,7 +11,7 @@ mod command_buffer;
 pub(crate) use command::Command;
 pub use command_buffer::CommandBuffer;
 
-use crate::{Entities, Entity};
+use crate::{Bundle, Entities, Entity, World};
 
 /// Allows commands to be issued without exclusive access the world by deferring them to be run at
 /// a later state, when exclusive access to the world is available.
@@ -54,6 +55,19 @@ impl<'b, 'e> Commands<'b, 'e> {
         self.entities.spawn()
     }
 
+    /// Creates `n` new entities at once. See `Entities::reserve`.
+    pub fn reserve(&mut self, n: usize) -> Vec<Entity> {
+        self.entities.reserve(n)
+    }
+
+    /// Creates a new `entity` which is given the components in `bundle` when the command buffer
+    /// is applied. See `World::spawn_with`.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        self.insert_bundle(entity, bundle);
+        entity
+    }
+
     /// Despawns an entity, removing its components (if any).
     pub fn despawn(&mut self, entity: Entity) {
         self.buffer.add(Command::Despawn(entity));
@@ -63,7 +77,7 @@ impl<'b, 'e> Commands<'b, 'e> {
     /// registered automatically. Returns `true` if `entity` did not have this kind of component
     /// before and `entity` exists. If `entity` exists and the component was already present,
     /// the old component is dropped and replaced with the new one.
-    pub fn add<T: 'static>(&mut self, entity: Entity, component: T) {
+    pub fn add<T: Send + Sync + 'static>(&mut self, entity: Entity, component: T) {
         unsafe fn drop<T: 'static>(ptr: *mut u8) {
             ptr.cast::<T>().drop_in_place();
         }
@@ -79,4 +93,69 @@ impl<'b, 'e> Commands<'b, 'e> {
             drop: drop::<T>,
         });
     }
+
+    /// Removes a component from an entity and drops it. See `World::remove`.
+    pub fn remove<T: 'static>(&mut self, entity: Entity) {
+        self.buffer.add(Command::RemoveComponent {
+            entity,
+            remove: |world, entity| {
+                world.remove::<T>(entity);
+            },
+        });
+    }
+
+    /// Adds every component in `bundle` to an entity. See `World::insert_bundle`.
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) {
+        unsafe fn insert<B: Bundle>(world: &mut World, entity: Entity, bundle: *mut u8) {
+            world.insert_bundle(entity, *Box::from_raw(bundle.cast::<B>()));
+        }
+        unsafe fn drop<B: Bundle>(bundle: *mut u8) {
+            mem::drop(Box::from_raw(bundle.cast::<B>()));
+        }
+        self.buffer.add(Command::InsertBundle {
+            entity,
+            bundle: Box::into_raw(Box::new(bundle)).cast(),
+            insert: insert::<B>,
+            drop: drop::<B>,
+        });
+    }
+
+    /// Removes the components in `B` from an entity and drops them. See `World::remove_bundle`.
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) {
+        self.buffer.add(Command::RemoveBundle {
+            entity,
+            remove: |world, entity| {
+                world.remove_bundle::<B>(entity);
+            },
+        });
+    }
+
+    /// Adds a resource to the world, replacing any previous resource of the same type. See
+    /// `World::add_resource`.
+    pub fn add_resource<T: Send + Sync + 'static>(&mut self, resource: T) {
+        unsafe fn insert<T: Send + Sync + 'static>(world: &mut World, resource: *mut u8) {
+            world.add_resource(*Box::from_raw(resource.cast::<T>()));
+        }
+        unsafe fn drop<T>(resource: *mut u8) {
+            mem::drop(Box::from_raw(resource.cast::<T>()));
+        }
+        self.buffer.add(Command::InsertResource {
+            resource: Box::into_raw(Box::new(resource)).cast(),
+            insert: insert::<T>,
+            drop: drop::<T>,
+        });
+    }
+
+    /// Removes a resource from the world and drops it. See `World::remove_resource`.
+    pub fn remove_resource<T: 'static>(&mut self) {
+        self.buffer.add(Command::RemoveResource(|world| {
+            world.remove_resource::<T>();
+        }));
+    }
+
+    /// Runs `f` with exclusive access to the world when the command buffer is applied, e.g. to
+    /// make changes there are no other commands for.
+    pub fn run(&mut self, f: impl FnOnce(&mut World) + Send + 'static) {
+        self.buffer.add(Command::Run(Some(Box::new(f))));
+    }
 }
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, ops, slice};
+
+use super::ComponentId;
+
+/// Identifies an `Archetype` in `Archetypes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArchetypeId(u32);
+
+impl ArchetypeId {
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Where the archetype stored components of an entity live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+/// A set of archetype stored components together with all entities that have exactly those
+/// archetype stored components. The components themselves live in one column per archetype in the
+/// `Storage` of each kind of component, where the `n`th row belongs to the `n`th entity in
+/// `entities`.
+#[derive(Debug)]
+pub struct Archetype {
+    id: ArchetypeId,
+    // Sorted
+    components: Vec<ComponentId>,
+    entities: Vec<u32>,
+}
+
+impl Archetype {
+    pub fn id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    /// The components of this archetype, sorted by id.
+    pub fn components(&self) -> &[ComponentId] {
+        &self.components
+    }
+
+    /// The ids of the entities in this archetype. The index of an entity is its row.
+    pub fn entities(&self) -> &[u32] {
+        &self.entities
+    }
+
+    pub fn contains(&self, component: ComponentId) -> bool {
+        self.components.binary_search(&component).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// Keeps track of which entities have which set of archetype stored components, i.e. components
+/// registered with `StorageType::Archetype`. Entities without any such components are not part of
+/// any archetype.
+#[derive(Debug, Default)]
+pub struct Archetypes {
+    // Indexed by `ArchetypeId`s
+    archetypes: Vec<Archetype>,
+    ids: HashMap<Vec<ComponentId>, ArchetypeId>,
+    // Indexed by entity ids
+    locations: Vec<Option<Location>>,
+}
+
+impl Archetypes {
+    /// Returns where the archetype stored components of the entity with id `entity_id` live, or
+    /// `None` if it does not have any.
+    pub fn location(&self, entity_id: u32) -> Option<Location> {
+        self.locations.get(entity_id as usize).copied().flatten()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Archetype> {
+        self.archetypes.iter()
+    }
+
+    /// Iterates over all archetypes containing every component in `components`.
+    pub fn matching<'a, 'c>(
+        &'a self,
+        components: &'c [ComponentId],
+    ) -> impl DoubleEndedIterator<Item = &'a Archetype> + 'c
+    where
+        'a: 'c,
+    {
+        self.archetypes
+            .iter()
+            .filter(move |a| components.iter().all(|&c| a.contains(c)))
+    }
+
+    /// Returns the id of the archetype with exactly the given components, creating it if it does
+    /// not exist yet.
+    pub(crate) fn get_or_insert(&mut self, mut components: Vec<ComponentId>) -> ArchetypeId {
+        components.sort_unstable();
+        components.dedup();
+        if let Some(&id) = self.ids.get(&components) {
+            return id;
+        }
+        let id = ArchetypeId(
+            self.archetypes
+                .len()
+                .try_into()
+                .expect("Too many archetypes"),
+        );
+        self.ids.insert(components.clone(), id);
+        self.archetypes.push(Archetype {
+            id,
+            components,
+            entities: Vec::new(),
+        });
+        id
+    }
+
+    /// Adds an entity to the end of an archetype. The caller is responsible for adding its
+    /// components to the end of the columns.
+    pub(crate) fn push(&mut self, archetype: ArchetypeId, entity_id: u32) -> Location {
+        let entities = &mut self.archetypes[archetype.index()].entities;
+        let location = Location {
+            archetype,
+            row: entities.len(),
+        };
+        entities.push(entity_id);
+
+        let index = entity_id as usize;
+        if self.locations.len() <= index {
+            self.locations.resize(index + 1, None);
+        }
+        self.locations[index] = Some(location);
+        location
+    }
+
+    /// Removes the entity at `location` by moving the last entity of the archetype into its place.
+    /// The caller is responsible for doing the same to the columns of the archetype.
+    pub(crate) fn swap_remove(&mut self, location: Location) {
+        let entities = &mut self.archetypes[location.archetype.index()].entities;
+        let removed = entities.swap_remove(location.row);
+        self.locations[removed as usize] = None;
+        if let Some(&moved) = entities.get(location.row) {
+            self.locations[moved as usize] = Some(location);
+        }
+    }
+}
+
+impl ops::Index<ArchetypeId> for Archetypes {
+    type Output = Archetype;
+
+    fn index(&self, id: ArchetypeId) -> &Self::Output {
+        &self.archetypes[id.index()]
+    }
+}
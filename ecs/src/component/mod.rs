@@ -1,7 +1,12 @@
+mod archetype;
 mod registry;
 mod storage;
 
+pub use archetype::{Archetype, ArchetypeId, Archetypes, Location};
+pub(crate) use registry::{clone_ptr, BorrowStatus};
 pub use registry::{
-    ComponentEntry, ComponentEntryRef, ComponentId, ComponentInfo, ComponentRegistry,
+    CloneFn, ComponentEntry, ComponentEntryRef, ComponentId, ComponentInfo, ComponentRegistry,
+    MapEntitiesFn, Ref,
 };
-pub use storage::{Storage, StorageType};
+pub(crate) use storage::ComponentBuffer;
+pub use storage::{ComponentTicks, Indices, Storage, StorageType};
@@ -2,25 +2,46 @@ use std::{
     alloc::Layout,
     any::{self, TypeId},
     borrow::Cow,
-    cell::RefCell,
     collections::HashMap,
-    fmt, ops,
-    rc::Rc,
+    fmt,
+    marker::PhantomData,
+    ops::{self, Deref},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
 };
 
 use super::{Storage, StorageType};
+use crate::{
+    dynamic::Schema,
+    hierarchy::{Children, Parent},
+    reflect::{Reflect, ReflectComponent, Typed},
+    scene::{EntityMap, MapEntities},
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ComponentId(u16);
 
+/// Writes a clone of the component pointed to by the first pointer to the second pointer. See
+/// `ComponentRegistry::register_clone`.
+pub type CloneFn = unsafe fn(*const u8, *mut u8);
+
+/// Updates the references to other entities in the component pointed to. See
+/// `ComponentRegistry::register_map_entities`.
+pub type MapEntitiesFn = unsafe fn(*mut u8, &EntityMap);
+
 /// Basic metadata about a kind of component.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub struct ComponentInfo {
     name: Cow<'static, str>,
     type_id: Option<TypeId>,
     id: ComponentId,
-    // TODO: maybe add some sort of is_thread_safe bool or require `Send + Sync` for all
-    // components.
+    reflect: Option<ReflectComponent>,
+    clone: Option<CloneFn>,
+    map_entities: Option<MapEntitiesFn>,
+    dynamic: Option<Arc<Schema>>,
 }
 
 impl ComponentInfo {
@@ -35,8 +56,43 @@ impl ComponentInfo {
     pub fn id(&self) -> ComponentId {
         self.id
     }
+
+    /// The reflection of this kind of component if it was registered with `register_reflect`.
+    pub fn reflect(&self) -> Option<&ReflectComponent> {
+        self.reflect.as_ref()
+    }
+
+    /// The function cloning this kind of component if it was registered with `register_clone`.
+    pub fn clone_fn(&self) -> Option<CloneFn> {
+        self.clone
+    }
+
+    /// The function mapping the entities this kind of component refers to, if it was registered
+    /// with `register_map_entities`.
+    pub fn map_entities_fn(&self) -> Option<MapEntitiesFn> {
+        self.map_entities
+    }
+
+    /// The schema of this kind of component if it was registered with `register_dynamic`.
+    pub fn schema(&self) -> Option<&Arc<Schema>> {
+        self.dynamic.as_ref()
+    }
 }
 
+impl PartialEq for ComponentInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.type_id == other.type_id
+            && self.id == other.id
+            && self.reflect == other.reflect
+            && self.clone.is_some() == other.clone.is_some()
+            && self.map_entities.is_some() == other.map_entities.is_some()
+            && self.dynamic == other.dynamic
+    }
+}
+
+impl Eq for ComponentInfo {}
+
 /// A kind of components registered in a `ComponentRegistry`. Includes both metadata about the kind
 /// of component and all the components of this kind.
 #[derive(Debug)]
@@ -54,34 +110,52 @@ impl ComponentEntry {
 #[derive(Debug)]
 pub struct ComponentEntryRef {
     ptr: *mut ComponentEntry,
-    borrowed: Rc<RefCell<Vec<BorrowStatus>>>,
+    // Kept apart from the entry, so other threads can check it while the storage is borrowed
+    // mutably
+    borrowed: *const BorrowStatus,
     mutable: bool,
 }
 
+// SAFETY: the borrow status is tracked atomically and components are `Send + Sync`, so a borrow can
+// be used from and released on any thread. A mutable borrow only gives out `&mut Storage`, which
+// no other thread can reference until the borrow is released.
+unsafe impl Send for ComponentEntryRef {}
+unsafe impl Sync for ComponentEntryRef {}
+
 impl ComponentEntryRef {
     pub fn get(&self) -> &ComponentEntry {
         unsafe { &*self.ptr }
     }
 
-    pub fn get_mut(&mut self) -> &mut ComponentEntry {
+    pub fn get_mut(&mut self) -> &mut Storage {
         assert!(
             self.mutable,
             "Tried to get mutable access to immutable borrow to component entry"
         );
-        unsafe { &mut *self.ptr }
+        // Safety: only project to the storage, the info can still be read by other threads
+        unsafe { &mut (*self.ptr).storage }
     }
 
     pub fn mutable(&self) -> bool {
         self.mutable
     }
 
-    fn try_new(
+    /// Same as `get` but the lifetime of the returned reference is not bound to `self`.
+    /// # Safety
+    /// The returned reference must not outlive `self`.
+    pub(crate) unsafe fn get_unbounded<'a>(&self) -> &'a ComponentEntry {
+        &*self.ptr
+    }
+
+    /// # Safety
+    /// `ptr` and `borrowed` must be valid for as long as the borrow is alive, and `borrowed` must
+    /// be the borrow status of the entry at `ptr`.
+    unsafe fn try_new(
         ptr: *mut ComponentEntry,
-        borrowed: Rc<RefCell<Vec<BorrowStatus>>>,
+        borrowed: *const BorrowStatus,
         mutable: bool,
     ) -> Option<Self> {
-        let id = unsafe { (*ptr).info.id.0 as usize };
-        borrowed.borrow_mut()[id].add_borrow(mutable).ok()?;
+        (*borrowed).add_borrow(mutable).ok()?;
 
         Some(Self {
             ptr,
@@ -93,21 +167,94 @@ impl ComponentEntryRef {
 
 impl Drop for ComponentEntryRef {
     fn drop(&mut self) {
-        let id = unsafe { (*self.ptr).info.id.0 as usize };
-        self.borrowed.borrow_mut()[id].remove_borrow(self.mutable);
+        unsafe { (*self.borrowed).remove_borrow(self.mutable) };
+    }
+}
+
+/// Shared access to a component which keeps its kind of component borrowed until it is dropped,
+/// so the component can not be changed through a query in the meantime. See `World::get`.
+pub struct Ref<'w, T: ?Sized> {
+    value: NonNull<T>,
+    // `None` if `value` is not owned by a storage, see `from_static`
+    _entry: Option<ComponentEntryRef>,
+    _marker: PhantomData<&'w T>,
+}
+
+// SAFETY: a `Ref` only gives shared access to `T`, and the borrow can be released on any thread.
+unsafe impl<T: ?Sized + Sync> Send for Ref<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for Ref<'_, T> {}
+
+impl<'w, T: ?Sized> Ref<'w, T> {
+    /// # Safety
+    /// `value` must point to a component owned by the storage of `entry`, or something borrowed
+    /// from it, which is valid for as long as `entry` is alive.
+    pub(crate) unsafe fn new(entry: ComponentEntryRef, value: NonNull<T>) -> Self {
+        Self {
+            value,
+            _entry: Some(entry),
+            _marker: PhantomData,
+        }
+    }
+
+    /// A `Ref` to a value which is not a component, e.g. an empty slice.
+    pub(crate) fn from_static(value: &'static T) -> Self {
+        Self {
+            value: NonNull::from(value),
+            _entry: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Makes a `Ref` to a part of the component, e.g. a field, keeping the component borrowed.
+    pub fn map<U: ?Sized>(mut orig: Self, f: impl FnOnce(&T) -> &U) -> Ref<'w, U> {
+        let value = NonNull::from(f(&orig));
+        Ref {
+            value,
+            _entry: orig._entry.take(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Having a `Drop` implementation makes the world stay borrowed until the `Ref` is dropped, not
+// only until it is last used, so the world can not be changed while the component is borrowed.
+impl<T: ?Sized> Drop for Ref<'_, T> {
+    fn drop(&mut self) {}
+}
+
+impl<T: ?Sized> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
     }
 }
 
 /// A registry for different kinds of components. Includes both metadata about the kinds of
 /// components and all components themselves.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct ComponentRegistry {
-    // Indexed by ComponentId's
+    // Indexed by ComponentId's. Only referenced one at a time, through raw pointers, since a
+    // mutable borrow of an entry can be alive on another thread.
     entries: Vec<ComponentEntry>,
+    // The borrow status of every entry, indexed by ComponentId's as well
+    borrowed: Vec<BorrowStatus>,
 
     rust_types: HashMap<TypeId, ComponentId>,
-
-    borrowed: Rc<RefCell<Vec<BorrowStatus>>>,
+    // The first kind registered with each name
+    names: HashMap<Cow<'static, str>, ComponentId>,
 }
 
 impl ComponentRegistry {
@@ -115,7 +262,16 @@ impl ComponentRegistry {
     /// the same registry.
     pub fn register<T>(&mut self) -> ComponentId
     where
-        T: 'static,
+        T: Send + Sync + 'static,
+    {
+        self.register_with_storage::<T>(StorageType::VecStorage)
+    }
+
+    /// Same as `register` but lets the component kind choose how its components are stored. See
+    /// `Storage` for the different kinds of storages.
+    pub fn register_with_storage<T>(&mut self, storage_type: StorageType) -> ComponentId
+    where
+        T: Send + Sync + 'static,
     {
         unsafe fn drop_ptr<T>(ptr: *mut u8) {
             ptr.cast::<T>().drop_in_place();
@@ -124,11 +280,12 @@ impl ComponentRegistry {
         // Safety: if the type id and layout do not match here or `drop_ptr` is invalid, thats on
         // Rust, not us.
         unsafe {
-            self.register_raw(
+            self.register_raw_with_storage(
                 TypeId::of::<T>(),
                 Cow::Borrowed(any::type_name::<T>()),
                 Layout::new::<T>(),
                 drop_ptr::<T>,
+                storage_type,
             )
         }
     }
@@ -137,7 +294,8 @@ impl ComponentRegistry {
     /// the same registry.
     /// # Safety
     /// The `type_id` and `layout` must match and `drop` must be a valid drop function for the
-    /// given `type_id`.
+    /// given `type_id`. The type must be `Send + Sync`, since the world can be shared between
+    /// threads.
     pub unsafe fn register_raw(
         &mut self,
         type_id: TypeId,
@@ -145,22 +303,153 @@ impl ComponentRegistry {
         layout: Layout,
         drop: unsafe fn(*mut u8),
     ) -> ComponentId {
-        let id = ComponentId(self.entries.len().try_into().unwrap());
+        self.register_raw_with_storage(type_id, name, layout, drop, StorageType::VecStorage)
+    }
 
+    /// Same as `register_raw` but lets the component kind choose how its components are stored.
+    /// # Safety
+    /// See `register_raw`.
+    pub unsafe fn register_raw_with_storage(
+        &mut self,
+        type_id: TypeId,
+        name: Cow<'static, str>,
+        layout: Layout,
+        drop: unsafe fn(*mut u8),
+        storage_type: StorageType,
+    ) -> ComponentId {
+        let id = self.push_entry(name, Some(type_id), layout, drop, storage_type);
         let old = self.rust_types.insert(type_id, id);
         debug_assert!(old.is_none());
-        assert!(self.check_exclusive_access());
 
-        let info = ComponentInfo {
-            name,
-            type_id: Some(type_id),
-            id,
-        };
-        let storage = Storage::new(StorageType::VecStorage, layout, drop);
+        // The hierarchy always refers to other entities
+        if type_id == TypeId::of::<Parent>() {
+            self[id].info.map_entities = Some(map_entities_ptr::<Parent>);
+        } else if type_id == TypeId::of::<Children>() {
+            self[id].info.map_entities = Some(map_entities_ptr::<Children>);
+        }
+        id
+    }
 
-        self.entries.push(ComponentEntry::new(info, storage));
-        self.borrowed.borrow_mut().push(BorrowStatus::default());
+    /// Registers a kind of component defined at runtime by `schema`, see `dynamic::Schema`.
+    /// Panics if a kind of component named like the schema is already registered.
+    pub fn register_dynamic(&mut self, schema: Schema) -> ComponentId {
+        self.register_dynamic_with_storage(schema, StorageType::VecStorage)
+    }
+
+    /// Same as `register_dynamic` but lets the component kind choose how its components are
+    /// stored.
+    pub fn register_dynamic_with_storage(
+        &mut self,
+        schema: Schema,
+        storage_type: StorageType,
+    ) -> ComponentId {
+        // Dynamic components only contain plain data
+        unsafe fn drop_nothing(_: *mut u8) {}
+
+        assert!(
+            !self.names.contains_key(schema.name()),
+            "A component named {} is already registered",
+            schema.name()
+        );
+        let name = Cow::Owned(schema.name().to_owned());
+        let layout = schema.layout();
+        // Safety: the components are plain data, which does not need to be dropped
+        let id = unsafe { self.push_entry(name, None, layout, drop_nothing, storage_type) };
+        self[id].info.dynamic = Some(Arc::new(schema));
+        id
+    }
+
+    /// Registers the reflection of `T`, so its components can be inspected and modified without
+    /// knowing their type, see `World::get_reflect`. Registers `T` as a component kind first if
+    /// needed.
+    pub fn register_reflect<T>(&mut self) -> ComponentId
+    where
+        T: Reflect + Typed,
+    {
+        let id = self.id::<T>().unwrap_or_else(|| self.register::<T>());
+        self[id].info.reflect = Some(ReflectComponent::new::<T>());
+        id
+    }
+
+    /// Lets components of kind `T` be cloned without knowing their type, e.g. by
+    /// `World::clone_entity`. Registers `T` as a component kind first if needed.
+    pub fn register_clone<T>(&mut self) -> ComponentId
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let id = self.id::<T>().unwrap_or_else(|| self.register::<T>());
+        // Safety: `clone_ptr` clones `T`s, which is what the component kind contains
+        unsafe { self.register_clone_raw(id, clone_ptr::<T>) };
+        id
+    }
+
+    /// Same as `register_clone` for component kinds which are not rust types known at compile
+    /// time.
+    /// # Safety
+    /// `clone` must write a valid clone of a component of kind `id` to the second pointer.
+    pub unsafe fn register_clone_raw(&mut self, id: ComponentId, clone: CloneFn) {
+        self[id].info.clone = Some(clone);
+    }
+
+    /// Lets the references to other entities in components of kind `T` be mapped when the
+    /// components are moved to another world, see `World::append`. Registers `T` as a component
+    /// kind first if needed. `Parent` and `Children` are always mapped.
+    pub fn register_map_entities<T>(&mut self) -> ComponentId
+    where
+        T: MapEntities + Send + Sync + 'static,
+    {
+        let id = self.id::<T>().unwrap_or_else(|| self.register::<T>());
+        self[id].info.map_entities = Some(map_entities_ptr::<T>);
+        id
+    }
 
+    /// Returns the id in this registry of the kind of component with id `id` in `other`,
+    /// registering it if needed. Used to move components between worlds, which may have given
+    /// different ids to the same kind. Kinds are matched by their rust type, or by their name if
+    /// they are dynamic, and get the functions registered for them in `other` which they do not
+    /// have in this registry, e.g. their reflection. Panics if a dynamic kind is registered with
+    /// another schema in this registry.
+    pub fn import(&mut self, other: &ComponentRegistry, id: ComponentId) -> ComponentId {
+        let entry = &other[id];
+        let info = &entry.info;
+        let storage_type = entry.storage.storage_type();
+        let id = match info.type_id {
+            Some(type_id) => self
+                .component_id_from_type_id(type_id)
+                // Safety: the type id, layout and drop function are those of the kind in `other`
+                .unwrap_or_else(|| unsafe {
+                    self.register_raw_with_storage(
+                        type_id,
+                        info.name.clone(),
+                        entry.storage.item_layout(),
+                        entry.storage.drop_fn(),
+                        storage_type,
+                    )
+                }),
+            None => {
+                let schema = info
+                    .schema()
+                    .expect("Components without a type are dynamic");
+                match self.id_by_name(schema.name()) {
+                    Some(id) => {
+                        assert!(
+                            self[id].info.schema() == Some(schema),
+                            "The component {} is registered differently in the two registries",
+                            schema.name()
+                        );
+                        id
+                    }
+                    None => self.register_dynamic_with_storage((**schema).clone(), storage_type),
+                }
+            }
+        };
+
+        let imported = &mut self[id].info;
+        if imported.reflect.is_none() {
+            imported.reflect = info.reflect.clone();
+        }
+        imported.clone = imported.clone.or(info.clone);
+        imported.map_entities = imported.map_entities.or(info.map_entities);
         id
     }
 
@@ -176,6 +465,12 @@ impl ComponentRegistry {
         self.rust_types.get(&TypeId::of::<T>()).copied()
     }
 
+    /// The id of the kind of component named `name`, i.e. the name of its schema for dynamic
+    /// components or the full type name for rust types.
+    pub fn id_by_name(&self, name: &str) -> Option<ComponentId> {
+        self.names.get(name).copied()
+    }
+
     pub fn component<T>(&self) -> Option<&ComponentEntry>
     where
         T: 'static,
@@ -183,13 +478,51 @@ impl ComponentRegistry {
         self.id::<T>().map(|id| &self[id])
     }
 
+    /// The metadata of every registered kind of component, in the order of their ids.
+    pub fn infos(&self) -> impl Iterator<Item = &ComponentInfo> {
+        let entries = self.entries.as_ptr();
+        // Safety: the info of an entry is never borrowed mutably through a `ComponentEntryRef`
+        (0..self.entries.len()).map(move |i| unsafe { &(*entries.add(i)).info })
+    }
+
     pub fn entries_mut(&mut self) -> &mut [ComponentEntry] {
         assert!(self.check_exclusive_access());
         &mut self.entries
     }
 
+    /// # Safety
+    /// `drop` must be a valid drop function for components with `layout`, see `register_raw`.
+    unsafe fn push_entry(
+        &mut self,
+        name: Cow<'static, str>,
+        type_id: Option<TypeId>,
+        layout: Layout,
+        drop: unsafe fn(*mut u8),
+        storage_type: StorageType,
+    ) -> ComponentId {
+        let id = ComponentId(self.entries.len().try_into().unwrap());
+        assert!(self.check_exclusive_access());
+
+        self.names.entry(name.clone()).or_insert(id);
+        let info = ComponentInfo {
+            name,
+            type_id,
+            id,
+            reflect: None,
+            clone: None,
+            map_entities: None,
+            dynamic: None,
+        };
+        let storage = Storage::new(storage_type, layout, drop);
+
+        self.entries.push(ComponentEntry::new(info, storage));
+        self.borrowed.push(BorrowStatus::default());
+
+        id
+    }
+
     fn check_exclusive_access(&self) -> bool {
-        self.borrowed.borrow().iter().all(|b| b.is_free())
+        self.borrowed.iter().all(BorrowStatus::is_free)
     }
 
     /// Tries to borrow the entry for the component with the given id. Set `mutable` to `true` if
@@ -200,10 +533,34 @@ impl ComponentRegistry {
     /// returned. Call the function after the borrow will no longer be accessed to indicate that
     /// the component is available to be borrowed again.
     pub fn try_borrow(&self, comp_id: ComponentId, mutable: bool) -> Option<ComponentEntryRef> {
-        let entry =
-            &self.entries[comp_id.0 as usize] as *const ComponentEntry as *mut ComponentEntry;
+        let index = comp_id.0 as usize;
+        assert!(index < self.entries.len());
+        // Safety: the entries and their borrow status are not moved while they are borrowed,
+        // since registering a kind of component needs exclusive access
+        unsafe {
+            let entry = self.entries.as_ptr().add(index).cast_mut();
+            ComponentEntryRef::try_new(entry, &self.borrowed[index], mutable)
+        }
+    }
 
-        ComponentEntryRef::try_new(entry, self.borrowed.clone(), mutable)
+    /// Returns the entry at `id` without making a reference to the others, which may be
+    /// borrowed mutably. Panics if the entry is borrowed mutably.
+    fn entry(&self, id: ComponentId) -> &ComponentEntry {
+        let index = id.0 as usize;
+        assert!(self.borrowed[index].is_readable());
+        // Safety: the index is in bounds since it is for `borrowed`, and the entry is not
+        // borrowed mutably
+        unsafe { &*self.entries.as_ptr().add(index) }
+    }
+}
+
+impl fmt::Debug for ComponentRegistry {
+    // Only the metadata, since the storages may be borrowed mutably
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentRegistry")
+            .field("infos", &self.infos().collect::<Vec<_>>())
+            .field("borrowed", &self.borrowed)
+            .finish()
     }
 }
 
@@ -211,74 +568,89 @@ impl ops::Index<ComponentId> for ComponentRegistry {
     type Output = ComponentEntry;
 
     fn index(&self, id: ComponentId) -> &Self::Output {
-        let index = id.0 as usize;
-        assert!(self.borrowed.borrow()[index].is_readable());
-        &self.entries[index]
+        self.entry(id)
     }
 }
 
 impl ops::IndexMut<ComponentId> for ComponentRegistry {
     fn index_mut(&mut self, id: ComponentId) -> &mut Self::Output {
-        let index = id.0 as usize;
-        assert!(self.borrowed.borrow()[index].is_free());
-        &mut self.entries[index]
+        assert!(self.borrowed[id.0 as usize].is_free());
+        &mut self.entries[id.0 as usize]
     }
 }
 
+/// Clones the `T` at `src` to `dst`.
+/// # Safety
+/// `src` must point to a valid `T` and `dst` must be valid for writes of a `T`.
+pub(crate) unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+/// Maps the entities the `T` at `ptr` refers to.
+/// # Safety
+/// `ptr` must point to a valid `T`.
+unsafe fn map_entities_ptr<T: MapEntities>(ptr: *mut u8, map: &EntityMap) {
+    (*ptr.cast::<T>()).map_entities(map);
+}
+
+/// The amount of readers of a kind of component if positive, or `-1` if it has a writer. Updated
+/// atomically so components can be borrowed from several threads at once.
 #[derive(Default)]
-struct BorrowStatus(i16);
+pub(crate) struct BorrowStatus(AtomicI32);
 
 impl BorrowStatus {
-    fn is_free(&self) -> bool {
-        self.0 == 0
+    pub(crate) fn get(&self) -> i32 {
+        self.0.load(Ordering::Acquire)
+    }
+    pub(crate) fn is_free(&self) -> bool {
+        self.get() == 0
     }
-    fn is_readable(&self) -> bool {
-        self.0 >= 0
+    pub(crate) fn is_readable(&self) -> bool {
+        self.get() >= 0
     }
-    fn add_borrow(&mut self, mutable: bool) -> Result<(), ()> {
+    pub(crate) fn add_borrow(&self, mutable: bool) -> Result<(), ()> {
         if mutable {
             self.add_writer()
         } else {
             self.add_reader()
         }
     }
-    fn add_reader(&mut self) -> Result<(), ()> {
-        if self.is_readable() {
-            self.0 += 1;
-            Ok(())
-        } else {
-            Err(())
-        }
+    pub(crate) fn add_reader(&self) -> Result<(), ()> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n >= 0).then_some(n + 1)
+            })
+            .map(drop)
+            .map_err(drop)
     }
-    fn add_writer(&mut self) -> Result<(), ()> {
-        if self.is_free() {
-            self.0 -= 1;
-            Ok(())
-        } else {
-            Err(())
-        }
+    pub(crate) fn add_writer(&self) -> Result<(), ()> {
+        self.0
+            .compare_exchange(0, -1, Ordering::AcqRel, Ordering::Acquire)
+            .map(drop)
+            .map_err(drop)
     }
-    fn remove_borrow(&mut self, mutable: bool) {
+    pub(crate) fn remove_borrow(&self, mutable: bool) {
         if mutable {
-            assert!(self.0 < 0);
-            self.0 += 1;
+            let prev = self.0.fetch_add(1, Ordering::Release);
+            assert!(prev < 0);
         } else {
-            assert!(self.0 > 0);
-            self.0 -= 1;
+            let prev = self.0.fetch_sub(1, Ordering::Release);
+            assert!(prev > 0);
         }
     }
 }
 
 impl fmt::Debug for BorrowStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.0 == 0 {
+        let n = self.get();
+        if n == 0 {
             write!(f, "BorrowStatus(free)")
-        } else if self.0 > 0 {
-            write!(f, "BorrowStatus({} readers)", self.0)
-        } else if self.0 == -1 {
+        } else if n > 0 {
+            write!(f, "BorrowStatus({} readers)", n)
+        } else if n == -1 {
             write!(f, "BorrowStatus(one writer)")
         } else {
-            write!(f, "BorrowStatus(invalid: {})", self.0)
+            write!(f, "BorrowStatus(invalid: {})", n)
         }
     }
 }
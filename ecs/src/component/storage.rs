@@ -1,26 +1,95 @@
-use dense_bitset::BitSet;
+use dense_bitset::{BitSet, IdxIter};
 use std::{
     alloc::{self, Layout},
     fmt,
     mem::{self, MaybeUninit},
     ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicU32, Ordering},
 };
 
+use super::{ArchetypeId, Location};
+
 /// A storage container for all instances of a certain kind of component.
-/// This is where the actual component data is stored. There is currently only one implementation
-/// which stores all components contiguously in memory in an array and with a bit set indicating
-/// which entities have the given component.
+/// This is where the actual component data is stored. There are currently three implementations:
+///
+/// `VecStorage` stores all components contiguously in memory in an array indexed by entity id and
+/// with a bit set indicating which entities have the given component. For components that only a
+/// few entities have this would waste a lot of space, but it is probably the best solution for
+/// common components e.g. position.
+///
+/// `SparseSetStorage` stores the components packed together in a dense array, with a sparse array
+/// of indices into it indexed by entity id. This is better for rare components, like markers and
+/// tags, since the components themselves only take up as much space as there are components.
 ///
-/// For components that only a few entities have this would waste a lot of space, but it is
-/// probably the best solution for common components e.g. position.
+/// `ArchetypeStorage` stores the components in one column per archetype (see `Archetypes`), so
+/// entities with the same set of archetype stored components are stored together and queries only
+/// have to visit the archetypes that match. Since the archetypes are kept track of by the `World`
+/// these components are accessed by `Location` instead of by entity id.
 #[derive(Debug)]
 pub enum Storage {
     VecStorage(VecStorage),
+    SparseSet(SparseSetStorage),
+    Archetype(ArchetypeStorage),
 }
 
+/// The ticks (see `World::change_tick`) at which a component was added and last changed. Adding a
+/// component counts as changing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    /// Returns `true` if the component was added after `tick`.
+    pub fn is_added_since(&self, tick: u32) -> bool {
+        self.added > tick
+    }
+
+    /// Returns `true` if the component was changed after `tick`.
+    pub fn is_changed_since(&self, tick: u32) -> bool {
+        self.changed > tick
+    }
+}
+
+/// The `ComponentTicks` of a component as stored in a `Storage`. The changed tick is atomic so
+/// components can be marked as changed through a shared reference to the storage, from any
+/// thread.
 #[derive(Debug)]
+struct Ticks {
+    added: u32,
+    changed: AtomicU32,
+}
+
+impl Ticks {
+    fn new(ticks: ComponentTicks) -> Self {
+        Self {
+            added: ticks.added,
+            changed: AtomicU32::new(ticks.changed),
+        }
+    }
+
+    fn get(&self) -> ComponentTicks {
+        ComponentTicks {
+            added: self.added,
+            changed: self.changed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StorageType {
     VecStorage,
+    SparseSet,
+    Archetype,
 }
 
 impl Storage {
@@ -31,39 +100,99 @@ impl Storage {
     pub unsafe fn new(storage_type: StorageType, layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
         match storage_type {
             StorageType::VecStorage => Self::VecStorage(VecStorage::new(layout, drop)),
+            StorageType::SparseSet => Self::SparseSet(SparseSetStorage::new(layout, drop)),
+            StorageType::Archetype => Self::Archetype(ArchetypeStorage::new(layout, drop)),
         }
     }
 
-    /// Returns `true` if `entity` did previously not have this kind of component.
+    /// The layout of the components in the storage.
+    pub fn item_layout(&self) -> Layout {
+        match self {
+            Self::VecStorage(s) => s.item_layout,
+            Self::SparseSet(s) => s.dense.item_layout,
+            Self::Archetype(s) => s.item_layout,
+        }
+    }
+
+    /// The last tick at which a component in the storage was added or changed, e.g. to skip
+    /// looking for changed components when there are none.
+    pub fn last_changed(&self) -> u32 {
+        self.changed().load(Ordering::Relaxed)
+    }
+
+    fn changed(&self) -> &AtomicU32 {
+        match self {
+            Self::VecStorage(s) => &s.changed,
+            Self::SparseSet(s) => &s.changed,
+            Self::Archetype(s) => &s.changed,
+        }
+    }
+
+    fn mark_changed(&self, tick: u32) {
+        // Only writes when needed, since this is done for every component accessed mutably
+        let changed = self.changed();
+        if changed.load(Ordering::Relaxed) < tick {
+            changed.fetch_max(tick, Ordering::Relaxed);
+        }
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        match self {
+            Self::VecStorage(_) => StorageType::VecStorage,
+            Self::SparseSet(_) => StorageType::SparseSet,
+            Self::Archetype(_) => StorageType::Archetype,
+        }
+    }
+
+    /// Returns `true` if `entity` did previously not have this kind of component. The component is
+    /// marked as changed at `tick`, and as added if there was no component at `index` before.
     /// # Safety
     /// `Self` must contain `T`s
-    pub unsafe fn set<T>(&mut self, index: usize, mut value: T) -> bool {
-        let res = self.set_ptr(index, ((&mut value) as *mut T).cast());
+    pub unsafe fn set<T>(&mut self, index: usize, mut value: T, tick: u32) -> bool {
+        let res = self.set_ptr(index, ((&mut value) as *mut T).cast(), tick);
         mem::forget(value);
         res
     }
 
-    /// Returns `true` if `entity` did previously not have this kind of component.
+    /// Returns `true` if `entity` did previously not have this kind of component. The component is
+    /// marked as changed at `tick`, and as added if there was no component at `index` before.
     /// # Safety
     /// The value pointed to by `ptr` must not be a valid value for the type `self` stores.
     /// It must *not* freed by the caller.
-    pub unsafe fn set_ptr(&mut self, index: usize, ptr: *mut u8) -> bool {
+    pub unsafe fn set_ptr(&mut self, index: usize, ptr: *mut u8, tick: u32) -> bool {
+        self.mark_changed(tick);
         match self {
-            Self::VecStorage(s) => s.set(index, ptr),
+            Self::VecStorage(s) => s.set(index, ptr, tick),
+            Self::SparseSet(s) => s.set(index, ptr, tick),
+            Self::Archetype(_) => not_indexed(),
         }
     }
 
     pub fn unset(&mut self, index: usize) -> bool {
         match self {
             Self::VecStorage(s) => s.unset(index),
+            Self::SparseSet(s) => s.unset(index),
+            Self::Archetype(_) => not_indexed(),
         }
     }
 
     /// # Safety
     /// `Self` must be a storage for `T`s
     pub unsafe fn remove<T: 'static>(&mut self, index: usize) -> Option<T> {
+        let mut res: MaybeUninit<T> = MaybeUninit::uninit();
+        self.remove_into(index, res.as_mut_ptr().cast())
+            .then(|| res.assume_init())
+    }
+
+    /// Moves the component at `index` to `dst` and removes it from `self`, without running its
+    /// destructor. Returns `false` and leaves `dst` untouched if there is no component at `index`.
+    /// # Safety
+    /// `dst` must be valid for writes of the type `self` stores.
+    pub(crate) unsafe fn remove_into(&mut self, index: usize, dst: *mut u8) -> bool {
         match self {
-            Self::VecStorage(s) => s.remove(index),
+            Self::VecStorage(s) => s.remove_into(index, dst),
+            Self::SparseSet(s) => s.remove_into(index, dst),
+            Self::Archetype(_) => not_indexed(),
         }
     }
 
@@ -83,6 +212,8 @@ impl Storage {
     pub fn get_ptr(&self, index: usize) -> *const u8 {
         match self {
             Self::VecStorage(s) => s.get(index),
+            Self::SparseSet(s) => s.get(index),
+            Self::Archetype(_) => not_indexed(),
         }
     }
 
@@ -90,6 +221,175 @@ impl Storage {
     pub fn get_mut_ptr(&mut self, index: usize) -> *mut u8 {
         match self {
             Self::VecStorage(s) => s.get_mut(index),
+            Self::SparseSet(s) => s.get_mut(index),
+            Self::Archetype(_) => not_indexed(),
+        }
+    }
+
+    /// Returns a pointer to the component of the entity with id `index`, whose archetype stored
+    /// components (if any) live at `location`. Works for every kind of storage.
+    /// Returns null if the entity does not have this kind of component.
+    pub fn get_entity_ptr(&self, index: usize, location: Option<Location>) -> *const u8 {
+        match self {
+            Self::Archetype(s) => location.map_or(ptr::null(), |location| s.get(location)),
+            _ => self.get_ptr(index),
+        }
+    }
+
+    /// Same as `get_entity_ptr` but for mutable access.
+    pub fn get_entity_mut_ptr(&mut self, index: usize, location: Option<Location>) -> *mut u8 {
+        match self {
+            Self::Archetype(s) => location.map_or(ptr::null_mut(), |location| s.get_mut(location)),
+            _ => self.get_mut_ptr(index),
+        }
+    }
+
+    /// Returns the ticks of the component of the entity with id `index`, whose archetype stored
+    /// components (if any) live at `location`, or `None` if it does not have this kind of
+    /// component. Works for every kind of storage.
+    pub fn get_entity_ticks(
+        &self,
+        index: usize,
+        location: Option<Location>,
+    ) -> Option<ComponentTicks> {
+        self.entity_ticks(index, location).map(Ticks::get)
+    }
+
+    /// Marks the component of the entity as changed at `tick`. Does nothing if the entity does not
+    /// have this kind of component. The caller must have mutable access to the component, but
+    /// since the ticks are stored separately from the components themselves this only needs a
+    /// shared reference to the storage.
+    pub(crate) fn set_changed(&self, index: usize, location: Option<Location>, tick: u32) {
+        if let Some(ticks) = self.entity_ticks(index, location) {
+            ticks.changed.store(tick, Ordering::Relaxed);
+            self.mark_changed(tick);
+        }
+    }
+
+    fn entity_ticks(&self, index: usize, location: Option<Location>) -> Option<&Ticks> {
+        match self {
+            Self::VecStorage(s) => s.ticks(index),
+            Self::SparseSet(s) => s.dense.ticks(s.dense_index(index)?),
+            Self::Archetype(s) => {
+                let location = location?;
+                s.column(location.archetype)?.ticks(location.row)
+            }
+        }
+    }
+
+    /// Returns null if nothing exists at `location`
+    pub fn get_ptr_at(&self, location: Location) -> *const u8 {
+        match self {
+            Self::Archetype(s) => s.get(location),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Returns null if nothing exists at `location`
+    pub fn get_mut_ptr_at(&mut self, location: Location) -> *mut u8 {
+        match self {
+            Self::Archetype(s) => s.get_mut(location),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Adds a component to the end of the column for `archetype`, added at `tick`. The caller
+    /// must make sure the rows of all columns in the archetype stay in sync with `Archetypes`.
+    /// # Safety
+    /// Same as for `set_ptr`.
+    pub(crate) unsafe fn push_ptr(&mut self, archetype: ArchetypeId, ptr: *mut u8, tick: u32) {
+        self.mark_changed(tick);
+        match self {
+            Self::Archetype(s) => s.column_mut(archetype).push(ptr, ComponentTicks::new(tick)),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Drops the component at `location` and replaces it with the value pointed to by `ptr`,
+    /// changed at `tick`.
+    /// # Safety
+    /// Same as for `set_ptr`. A component must exist at `location`.
+    pub(crate) unsafe fn replace_ptr_at(&mut self, location: Location, ptr: *mut u8, tick: u32) {
+        self.mark_changed(tick);
+        match self {
+            Self::Archetype(s) => s
+                .column_mut(location.archetype)
+                .replace(location.row, ptr, tick),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Drops the component at `location` and moves the last component of the column into its
+    /// place.
+    pub(crate) fn swap_remove_at(&mut self, location: Location) {
+        match self {
+            Self::Archetype(s) => s.column_mut(location.archetype).swap_remove(location.row),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Moves the component at `location` to `dst` and moves the last component of the column
+    /// into its place. Does not run the destructor.
+    /// # Safety
+    /// `dst` must be valid for writes of the type `self` stores and a component must exist at
+    /// `location`.
+    pub(crate) unsafe fn take_at_into(&mut self, location: Location, dst: *mut u8) {
+        match self {
+            Self::Archetype(s) => s
+                .column_mut(location.archetype)
+                .swap_remove_into(location.row, dst),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Moves the component at `from` to the end of the column for `to`.
+    pub(crate) fn move_row(&mut self, from: Location, to: ArchetypeId) {
+        match self {
+            Self::Archetype(s) => s.move_row(from, to),
+            _ => not_archetype(),
+        }
+    }
+
+    /// Runs the destructor of the value pointed to by `ptr`.
+    /// # Safety
+    /// `ptr` must point to a valid value of the type `self` stores which must not be used again.
+    pub(crate) unsafe fn drop_ptr(&self, ptr: *mut u8) {
+        (self.drop_fn())(ptr)
+    }
+
+    /// The function dropping the components in the storage.
+    pub(crate) fn drop_fn(&self) -> unsafe fn(*mut u8) {
+        match self {
+            Self::VecStorage(s) => s.drop,
+            Self::SparseSet(s) => s.dense.drop,
+            Self::Archetype(s) => s.drop,
+        }
+    }
+
+    /// Returns the amount of components in the storage.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::VecStorage(s) => s.len,
+            Self::SparseSet(s) => s.entities.len(),
+            Self::Archetype(s) => s.columns.iter().flatten().map(|c| c.len).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the indices (entity ids) of all components in the storage. This is much
+    /// cheaper than checking every entity for storages with few components.
+    pub fn indices(&self) -> Indices<'_> {
+        let inner = match self {
+            Self::VecStorage(s) => IndicesInner::Vec(s.occupied.iter()),
+            Self::SparseSet(s) => IndicesInner::SparseSet(s.entities.iter()),
+            Self::Archetype(_) => not_indexed(),
+        };
+        Indices {
+            inner,
+            remaining: self.len(),
         }
     }
 
@@ -97,10 +397,57 @@ impl Storage {
     pub fn last_set_index(&self) -> Option<usize> {
         match self {
             Self::VecStorage(s) => s.last_set_index(),
+            Self::SparseSet(s) => s.last_set_index(),
+            Self::Archetype(_) => not_indexed(),
         }
     }
 }
 
+/// An iterator over the indices of all components in a `Storage`. See `Storage::indices`.
+pub struct Indices<'s> {
+    inner: IndicesInner<'s>,
+    remaining: usize,
+}
+
+enum IndicesInner<'s> {
+    Vec(IdxIter<&'s BitSet>),
+    SparseSet(slice::Iter<'s, u32>),
+}
+
+impl<'s> Iterator for Indices<'s> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let index = match &mut self.inner {
+            IndicesInner::Vec(iter) => iter.next(),
+            IndicesInner::SparseSet(iter) => iter.next().map(|&i| i as usize),
+        };
+        self.remaining -= 1;
+        index
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'s> ExactSizeIterator for Indices<'s> {}
+
+#[cold]
+#[track_caller]
+fn not_indexed() -> ! {
+    panic!("Archetype storages can not be indexed by entity id, use a `Location` instead")
+}
+
+#[cold]
+#[track_caller]
+fn not_archetype() -> ! {
+    panic!("Only archetype storages can be indexed by `Location`")
+}
+
 pub struct VecStorage {
     item_layout: Layout,
     drop: unsafe fn(*mut u8),
@@ -111,12 +458,21 @@ pub struct VecStorage {
     // # Safety
     // May never contain any index `>= cap`
     occupied: BitSet,
+    // The amount of indices in `occupied`
+    len: usize,
+    // Indexed by entity ids. Only meaningful for indices in `occupied`.
+    ticks: Vec<Ticks>,
+    // See `Storage::last_changed`
+    changed: AtomicU32,
 }
 
 impl VecStorage {
     fn new(item_layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
         Self {
             occupied: BitSet::default(),
+            len: 0,
+            ticks: Vec::new(),
+            changed: AtomicU32::new(0),
             item_layout,
             drop,
             cap: 0,
@@ -126,8 +482,12 @@ impl VecStorage {
 
     /// `self` effectively takes ownership over the value pointed to by `value` and should not be
     /// freed by the caller. Returns `true` if the there was nothing at `index` before.
-    unsafe fn set(&mut self, index: usize, value: *mut u8) -> bool {
+    unsafe fn set(&mut self, index: usize, value: *mut u8, tick: u32) -> bool {
         self.ensure_capacity(index + 1);
+        if self.ticks.len() <= index {
+            self.ticks
+                .resize_with(index + 1, || Ticks::new(ComponentTicks::new(0)));
+        }
 
         self.occupied.highest_bit();
 
@@ -135,6 +495,12 @@ impl VecStorage {
         self.get_mut_unchecked(index)
             .copy_from_nonoverlapping(value, self.item_layout.size());
         self.occupied.insert(index);
+        self.len += 1;
+        if res {
+            self.ticks[index] = Ticks::new(ComponentTicks::new(tick));
+        } else {
+            *self.ticks[index].changed.get_mut() = tick;
+        }
         res
     }
 
@@ -145,30 +511,38 @@ impl VecStorage {
             return false;
         }
         self.occupied.remove(index);
+        self.len -= 1;
         unsafe {
             (self.drop)(self.get_mut_unchecked(index));
         }
         true
     }
 
-    /// Take out the component from `Self`. Does not run its destructor.
+    /// Moves the component out of `Self` to `dst`. Does not run its destructor.
     /// # Safety
-    /// `Self` must contain `T`s
-    unsafe fn remove<T: 'static>(&mut self, index: usize) -> Option<T> {
+    /// `dst` must be valid for writes of the type `Self` contains.
+    unsafe fn remove_into(&mut self, index: usize, dst: *mut u8) -> bool {
         if !self.occupied.get(index) {
-            return None;
+            return false;
         }
         self.occupied.remove(index);
-        let ptr = self.get_unchecked(index).cast::<T>();
-        let mut res: MaybeUninit<T> = MaybeUninit::uninit();
-        res.as_mut_ptr().copy_from(ptr, 1);
-        Some(res.assume_init())
+        self.len -= 1;
+        dst.copy_from_nonoverlapping(self.get_unchecked(index), self.item_layout.size());
+        true
     }
 
     fn last_set_index(&self) -> Option<usize> {
         self.occupied.highest_bit()
     }
 
+    fn ticks(&self, index: usize) -> Option<&Ticks> {
+        if self.occupied.get(index) {
+            self.ticks.get(index)
+        } else {
+            None
+        }
+    }
+
     /// Panics on allocation failiure.
     fn ensure_capacity(&mut self, cap: usize) {
         let old_cap = self.cap;
@@ -214,18 +588,20 @@ impl VecStorage {
 
     /// Returns a null pointer if nothing exists as `index`
     fn get(&self, index: usize) -> *const u8 {
-        self.occupied
-            .get(index)
-            .then(|| unsafe { self.get_unchecked(index) })
-            .unwrap_or(ptr::null())
+        if self.occupied.get(index) {
+            unsafe { self.get_unchecked(index) }
+        } else {
+            ptr::null()
+        }
     }
 
     /// Returns a null pointer if nothing exists at `index`
     fn get_mut(&mut self, index: usize) -> *mut u8 {
-        self.occupied
-            .get(index)
-            .then(|| unsafe { self.get_mut_unchecked(index) })
-            .unwrap_or(ptr::null_mut())
+        if self.occupied.get(index) {
+            unsafe { self.get_mut_unchecked(index) }
+        } else {
+            ptr::null_mut()
+        }
     }
 
     fn layout_with_cap(&self, cap: usize) -> Layout {
@@ -237,6 +613,11 @@ impl VecStorage {
     }
 }
 
+// SAFETY: the storage owns its components like a `Vec` would, and only components which are
+// `Send + Sync` can be registered.
+unsafe impl Send for VecStorage {}
+unsafe impl Sync for VecStorage {}
+
 impl Drop for VecStorage {
     fn drop(&mut self) {
         self.clear();
@@ -252,14 +633,413 @@ impl Drop for VecStorage {
 
 impl fmt::Debug for VecStorage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VecStorage {{ {} items }}", self.len)
+    }
+}
+
+pub struct SparseSetStorage {
+    // Indexed by entity ids. Contains the index into `dense` and `entities` for the entity's
+    // component, or `EMPTY` if the entity does not have one.
+    sparse: Vec<u32>,
+    dense: Column,
+    // The entity id of every component in `dense`.
+    entities: Vec<u32>,
+    // See `Storage::last_changed`
+    changed: AtomicU32,
+}
+
+impl SparseSetStorage {
+    const EMPTY: u32 = u32::MAX;
+
+    fn new(item_layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense: Column::new(item_layout, drop),
+            entities: Vec::new(),
+            changed: AtomicU32::new(0),
+        }
+    }
+
+    /// The entity ids of all components in this storage, in the order they're stored.
+    pub fn entities(&self) -> &[u32] {
+        &self.entities
+    }
+
+    fn dense_index(&self, index: usize) -> Option<usize> {
+        match self.sparse.get(index) {
+            Some(&i) if i != Self::EMPTY => Some(i as usize),
+            _ => None,
+        }
+    }
+
+    /// `self` effectively takes ownership over the value pointed to by `value` and should not be
+    /// freed by the caller. Returns `true` if the there was nothing at `index` before.
+    unsafe fn set(&mut self, index: usize, value: *mut u8, tick: u32) -> bool {
+        if let Some(i) = self.dense_index(index) {
+            self.dense.replace(i, value, tick);
+            return false;
+        }
+        if self.sparse.len() <= index {
+            self.sparse.resize(index + 1, Self::EMPTY);
+        }
+        self.sparse[index] = self.entities.len().try_into().unwrap();
+        self.entities.push(index as u32);
+        self.dense.push(value, ComponentTicks::new(tick));
+        true
+    }
+
+    /// Runs the destructor for the component and removes it.
+    /// Returns true if the component was removed.
+    fn unset(&mut self, index: usize) -> bool {
+        match self.dense_index(index) {
+            Some(i) => {
+                self.dense.swap_remove(i);
+                self.remove_entity(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the component out of `Self` to `dst`. Does not run its destructor.
+    /// # Safety
+    /// `dst` must be valid for writes of the type `Self` contains.
+    unsafe fn remove_into(&mut self, index: usize, dst: *mut u8) -> bool {
+        let Some(i) = self.dense_index(index) else {
+            return false;
+        };
+        self.dense.swap_remove_into(i, dst);
+        self.remove_entity(i);
+        true
+    }
+
+    /// Removes the entity at `dense_index` from `entities` the same way `Column` removes
+    /// components, by moving the last one into its place.
+    fn remove_entity(&mut self, dense_index: usize) {
+        let removed = self.entities.swap_remove(dense_index);
+        self.sparse[removed as usize] = Self::EMPTY;
+        if let Some(&moved) = self.entities.get(dense_index) {
+            self.sparse[moved as usize] = dense_index as u32;
+        }
+    }
+
+    fn last_set_index(&self) -> Option<usize> {
+        self.entities.iter().max().map(|&i| i as usize)
+    }
+
+    /// Returns a null pointer if nothing exists as `index`
+    fn get(&self, index: usize) -> *const u8 {
+        self.dense_index(index)
+            .map_or(ptr::null(), |i| self.dense.get(i))
+    }
+
+    /// Returns a null pointer if nothing exists at `index`
+    fn get_mut(&mut self, index: usize) -> *mut u8 {
+        match self.dense_index(index) {
+            Some(i) => self.dense.get_mut(i),
+            None => ptr::null_mut(),
+        }
+    }
+}
+
+impl fmt::Debug for SparseSetStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SparseSetStorage {{ {} items }}", self.entities.len())
+    }
+}
+
+pub struct ArchetypeStorage {
+    item_layout: Layout,
+    drop: unsafe fn(*mut u8),
+    // Indexed by `ArchetypeId`s. `None` for archetypes that does not contain this component or
+    // where no entity has been added yet.
+    columns: Vec<Option<Column>>,
+    // See `Storage::last_changed`
+    changed: AtomicU32,
+}
+
+impl ArchetypeStorage {
+    fn new(item_layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
+        Self {
+            item_layout,
+            drop,
+            columns: Vec::new(),
+            changed: AtomicU32::new(0),
+        }
+    }
+
+    fn column(&self, archetype: ArchetypeId) -> Option<&Column> {
+        self.columns.get(archetype.index()).and_then(Option::as_ref)
+    }
+
+    /// Creates the column if it does not exist yet.
+    fn column_mut(&mut self, archetype: ArchetypeId) -> &mut Column {
+        let index = archetype.index();
+        if self.columns.len() <= index {
+            self.columns.resize_with(index + 1, || None);
+        }
+        let (item_layout, drop) = (self.item_layout, self.drop);
+        self.columns[index].get_or_insert_with(|| Column::new(item_layout, drop))
+    }
+
+    /// Returns a null pointer if nothing exists at `location`
+    fn get(&self, location: Location) -> *const u8 {
+        self.column(location.archetype)
+            .map_or(ptr::null(), |column| column.get(location.row))
+    }
+
+    /// Returns a null pointer if nothing exists at `location`
+    fn get_mut(&mut self, location: Location) -> *mut u8 {
+        match self.columns.get_mut(location.archetype.index()) {
+            Some(Some(column)) => column.get_mut(location.row),
+            _ => ptr::null_mut(),
+        }
+    }
+
+    fn move_row(&mut self, from: Location, to: ArchetypeId) {
+        debug_assert_ne!(from.archetype, to);
+        // NOTE: this might reallocate `self.columns` so it must be done before we get any pointer
+        // into a column.
+        self.column_mut(to);
+        let from_column = self.column_mut(from.archetype);
+        let value = from_column.get_mut(from.row);
+        assert!(!value.is_null(), "No component to move at {:?}", from);
+        let ticks = from_column.ticks[from.row].get();
+        unsafe {
+            self.column_mut(to).push(value, ticks);
+            self.column_mut(from.archetype).forget_swap_remove(from.row);
+        }
+    }
+}
+
+impl fmt::Debug for ArchetypeStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns = self.columns.iter().flatten();
         write!(
             f,
-            "VecStorage {{ {} items }}",
-            self.occupied.element_count()
+            "ArchetypeStorage {{ {} items in {} columns }}",
+            columns.clone().map(|c| c.len).sum::<usize>(),
+            columns.count(),
         )
     }
 }
 
+/// A type erased growable array of components, where components are removed by moving the last
+/// one into the hole.
+struct Column {
+    item_layout: Layout,
+    drop: unsafe fn(*mut u8),
+    cap: usize,
+    len: usize,
+    // Is dangling when `cap * layout.size()` is zero. Points to an allocated buffer of
+    // `cap * layout.size()` bytes otherwise.
+    ptr: NonNull<u8>,
+    // The ticks of every value, in the same order as the values.
+    ticks: Vec<Ticks>,
+}
+
+impl Column {
+    fn new(item_layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
+        Self {
+            item_layout,
+            drop,
+            cap: 0,
+            len: 0,
+            ptr: NonNull::dangling(),
+            ticks: Vec::new(),
+        }
+    }
+
+    /// `self` takes ownership over the value pointed to by `value` and it should not be freed by
+    /// the caller.
+    unsafe fn push(&mut self, value: *mut u8, ticks: ComponentTicks) {
+        self.ensure_capacity(self.len + 1);
+        self.get_unchecked(self.len)
+            .copy_from_nonoverlapping(value, self.item_layout.size());
+        self.ticks.push(Ticks::new(ticks));
+        self.len += 1;
+    }
+
+    /// Drops the value at `row` and takes ownership over the value pointed to by `value` instead,
+    /// marking it as changed at `tick`.
+    unsafe fn replace(&mut self, row: usize, value: *mut u8, tick: u32) {
+        assert!(row < self.len);
+        let item = self.get_unchecked(row);
+        (self.drop)(item);
+        item.copy_from_nonoverlapping(value, self.item_layout.size());
+        *self.ticks[row].changed.get_mut() = tick;
+    }
+
+    /// Drops the value at `row` and moves the last value into its place.
+    fn swap_remove(&mut self, row: usize) {
+        assert!(row < self.len);
+        unsafe {
+            (self.drop)(self.get_unchecked(row));
+            self.forget_swap_remove(row);
+        }
+    }
+
+    /// Moves the value at `row` into `dst` and the last value into its place.
+    /// # Safety
+    /// `dst` must be valid for writes of a value of the stored type.
+    unsafe fn swap_remove_into(&mut self, row: usize, dst: *mut u8) {
+        assert!(row < self.len);
+        dst.copy_from_nonoverlapping(self.get_unchecked(row), self.item_layout.size());
+        self.forget_swap_remove(row);
+    }
+
+    /// Moves the last value into `row` without dropping the value that was there.
+    /// # Safety
+    /// The value at `row` must have been moved out or dropped by the caller.
+    unsafe fn forget_swap_remove(&mut self, row: usize) {
+        let last = self.len - 1;
+        if row != last {
+            self.get_unchecked(row)
+                .copy_from_nonoverlapping(self.get_unchecked(last), self.item_layout.size());
+        }
+        self.ticks.swap_remove(row);
+        self.len -= 1;
+    }
+
+    fn ticks(&self, row: usize) -> Option<&Ticks> {
+        self.ticks.get(row)
+    }
+
+    /// Returns a null pointer if nothing exists at `row`
+    fn get(&self, row: usize) -> *const u8 {
+        if row < self.len {
+            unsafe { self.get_unchecked(row) }
+        } else {
+            ptr::null()
+        }
+    }
+
+    /// Returns a null pointer if nothing exists at `row`
+    fn get_mut(&mut self, row: usize) -> *mut u8 {
+        if row < self.len {
+            unsafe { self.get_unchecked(row) }
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    /// May be dangling but never null
+    /// # Safety
+    /// If `row >= self.cap` the result is undefined behaviour
+    unsafe fn get_unchecked(&self, row: usize) -> *mut u8 {
+        self.ptr.as_ptr().add(row * self.offset())
+    }
+
+    /// Panics on allocation failiure.
+    fn ensure_capacity(&mut self, cap: usize) {
+        let old_cap = self.cap;
+        if old_cap >= cap {
+            return;
+        }
+        let cap = cap.next_power_of_two();
+        self.cap = cap;
+        let curr_layout = self.layout_with_cap(old_cap);
+        let new_layout = self.layout_with_cap(cap);
+        if new_layout.size() == 0 {
+            return;
+        }
+        let new_data = unsafe {
+            if curr_layout.size() == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                alloc::realloc(self.ptr.as_ptr(), curr_layout, new_layout.size())
+            }
+        };
+        self.ptr = NonNull::new(new_data).expect("Failed to allocate component array");
+    }
+
+    fn layout_with_cap(&self, cap: usize) -> Layout {
+        repeat(&self.item_layout, cap).expect("Failed to get memory layout of components")
+    }
+
+    fn offset(&self) -> usize {
+        self.item_layout.size() + padding_needed_for(&self.item_layout, self.item_layout.align())
+    }
+}
+
+// SAFETY: see `VecStorage`
+unsafe impl Send for Column {}
+unsafe impl Sync for Column {}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        for row in 0..self.len {
+            unsafe {
+                (self.drop)(self.get_unchecked(row));
+            }
+        }
+        let layout = self.layout_with_cap(self.cap);
+        if layout.size() == 0 {
+            return;
+        }
+        unsafe {
+            alloc::dealloc(self.ptr.as_ptr(), layout);
+        }
+    }
+}
+
+/// Temporary storage for a few components of different kinds, e.g. clones which are moved into a
+/// world afterwards. Does not drop the components, which must be moved out or dropped by the
+/// user.
+pub(crate) struct ComponentBuffer {
+    // Is aligned but dangling when `layout.size()` is zero
+    ptr: NonNull<u8>,
+    layout: Layout,
+    offsets: Vec<usize>,
+}
+
+impl ComponentBuffer {
+    /// Allocates space for a component of every layout in `layouts`.
+    pub(crate) fn new(layouts: impl IntoIterator<Item = Layout>) -> Self {
+        let mut layout = Layout::new::<()>();
+        let offsets = layouts
+            .into_iter()
+            .map(|item| {
+                let (extended, offset) = layout
+                    .extend(item)
+                    .expect("Failed to get memory layout of components");
+                layout = extended;
+                offset
+            })
+            .collect();
+        let layout = layout.pad_to_align();
+        let ptr = if layout.size() == 0 {
+            NonNull::new(ptr::without_provenance_mut(layout.align())).unwrap()
+        } else {
+            NonNull::new(unsafe { alloc::alloc(layout) })
+                .unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        Self {
+            ptr,
+            layout,
+            offsets,
+        }
+    }
+
+    /// The space for the component with the layout at `index` in the layouts the buffer was
+    /// created with.
+    pub(crate) fn get(&self, index: usize) -> *mut u8 {
+        unsafe { self.ptr.as_ptr().add(self.offsets[index]) }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.offsets.len()
+    }
+}
+
+impl Drop for ComponentBuffer {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+        }
+    }
+}
+
 // TODO: replace these with the methods on `Layout` when those become stable
 
 // From: https://doc.rust-lang.org/src/core/alloc/layout.rs.html#299
@@ -0,0 +1,501 @@
+use std::{alloc::Layout, borrow::Cow, ptr, slice, sync::Arc};
+
+use crate::{
+    component::{ComponentEntryRef, ComponentId},
+    query::{ComponentQuery, Iter, QueryResponse},
+    DynamicError, Entity,
+};
+
+/// The type of a field of a dynamic component. Dynamic components only contain plain data, so
+/// they never need to be dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    Bool,
+    I32,
+    I64,
+    U32,
+    U64,
+    F32,
+    F64,
+    Entity,
+}
+
+impl FieldType {
+    pub fn layout(self) -> Layout {
+        match self {
+            Self::Bool => Layout::new::<bool>(),
+            Self::I32 => Layout::new::<i32>(),
+            Self::I64 => Layout::new::<i64>(),
+            Self::U32 => Layout::new::<u32>(),
+            Self::U64 => Layout::new::<u64>(),
+            Self::F32 => Layout::new::<f32>(),
+            Self::F64 => Layout::new::<f64>(),
+            Self::Entity => Layout::new::<Entity>(),
+        }
+    }
+}
+
+/// The value of a field of a dynamic component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Entity(Entity),
+}
+
+impl Value {
+    pub fn ty(&self) -> FieldType {
+        match self {
+            Self::Bool(_) => FieldType::Bool,
+            Self::I32(_) => FieldType::I32,
+            Self::I64(_) => FieldType::I64,
+            Self::U32(_) => FieldType::U32,
+            Self::U64(_) => FieldType::U64,
+            Self::F32(_) => FieldType::F32,
+            Self::F64(_) => FieldType::F64,
+            Self::Entity(_) => FieldType::Entity,
+        }
+    }
+
+    /// Reads a value of type `ty` from `src`, which does not need to be aligned.
+    /// # Safety
+    /// `src` must be valid for reads of `ty.layout().size()` initialized bytes, which must be a
+    /// valid value of `ty` unless it is a `bool`.
+    unsafe fn read(ty: FieldType, src: *const u8) -> Self {
+        match ty {
+            // Any byte is accepted since the bytes of a component can be set directly
+            FieldType::Bool => Self::Bool(*src != 0),
+            FieldType::I32 => Self::I32(ptr::read_unaligned(src.cast())),
+            FieldType::I64 => Self::I64(ptr::read_unaligned(src.cast())),
+            FieldType::U32 => Self::U32(ptr::read_unaligned(src.cast())),
+            FieldType::U64 => Self::U64(ptr::read_unaligned(src.cast())),
+            FieldType::F32 => Self::F32(ptr::read_unaligned(src.cast())),
+            FieldType::F64 => Self::F64(ptr::read_unaligned(src.cast())),
+            FieldType::Entity => Self::Entity(ptr::read_unaligned(src.cast())),
+        }
+    }
+
+    /// Writes the value to `dst`, which does not need to be aligned.
+    /// # Safety
+    /// `dst` must be valid for writes of `self.ty().layout().size()` bytes.
+    unsafe fn write(self, dst: *mut u8) {
+        match self {
+            Self::Bool(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::I32(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::I64(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::U32(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::U64(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::F32(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::F64(v) => ptr::write_unaligned(dst.cast(), v),
+            Self::Entity(v) => ptr::write_unaligned(dst.cast(), v),
+        }
+    }
+}
+
+/// A field of a dynamic component, see `Schema`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    name: Cow<'static, str>,
+    ty: FieldType,
+    offset: usize,
+}
+
+impl Field {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ty(&self) -> FieldType {
+        self.ty
+    }
+
+    /// The offset of the field in bytes from the start of the component.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Describes a kind of component defined at runtime, e.g. by a script, instead of by a rust
+/// type. Registered with `ComponentRegistry::register_dynamic`, after which its components can
+/// be added to entities as bytes or field by field, and queried by their `ComponentId`.
+/// # Examples
+/// ```
+/// # use ecs::{dynamic::{DynamicComponent, FieldType, Schema, Value}, World};
+/// let mut world = World::default();
+/// let health = world.component_registry_mut().register_dynamic(
+///     Schema::new("Health")
+///         .with_field("current", FieldType::F32)
+///         .with_field("max", FieldType::F32),
+/// );
+/// assert_eq!(world.component_registry().id_by_name("Health"), Some(health));
+///
+/// let schema = world.component_registry()[health].info.schema().unwrap().clone();
+/// let mut component = DynamicComponent::new(schema);
+/// component.set("max", Value::F32(100.0)).unwrap();
+/// let e = world.spawn();
+/// world.add_dynamic(e, health, component.bytes());
+/// assert_eq!(
+///     world.get_dynamic(e, health).unwrap().get("max"),
+///     Some(Value::F32(100.0))
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    name: Cow<'static, str>,
+    fields: Vec<Field>,
+    layout: Layout,
+}
+
+impl Schema {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            fields: Vec::new(),
+            layout: Layout::new::<()>(),
+        }
+    }
+
+    /// Adds a field after the previous ones. Panics if there already is a field named `name`.
+    pub fn with_field(mut self, name: impl Into<Cow<'static, str>>, ty: FieldType) -> Self {
+        let name = name.into();
+        assert!(
+            self.field(&name).is_none(),
+            "The component {} already has a field named {}",
+            self.name,
+            name
+        );
+        let (layout, offset) = self
+            .layout
+            .extend(ty.layout())
+            .expect("Failed to get memory layout of component");
+        self.layout = layout;
+        self.fields.push(Field { name, ty, offset });
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// The layout of the components, which is that of a `#[repr(C)]` struct with the fields.
+    pub fn layout(&self) -> Layout {
+        self.layout.pad_to_align()
+    }
+
+    fn get(&self, bytes: &[u8], name: &str) -> Option<Value> {
+        let field = self.field(name)?;
+        // Safety: the bytes are a component with this schema, which has the field at `offset`
+        Some(unsafe { Value::read(field.ty, bytes.as_ptr().add(field.offset)) })
+    }
+
+    fn set(&self, bytes: &mut [u8], name: &str, value: Value) -> Result<(), DynamicError> {
+        let field = self
+            .field(name)
+            .ok_or_else(|| DynamicError::NoField(name.to_owned()))?;
+        if field.ty != value.ty() {
+            return Err(DynamicError::WrongType {
+                field: name.to_owned(),
+                expected: field.ty,
+                found: value.ty(),
+            });
+        }
+        // Safety: see `get`
+        unsafe { value.write(bytes.as_mut_ptr().add(field.offset)) };
+        Ok(())
+    }
+}
+
+/// A dynamic component which is not part of a world, e.g. to be added to an entity with
+/// `World::add_dynamic`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicComponent {
+    schema: Arc<Schema>,
+    bytes: Vec<u8>,
+}
+
+impl DynamicComponent {
+    /// Creates a component whose bytes are all zero, i.e. every field is `0`, `false` or the
+    /// entity with id and generation `0`.
+    pub fn new(schema: Arc<Schema>) -> Self {
+        let bytes = vec![0; schema.layout().size()];
+        Self { schema, bytes }
+    }
+
+    /// Creates a component from its bytes. Fails if there is not exactly one byte for every byte
+    /// of the layout of the schema.
+    pub fn from_bytes(schema: Arc<Schema>, bytes: Vec<u8>) -> Result<Self, DynamicError> {
+        let size = schema.layout().size();
+        if bytes.len() != size {
+            return Err(DynamicError::WrongSize {
+                expected: size,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self { schema, bytes })
+    }
+
+    pub fn schema(&self) -> &Arc<Schema> {
+        &self.schema
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    pub fn get(&self, field: &str) -> Option<Value> {
+        self.schema.get(&self.bytes, field)
+    }
+
+    pub fn set(&mut self, field: &str, value: Value) -> Result<(), DynamicError> {
+        self.schema.set(&mut self.bytes, field, value)
+    }
+}
+
+/// A dynamic component in a world, see `World::get_dynamic`.
+#[derive(Debug)]
+pub struct DynamicRef<'a> {
+    schema: &'a Arc<Schema>,
+    bytes: &'a [u8],
+    // Keeps the kind of component borrowed if the component is not borrowed through a query
+    _entry: Option<ComponentEntryRef>,
+}
+
+impl<'a> DynamicRef<'a> {
+    /// # Safety
+    /// `ptr` must point to a component with `schema` which is valid for `'a`.
+    pub(crate) unsafe fn new(schema: &'a Arc<Schema>, ptr: *const u8) -> Self {
+        let bytes = slice::from_raw_parts(ptr, schema.layout().size());
+        Self {
+            schema,
+            bytes,
+            _entry: None,
+        }
+    }
+
+    /// Same as `new` but keeps the kind of component borrowed by `entry` until this is dropped.
+    /// # Safety
+    /// `ptr` must point to a component with `schema` owned by the storage of `entry`.
+    pub(crate) unsafe fn borrowed(
+        schema: &'a Arc<Schema>,
+        ptr: *const u8,
+        entry: ComponentEntryRef,
+    ) -> Self {
+        let bytes = slice::from_raw_parts(ptr, schema.layout().size());
+        Self {
+            schema,
+            bytes,
+            _entry: Some(entry),
+        }
+    }
+
+    pub fn schema(&self) -> &'a Arc<Schema> {
+        self.schema
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    pub fn get(&self, field: &str) -> Option<Value> {
+        self.schema.get(self.bytes, field)
+    }
+
+    /// Copies the component out of the world.
+    pub fn to_component(&self) -> DynamicComponent {
+        DynamicComponent {
+            schema: self.schema.clone(),
+            bytes: self.bytes.to_vec(),
+        }
+    }
+}
+
+// Makes the world stay borrowed until the `DynamicRef` is dropped, see the same for `Ref`.
+impl Drop for DynamicRef<'_> {
+    fn drop(&mut self) {}
+}
+
+/// A mutable dynamic component in a world, see `World::get_dynamic_mut`.
+#[derive(Debug)]
+pub struct DynamicMut<'a> {
+    schema: &'a Arc<Schema>,
+    bytes: &'a mut [u8],
+}
+
+impl<'a> DynamicMut<'a> {
+    /// # Safety
+    /// `ptr` must point to a component with `schema` which is valid for writes for `'a`.
+    pub(crate) unsafe fn new(schema: &'a Arc<Schema>, ptr: *mut u8) -> Self {
+        let bytes = slice::from_raw_parts_mut(ptr, schema.layout().size());
+        Self { schema, bytes }
+    }
+
+    pub fn schema(&self) -> &'a Arc<Schema> {
+        self.schema
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+
+    pub fn get(&self, field: &str) -> Option<Value> {
+        self.schema.get(self.bytes, field)
+    }
+
+    pub fn set(&mut self, field: &str, value: Value) -> Result<(), DynamicError> {
+        self.schema.set(self.bytes, field, value)
+    }
+
+    /// Copies the component out of the world.
+    pub fn to_component(&self) -> DynamicComponent {
+        DynamicComponent {
+            schema: self.schema.clone(),
+            bytes: self.bytes.to_vec(),
+        }
+    }
+}
+
+/// Queries a world for components by their ids, giving access to the dynamic ones. See
+/// `World::query_dynamic`.
+pub struct DynamicQuery<'w, 'q> {
+    response: QueryResponse<'w, 'q>,
+    components: Vec<ComponentQuery>,
+    // The schema of every component in the query, if it's dynamic
+    schemas: Vec<Option<Arc<Schema>>>,
+}
+
+impl<'w, 'q> DynamicQuery<'w, 'q> {
+    pub(crate) fn new(response: QueryResponse<'w, 'q>, schemas: Vec<Option<Arc<Schema>>>) -> Self {
+        Self {
+            components: response.query().components().to_vec(),
+            response,
+            schemas,
+        }
+    }
+
+    /// See `QueryResponse::since`.
+    pub fn since(mut self, tick: u32) -> Self {
+        self.response = self.response.since(tick);
+        self
+    }
+
+    /// Returns the components of `entity` if it matches the query.
+    pub fn get(&mut self, entity: Entity) -> Option<DynamicRow<'_>> {
+        // Safety: the row only gives access to the components allowed by the query
+        let ptrs = unsafe { self.response.try_get(entity)? };
+        Some(DynamicRow {
+            entity,
+            ptrs,
+            components: &self.components,
+            schemas: &self.schemas,
+        })
+    }
+
+    /// Iterates over every entity matching the query.
+    pub fn iter(&mut self) -> DynamicIter<'_, 'w, 'q> {
+        DynamicIter {
+            // Safety: see `get`. Every entity is only returned once.
+            inner: unsafe { self.response.iter() },
+            components: &self.components,
+            schemas: &self.schemas,
+        }
+    }
+}
+
+pub struct DynamicIter<'a, 'w, 'q> {
+    inner: Iter<'a, 'w, 'q>,
+    components: &'a [ComponentQuery],
+    schemas: &'a [Option<Arc<Schema>>],
+}
+
+impl<'a, 'w, 'q> Iterator for DynamicIter<'a, 'w, 'q> {
+    type Item = DynamicRow<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entity, ptrs) = self.inner.next()?;
+        Some(DynamicRow {
+            entity,
+            ptrs,
+            components: self.components,
+            schemas: self.schemas,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// The components of an entity matching a `DynamicQuery`, in the order of the query.
+pub struct DynamicRow<'a> {
+    entity: Entity,
+    ptrs: Vec<*mut u8>,
+    components: &'a [ComponentQuery],
+    schemas: &'a [Option<Arc<Schema>>],
+}
+
+impl<'a> DynamicRow<'a> {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The id of the component at `index` in the query.
+    pub fn id(&self, index: usize) -> ComponentId {
+        self.components[index].id
+    }
+
+    /// A pointer to the component at `index` in the query, e.g. to access components of rust
+    /// types. Null if the component is optional and the entity does not have it.
+    pub fn ptr(&self, index: usize) -> *mut u8 {
+        self.ptrs[index]
+    }
+
+    /// The component at `index` in the query, or `None` if it's optional and the entity does not
+    /// have it. Panics if the component is not dynamic.
+    pub fn get(&self, index: usize) -> Option<DynamicRef<'_>> {
+        let schema = self.schema(index);
+        let ptr = self.ptrs[index];
+        // Safety: the query has access to the component, which has `schema`
+        (!ptr.is_null()).then(|| unsafe { DynamicRef::new(schema, ptr) })
+    }
+
+    /// Same as `get` for mutable access. Panics if the component is not accessed mutably by the
+    /// query.
+    pub fn get_mut(&mut self, index: usize) -> Option<DynamicMut<'_>> {
+        assert!(
+            self.components[index].mutable,
+            "The component at {} is not accessed mutably by the query",
+            index
+        );
+        let schema = self.schema(index);
+        let ptr = self.ptrs[index];
+        // Safety: see `get`. The query has mutable access and `self` is borrowed mutably.
+        (!ptr.is_null()).then(|| unsafe { DynamicMut::new(schema, ptr) })
+    }
+
+    fn schema(&self, index: usize) -> &'a Arc<Schema> {
+        self.schemas[index]
+            .as_ref()
+            .unwrap_or_else(|| panic!("The component at {} in the query is not dynamic", index))
+    }
+}
@@ -1,18 +1,29 @@
+use serde::{Deserialize, Serialize};
 use std::{
-    cell::{Cell, RefCell},
-    collections::HashSet,
+    fmt,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
 };
 
 type EntityId = u32;
 type Generation = u32;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub struct Entity {
     id: EntityId,
     gen: Generation,
 }
 
 impl Entity {
+    /// An entity which never exists, to be used as a placeholder, e.g. for an entity which will
+    /// be known later.
+    pub const DANGLING: Entity = Entity {
+        id: EntityId::MAX,
+        gen: Generation::MAX,
+    };
+
     /// Retrieves the id of `self` without checking if `self` is still alive. Most callers should
     /// use `Entities::id` instead.
     pub fn get_id_unchecked(self) -> EntityId {
@@ -20,32 +31,35 @@ impl Entity {
     }
 }
 
-/// Currently there can be at most `u32::MAX + 1` entities alive at a time and for every one of
-/// those 'slots' there can exist at most `u32::MAX + 1` different entities at a time. If any of
-/// these are exceeded there will be a panic.
+/// Currently there can be at most `u32::MAX` entities alive at a time, which will panic if
+/// exceeded. Every one of those 'slots' can be reused for `u32::MAX + 1` different entities,
+/// after which the slot is retired and never used again.
 #[derive(Debug, Default)]
 pub struct Entities {
-    // TODO: optimize
-    generations: RefCell<Vec<Cell<Generation>>>,
-    unused_ids: RefCell<Vec<EntityId>>,
+    slots: Slots,
+    // Also serializes every change to `slots` made through a shared reference.
+    unused_ids: Mutex<Vec<EntityId>>,
 }
 
 impl Entities {
     /// Creates a new `entity`
     /// # Time complexity
     /// *O*(1) (ammortized).
-    /// The current implementation keeps a `Vec` of all entities' *generations* which might have to
-    /// grow.
+    /// The current implementation keeps the *generations* of all entities in blocks, of which a
+    /// new one might have to be allocated.
     pub fn spawn(&self) -> Entity {
-        if let Some(id) = self.unused_ids.borrow_mut().pop() {
-            let gen = self.generations.borrow()[id as usize].get();
-            Entity { id, gen }
-        } else {
-            Entity {
-                id: self.create_new_id(),
-                gen: 0,
-            }
-        }
+        let mut unused_ids = self.unused_ids.lock().unwrap();
+        self.slots.spawn(&mut unused_ids)
+    }
+
+    /// Creates `n` new entities at once, e.g. so a loader can know the entities it will spawn
+    /// components for before adding any. Like `spawn`, this only needs shared access, and no
+    /// other entity is spawned in between.
+    /// # Time complexity
+    /// *O*(*n*) (ammortized).
+    pub fn reserve(&self, n: usize) -> Vec<Entity> {
+        let mut unused_ids = self.unused_ids.lock().unwrap();
+        (0..n).map(|_| self.slots.spawn(&mut unused_ids)).collect()
     }
 
     /// Returns `true` if the `entity` was despawned and `false` if `entity` had been despawned
@@ -63,39 +77,89 @@ impl Entities {
     }
 
     /// Despawns the entity with id `id`. Does not check generation or if `id` is already currently
-    /// despawned.
+    /// despawned. If every generation of `id` has been used, `id` is retired instead of being
+    /// reused.
     pub(crate) fn despawn_unchecked(&mut self, id: EntityId) {
-        let gen = &self.generations.borrow()[id as usize];
-        if gen.get() == Generation::MAX {
-            // TODO: we're not doomed in this scenario. We can still mark this id as no longer
-            // usable somehow.
-            panic!("Generation counter for entity id {} has overflown.", id);
+        let gen = self.slots.get(id) as Generation;
+        if gen == Generation::MAX {
+            self.slots.set(id, Slots::pack(gen, false));
+            return;
+        }
+        self.slots.set(id, Slots::pack(gen + 1, false));
+        self.unused_ids.get_mut().unwrap().push(id);
+    }
+
+    /// Copies which entities are alive and which ids are unused, see `World::snapshot`.
+    pub(crate) fn snapshot(&self) -> EntitiesSnapshot {
+        let unused_ids = self.unused_ids.lock().unwrap();
+        EntitiesSnapshot {
+            slots: (0..self.slots.len()).map(|id| self.slots.get(id)).collect(),
+            unused_ids: unused_ids.clone(),
+        }
+    }
+
+    /// Brings back the entities alive when `snapshot` was taken, and despawns the others. Does
+    /// not touch any component.
+    pub(crate) fn restore(&mut self, snapshot: &EntitiesSnapshot) {
+        self.slots.truncate(snapshot.slots.len() as EntityId);
+        for (id, &slot) in snapshot.slots.iter().enumerate() {
+            self.slots.set(id as EntityId, slot);
         }
-        gen.set(gen.get() + 1);
-        self.unused_ids.borrow_mut().push(id);
+        self.unused_ids
+            .get_mut()
+            .unwrap()
+            .clone_from(&snapshot.unused_ids);
+    }
+
+    /// Sets the generation of the current entity with id `id`, to test running out of
+    /// generations.
+    #[cfg(test)]
+    pub(crate) fn set_generation(&mut self, id: EntityId, gen: Generation) {
+        let alive = Slots::is_alive(self.slots.get(id));
+        self.slots.set(id, Slots::pack(gen, alive));
+    }
+
+    /// Iterates over the alive entities the way `Iter` did before `Slots` kept track of them,
+    /// by collecting the unused ids in a `HashSet` first. Only kept to compare the two in the
+    /// entity iteration benchmark.
+    #[cfg(test)]
+    pub(crate) fn iter_with_unused_set(&self) -> impl Iterator<Item = Entity> + '_ {
+        let unused_ids: std::collections::HashSet<EntityId> =
+            self.unused_ids.lock().unwrap().iter().copied().collect();
+        (0..self.slots.len())
+            .filter(move |id| !unused_ids.contains(id))
+            .map(|id| self.with_id_unchecked(id))
     }
 
     /// Indicates whether `entity` still is alive.
     /// # Time complexity
     /// *O*(1)
     pub fn exists(&self, entity: Entity) -> bool {
-        let Entity { id, gen } = entity;
-        self.generations.borrow()[id as usize].get() == gen
+        Slots::exists(self.slots.get(entity.id), entity)
     }
 
     /// Returns the id of `entity` if `entity` is still alive.
     /// # Time complexity
     /// *O*(1)
     pub fn id(&self, entity: Entity) -> Option<EntityId> {
-        self.exists(entity).then(|| entity.id)
+        self.exists(entity).then_some(entity.id)
+    }
+
+    /// Returns the entity currently using the id `id`, without checking if `id` is in use.
+    pub(crate) fn with_id_unchecked(&self, id: EntityId) -> Entity {
+        Entity {
+            id,
+            gen: self.slots.get(id) as Generation,
+        }
     }
 
     /// Creates an iterator over all currently alive entities.
     ///
     /// # Time complexity
-    /// Creation: *O*(*u*) where *u* is the amount of currently unused entity ID's.
-    /// Iteration: *O*(1) for every call to next.
-    pub fn iter(&self) -> Iter {
+    /// Creation: *O*(1), without allocating.
+    /// Iteration: *O*(*n*) in total where *n* is the amount of entity ID's ever used, alive or
+    /// not, without locking. Unused ID's are skipped 64 at a time.
+    pub fn iter(&self) -> Iter<'_> {
         Iter::new(self)
     }
 
@@ -103,38 +167,21 @@ impl Entities {
     /// entities. If `(A, B)` is yielded, then `(B, A)` is not.
     ///
     /// # Time complexity
-    /// Creation: *O*(*u*) where *u* is the amount of currently unused entity ID's.
-    /// Iteration: *O*(1) for every call to next.
-    pub fn iter_combinations(&self) -> IterCombinations {
+    /// Creation: *O*(1), without allocating.
+    /// Iteration: *O*(*n*²) in total where *n* is the amount of entity ID's ever used.
+    pub fn iter_combinations(&self) -> IterCombinations<'_> {
         IterCombinations::new(self)
     }
-
-    fn create_new_id(&self) -> EntityId {
-        let mut g = self.generations.borrow_mut();
-        let id = g
-            .len()
-            .try_into()
-            // The bookkeeping alone for all those entities would require more than 17 GB so this
-            // shouldn'n be an issue.
-            .expect("Max entity count (4 294 967 296) exceeded");
-        g.push(Cell::new(0));
-        id
-    }
 }
 
 pub struct Iter<'e> {
     curr: EntityId,
     entities: &'e Entities,
-    unused_ids: HashSet<EntityId>,
 }
 
 impl<'e> Iter<'e> {
     fn new(entities: &'e Entities) -> Self {
-        Self {
-            curr: 1, // skip the resource holder
-            entities,
-            unused_ids: entities.unused_ids.borrow().iter().copied().collect(),
-        }
+        Self { curr: 0, entities }
     }
 
     /// Get the iter's entities.
@@ -147,17 +194,9 @@ impl<'e> Iterator for Iter<'e> {
     type Item = Entity;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while (self.curr as usize) < self.entities.generations.borrow().len() {
-            let curr = self.curr;
-            self.curr += 1;
-            if self.unused_ids.contains(&curr) {
-                continue;
-            } else {
-                let gen = self.entities.generations.borrow()[curr as usize].get();
-                return Some(Entity { id: curr, gen });
-            }
-        }
-        None
+        let entity = self.entities.slots.next_alive(self.curr)?;
+        self.curr = entity.id + 1;
+        Some(entity)
     }
 }
 
@@ -165,16 +204,14 @@ pub struct IterCombinations<'e> {
     curr_a: EntityId,
     curr_b: EntityId,
     entities: &'e Entities,
-    unused_ids: HashSet<EntityId>,
 }
 
 impl<'e> IterCombinations<'e> {
     fn new(entities: &'e Entities) -> Self {
         Self {
-            curr_a: 1, // skip the resource holder
-            curr_b: 2,
+            curr_a: 0,
+            curr_b: 1,
             entities,
-            unused_ids: entities.unused_ids.borrow().iter().copied().collect(),
         }
     }
 
@@ -188,34 +225,199 @@ impl<'e> Iterator for IterCombinations<'e> {
     type Item = (Entity, Entity);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let gen = self.entities.generations.borrow();
-        while (self.curr_a as usize) < gen.len() {
-            let id_a = self.curr_a;
-            if self.unused_ids.contains(&id_a) {
-                continue;
+        let slots = &self.entities.slots;
+        loop {
+            let a = slots.next_alive(self.curr_a)?;
+            if a.id != self.curr_a {
+                self.curr_a = a.id;
+                self.curr_b = a.id + 1;
             }
-            while (self.curr_b as usize) < gen.len() {
-                let id_b = self.curr_b;
-                self.curr_b += 1;
-                if self.unused_ids.contains(&id_b) {
-                    continue;
-                }
-                let gen_a = gen[id_a as usize].get();
-                let gen_b = gen[id_b as usize].get();
-                return Some((
-                    Entity {
-                        id: id_a,
-                        gen: gen_a,
-                    },
-                    Entity {
-                        id: id_b,
-                        gen: gen_b,
-                    },
-                ));
+            if let Some(b) = slots.next_alive(self.curr_b) {
+                self.curr_b = b.id + 1;
+                return Some((a, b));
             }
             self.curr_a += 1;
             self.curr_b = self.curr_a + 1;
         }
+    }
+}
+
+/// A copy of the state of `Entities`, see `Entities::snapshot`.
+#[derive(Debug, Clone)]
+pub(crate) struct EntitiesSnapshot {
+    // The packed slot of every id ever used, see `Slots`
+    slots: Vec<u64>,
+    unused_ids: Vec<EntityId>,
+}
+
+impl EntitiesSnapshot {
+    /// Returns `true` if `entity` was alive when the snapshot was taken.
+    pub(crate) fn exists(&self, entity: Entity) -> bool {
+        let slot = self.slots.get(entity.id as usize).copied().unwrap_or(0);
+        Slots::exists(slot, entity)
+    }
+}
+
+/// The amount of slots in the first block of `Slots`. Every block after it is twice as large as
+/// the one before.
+const FIRST_BLOCK_LEN: u64 = 64;
+/// Enough blocks for `EntityId::MAX` slots.
+const BLOCKS: usize = 27;
+
+/// The generation and whether it is in use of every entity id, packed into one word per id with
+/// the generation in the low 32 bits. The slots are kept in blocks which are never moved once
+/// they are allocated, so they can be read without locking while new ids are taken into use. Only
+/// one thread may take new ids into use at a time, which `Entities` makes sure of.
+struct Slots {
+    blocks: [OnceLock<Block>; BLOCKS],
+    // The amount of ids ever used
+    len: AtomicU32,
+}
+
+/// The slots of a range of ids, and a bit set of those in use so iterating can skip the unused
+/// ones a word at a time. A bit is set after its slot is, so a reader seeing the bit sees the
+/// generation in use.
+struct Block {
+    slots: Box<[AtomicU64]>,
+    alive: Box<[AtomicU64]>,
+}
+
+impl Block {
+    fn new(len: u64) -> Self {
+        Self {
+            slots: (0..len).map(|_| AtomicU64::new(0)).collect(),
+            alive: (0..len / 64).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+impl Slots {
+    const ALIVE: u64 = 1 << 32;
+
+    fn pack(gen: Generation, alive: bool) -> u64 {
+        gen as u64 | if alive { Self::ALIVE } else { 0 }
+    }
+
+    fn is_alive(slot: u64) -> bool {
+        slot & Self::ALIVE != 0
+    }
+
+    fn exists(slot: u64, Entity { gen, .. }: Entity) -> bool {
+        slot == Self::pack(gen, true)
+    }
+
+    /// The block containing the slot of `id` and the index of the slot in that block.
+    fn position(id: EntityId) -> (usize, usize) {
+        let n = id as u64 + FIRST_BLOCK_LEN;
+        let block = (n.ilog2() - FIRST_BLOCK_LEN.ilog2()) as usize;
+        (block, (n - (FIRST_BLOCK_LEN << block)) as usize)
+    }
+
+    fn len(&self) -> EntityId {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// The slot of `id`, which is `0` if `id` has never been used.
+    fn get(&self, id: EntityId) -> u64 {
+        let (block, index) = Self::position(id);
+        self.blocks[block]
+            .get()
+            .map_or(0, |b| b.slots[index].load(Ordering::Acquire))
+    }
+
+    /// Sets the slot of `id` and whether it is in the bit set of ids in use.
+    fn set(&mut self, id: EntityId, slot: u64) {
+        let (block, index) = Self::position(id);
+        self.block(block);
+        let block = self.blocks[block].get_mut().unwrap();
+        *block.slots[index].get_mut() = slot;
+        let word = block.alive[index / 64].get_mut();
+        let bit = 1 << (index % 64);
+        if Self::is_alive(slot) {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    /// The block with index `block`, which is allocated if it has not been yet.
+    fn block(&self, block: usize) -> &Block {
+        self.blocks[block].get_or_init(|| Block::new(FIRST_BLOCK_LEN << block))
+    }
+
+    /// Marks every id from `len` on as never used.
+    fn truncate(&mut self, len: EntityId) {
+        for id in len..self.len() {
+            self.set(id, 0);
+        }
+        *self.len.get_mut() = len;
+    }
+
+    /// Takes an unused id, or a new one if there is none, into use. Must not be called from
+    /// several threads at once.
+    fn spawn(&self, unused_ids: &mut Vec<EntityId>) -> Entity {
+        let id = unused_ids.pop().unwrap_or_else(|| {
+            let id = self.len();
+            // `EntityId::MAX` is the id of `Entity::DANGLING`. The bookkeeping alone for all
+            // those entities would require more than 34 GB so this shouldn't be an issue.
+            assert!(
+                id < EntityId::MAX,
+                "Max entity count (4 294 967 295) exceeded"
+            );
+            id
+        });
+        let (block, index) = Self::position(id);
+        let block = self.block(block);
+        let slot = &block.slots[index];
+        let gen = slot.load(Ordering::Relaxed) as Generation;
+        slot.store(Self::pack(gen, true), Ordering::Release);
+        block.alive[index / 64].fetch_or(1 << (index % 64), Ordering::Release);
+        if id == self.len() {
+            self.len.store(id + 1, Ordering::Release);
+        }
+        Entity { id, gen }
+    }
+
+    /// The first entity in use starting from `id`, found by scanning the bit sets of the blocks
+    /// a word at a time.
+    fn next_alive(&self, mut id: EntityId) -> Option<Entity> {
+        let len = self.len();
+        while id < len {
+            let (block, index) = Self::position(id);
+            let block = self.blocks[block].get()?;
+            // The ids before `id` in its word are masked out
+            let offset = index % 64;
+            let word = block.alive[index / 64].load(Ordering::Acquire) & (u64::MAX << offset);
+            if word == 0 {
+                id = id.saturating_add((64 - offset) as EntityId);
+                continue;
+            }
+            let skipped = word.trailing_zeros() as usize - offset;
+            let id = id + skipped as EntityId;
+            if id >= len {
+                return None;
+            }
+            let slot = block.slots[index + skipped].load(Ordering::Acquire);
+            return Some(Entity {
+                id,
+                gen: slot as Generation,
+            });
+        }
         None
     }
 }
+
+impl Default for Slots {
+    fn default() -> Self {
+        Self {
+            blocks: std::array::from_fn(|_| OnceLock::new()),
+            len: AtomicU32::new(0),
+        }
+    }
+}
+
+impl fmt::Debug for Slots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Slots").field("len", &self.len()).finish()
+    }
+}
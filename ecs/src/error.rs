@@ -1,6 +1,6 @@
 use std::{error::Error, fmt};
 
-use crate::component::ComponentId;
+use crate::{component::ComponentId, dynamic::FieldType};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct BorrowMutError {
@@ -24,3 +24,145 @@ impl fmt::Display for BorrowMutError {
 }
 
 impl Error for BorrowMutError {}
+
+/// An error from ordering the systems of a `Schedule`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// `system` is ordered relative to `other`, but no system named `other` has been added.
+    UnknownSystem { system: String, other: String },
+    /// `system` is ordered to run before `other` even though `other` is in an earlier stage, or
+    /// the other way around.
+    WrongStage { system: String, other: String },
+    /// The ordering constraints of these systems form a cycle.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSystem { system, other } => write!(
+                f,
+                "The system {:?} is ordered relative to {:?}, which does not exist",
+                system, other
+            ),
+            Self::WrongStage { system, other } => write!(
+                f,
+                "The system {:?} is ordered relative to {:?} against the order of their stages",
+                system, other
+            ),
+            Self::Cycle(systems) => write!(
+                f,
+                "The ordering constraints of the systems {:?} form a cycle",
+                systems
+            ),
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+/// An error from setting a field of a value through `Reflect`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReflectError {
+    /// The value has no field at this path.
+    NoField(String),
+    /// The field at `path` has the type `expected` but was given a value of type `found`.
+    WrongType {
+        path: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoField(path) => write!(f, "There is no field at the path {:?}", path),
+            Self::WrongType {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The field at the path {:?} has the type {} but was given a {}",
+                path, expected, found
+            ),
+        }
+    }
+}
+
+impl Error for ReflectError {}
+
+/// An error from setting a field of a dynamic component or creating one from bytes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DynamicError {
+    /// The component has no field with this name.
+    NoField(String),
+    /// The field has the type `expected` but was given a value of type `found`.
+    WrongType {
+        field: String,
+        expected: FieldType,
+        found: FieldType,
+    },
+    /// The component has a size of `expected` bytes but was given `found` bytes.
+    WrongSize { expected: usize, found: usize },
+}
+
+impl fmt::Display for DynamicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoField(field) => write!(f, "There is no field named {:?}", field),
+            Self::WrongType {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The field {:?} has the type {:?} but was given a {:?}",
+                field, expected, found
+            ),
+            Self::WrongSize { expected, found } => write!(
+                f,
+                "The component has a size of {} bytes but was given {} bytes",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for DynamicError {}
+
+/// An error from saving or loading a `Scene`.
+#[derive(Debug)]
+pub enum SceneError {
+    /// The scene could not be written as RON.
+    Ron(ron::Error),
+    /// The scene could not be read from RON, e.g. since it contains a component which is not
+    /// registered in the `SceneRegistry`.
+    Parse(ron::error::SpannedError),
+    /// The scene could not be written or read in the binary format.
+    #[cfg(feature = "binary")]
+    Binary(bincode::Error),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ron(err) => write!(f, "Failed to write the scene as RON: {}", err),
+            Self::Parse(err) => write!(f, "Failed to read the scene: {}", err),
+            #[cfg(feature = "binary")]
+            Self::Binary(err) => write!(f, "Failed to read or write the binary scene: {}", err),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Ron(err) => Some(err),
+            Self::Parse(err) => Some(err),
+            #[cfg(feature = "binary")]
+            Self::Binary(err) => Some(err),
+        }
+    }
+}
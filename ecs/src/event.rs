@@ -0,0 +1,211 @@
+use std::{fmt, marker::PhantomData, mem};
+
+use crate::{resource::ResMut, World};
+
+/// A queue of events of type `T`, e.g. collisions, which some systems send and other systems read.
+/// Added to the world as a resource with `World::add_event`.
+///
+/// Events are double-buffered: `update`, which `World::update_events` calls once per update,
+/// drops the events of the previous update and keeps those of the current one. Every event can
+/// therefore be read during the update it was sent in and the next one, regardless of the order
+/// in which the sending and reading systems run. Every reader keeps its own cursor, see
+/// `EventReader`.
+pub struct Events<T> {
+    // The events sent during the previous and during the current update
+    previous: Vec<T>,
+    current: Vec<T>,
+    // The number of events sent before the first one in `previous`. Cursors of readers count
+    // every event ever sent.
+    start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.current.extend(events);
+    }
+
+    /// Drops the events sent before the last update. The events sent since are kept until the
+    /// next one.
+    pub fn update(&mut self) {
+        self.start += self.previous.len();
+        self.previous.clear();
+        mem::swap(&mut self.previous, &mut self.current);
+    }
+
+    /// Drops every event. Readers will not see them, even if they have not read them yet.
+    pub fn clear(&mut self) {
+        self.start = self.end();
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    /// The number of events kept, sent during this and the previous update.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over every event kept, from the oldest to the newest, without moving any cursor.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+
+    /// Returns a reader which only reads the events sent from now on.
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader {
+            cursor: self.end(),
+            marker: PhantomData,
+        }
+    }
+
+    // The cursor after the last event
+    fn end(&self) -> usize {
+        self.start + self.len()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Events<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("previous", &self.previous)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// Reads the events of type `T`, remembering which ones it has read. Each reader sees every
+/// event once, as long as it reads at least once every other update, so a system usually owns
+/// its reader:
+/// ```
+/// # use ecs::{event::{EventReader, Events}, schedule::System};
+/// struct Scored(u32);
+///
+/// let mut reader = EventReader::<Scored>::new();
+/// let system = System::new("score", move |world| {
+///     let events = world.res::<Events<Scored>>().unwrap();
+///     for Scored(points) in reader.read(&events) {
+///         println!("+{}", points);
+///     }
+/// })
+/// .reads_resource::<Events<Scored>>();
+/// ```
+pub struct EventReader<T> {
+    // The number of events sent before the next one to read
+    cursor: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> EventReader<T> {
+    /// Returns a reader which reads every event still kept, including those sent before it was
+    /// created. See `Events::reader` for one that skips them.
+    pub fn new() -> Self {
+        Self {
+            cursor: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the events which this reader has not read yet, from the oldest to the newest, and
+    /// marks them as read.
+    pub fn read<'e>(&mut self, events: &'e Events<T>) -> impl DoubleEndedIterator<Item = &'e T> {
+        let unread = self.cursor.saturating_sub(events.start);
+        let previous = events.previous.get(unread..).unwrap_or(&[]);
+        let current = unread.saturating_sub(events.previous.len());
+        let current = events.current.get(current..).unwrap_or(&[]);
+        self.cursor = events.end();
+        previous.iter().chain(current)
+    }
+
+    /// The number of events which this reader has not read yet.
+    pub fn len(&self, events: &Events<T>) -> usize {
+        events.end() - self.cursor.clamp(events.start, events.end())
+    }
+
+    pub fn is_empty(&self, events: &Events<T>) -> bool {
+        self.len(events) == 0
+    }
+
+    /// Marks every event as read without reading it.
+    pub fn clear(&mut self, events: &Events<T>) {
+        self.cursor = events.end();
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cursor: self.cursor,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for EventReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventReader")
+            .field("cursor", &self.cursor)
+            .finish()
+    }
+}
+
+/// Sends events of type `T` from a system, see `World::event_writer`. Holds a mutable borrow of
+/// the `Events<T>` resource until dropped.
+pub struct EventWriter<'w, T> {
+    events: ResMut<'w, Events<T>>,
+}
+
+impl<'w, T: 'static> EventWriter<'w, T> {
+    pub(crate) fn new(events: ResMut<'w, Events<T>>) -> Self {
+        Self { events }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.events.send_batch(events);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for EventWriter<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventWriter")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+/// Updates the `Events<T>` resource, if the world has one. Registered by `World::add_event`.
+pub(crate) fn update<T: 'static>(world: &mut World) {
+    if let Some(events) = world.resource_mut::<Events<T>>() {
+        events.update();
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::{iter, slice};
+
+use crate::Entity;
+
+/// The parent of an entity in a hierarchy of entities. Set by `World::set_parent` and removed by
+/// `World::remove_parent`, which keep it in sync with the `Children` of the parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub(crate) Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order they were given their parent. An entity without
+/// children does not have this component. See `Parent`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(pub(crate) Vec<Entity>);
+
+impl Children {
+    pub fn iter(&self) -> iter::Copied<slice::Iter<'_, Entity>> {
+        self.0.iter().copied()
+    }
+
+    pub fn as_slice(&self) -> &[Entity] {
+        &self.0
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Children {
+    type Item = Entity;
+    type IntoIter = iter::Copied<slice::Iter<'a, Entity>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
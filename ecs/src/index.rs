@@ -0,0 +1,165 @@
+use std::{borrow::Borrow, collections::HashMap, fmt, hash::Hash, sync::MutexGuard};
+
+use crate::{query::Changed, Entity, World};
+
+/// Finds the entities whose components of kind `T` have a given key, e.g. all units of a team,
+/// without looking at every component. The key of a component is computed by a function given to
+/// `World::add_index`, and the index is looked up with `World::index`.
+///
+/// The index keeps itself up to date: when it is looked up it indexes every component of kind
+/// `T` added or changed since the last lookup, which it finds through their change ticks, and it
+/// leaves out entities which no longer have a `T`. Looking it up when no `T` has changed only
+/// costs a hash map lookup.
+/// # Examples
+/// ```
+/// # use ecs::World;
+/// struct Team(u32);
+///
+/// let mut world = World::default();
+/// world.add_index(|team: &Team| team.0);
+/// let a = world.spawn_with((Team(1),));
+/// let b = world.spawn_with((Team(2),));
+/// assert_eq!(world.index::<Team, u32>().get(&1), &[a]);
+///
+/// world.get_mut::<Team>(b).unwrap().0 = 1;
+/// assert_eq!(world.index::<Team, u32>().get(&1), &[a, b]);
+/// ```
+pub struct Index<T, K> {
+    key: fn(&T) -> K,
+    entities: HashMap<K, Vec<Entity>>,
+    // The key every indexed entity is in `entities` under
+    keys: HashMap<Entity, K>,
+    // The components changed after this tick have not been indexed yet
+    synced: u32,
+}
+
+impl<T, K> Index<T, K>
+where
+    T: 'static,
+    K: Hash + Eq + Clone,
+{
+    pub(crate) fn new(key: fn(&T) -> K) -> Self {
+        Self {
+            key,
+            entities: HashMap::new(),
+            keys: HashMap::new(),
+            synced: 0,
+        }
+    }
+
+    /// Indexes the components of kind `T` changed since the last sync.
+    fn sync(&mut self, world: &World) {
+        let Some(id) = world.component_registry().id::<T>() else {
+            return;
+        };
+        let storage = &world.component_registry()[id].storage;
+        if storage.last_changed() <= self.synced {
+            return;
+        }
+
+        let mut changed = world
+            .query_filtered::<(Entity, &T), Changed<T>>()
+            .since(self.synced);
+        for (entity, component) in changed.iter() {
+            let key = (self.key)(component);
+            match self.keys.get(&entity) {
+                Some(old) if *old == key => continue,
+                Some(old) => remove_entity(&mut self.entities, old, entity),
+                None => {}
+            }
+            self.entities.entry(key.clone()).or_default().push(entity);
+            self.keys.insert(entity, key);
+        }
+
+        // Entities without a `T` are only left out when they are looked up, so get rid of them
+        // once they make up most of the index
+        if self.keys.len() > 2 * storage.len() {
+            let entities = &mut self.entities;
+            self.keys.retain(|&entity, key| {
+                let keep = world.get::<T>(entity).is_some();
+                if !keep {
+                    remove_entity(entities, key, entity);
+                }
+                keep
+            });
+        }
+        // Components may still be changed during the current tick
+        self.synced = world.change_tick() - 1;
+    }
+}
+
+impl<T, K: fmt::Debug> fmt::Debug for Index<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Index")
+            .field("entities", &self.entities)
+            .field("synced", &self.synced)
+            .finish()
+    }
+}
+
+fn remove_entity<K: Hash + Eq>(entities: &mut HashMap<K, Vec<Entity>>, key: &K, entity: Entity) {
+    if let Some(bucket) = entities.get_mut(key) {
+        bucket.retain(|&e| e != entity);
+        if bucket.is_empty() {
+            entities.remove(key);
+        }
+    }
+}
+
+/// A locked and up to date `Index`, see `World::index`. Other lookups of the same index wait
+/// until it is dropped.
+pub struct IndexRef<'w, T, K> {
+    index: MutexGuard<'w, Index<T, K>>,
+    world: &'w World,
+}
+
+impl<'w, T, K> IndexRef<'w, T, K>
+where
+    T: 'static,
+    K: Hash + Eq + Clone,
+{
+    pub(crate) fn new(mut index: MutexGuard<'w, Index<T, K>>, world: &'w World) -> Self {
+        index.sync(world);
+        Self { index, world }
+    }
+
+    /// The entities whose `T` has the key `key`, in the order they were indexed.
+    pub fn get<Q>(&mut self, key: &Q) -> &[Entity]
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let world = self.world;
+        let Index { entities, keys, .. } = &mut *self.index;
+        let Some(bucket) = entities.get_mut(key) else {
+            return &[];
+        };
+        // Components removed since the last sync are not indexed, but they are not changed either
+        bucket.retain(|&entity| {
+            let keep = world.get::<T>(entity).is_some();
+            if !keep {
+                keys.remove(&entity);
+            }
+            keep
+        });
+        bucket
+    }
+
+    /// The key of the `T` of `entity`, if it has one.
+    pub fn key(&self, entity: Entity) -> Option<&K> {
+        self.world.get::<T>(entity)?;
+        self.index.keys.get(&entity)
+    }
+
+    /// The number of entities in the index, including those left out on lookup.
+    #[cfg(test)]
+    pub(crate) fn indexed(&self) -> usize {
+        self.index.keys.len()
+    }
+}
+
+impl<T, K: fmt::Debug> fmt::Debug for IndexRef<'_, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.index.fmt(f)
+    }
+}
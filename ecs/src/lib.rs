@@ -1,16 +1,34 @@
 #![deny(warnings)]
 
+pub mod bundle;
 mod commands;
 pub mod component;
+pub mod dynamic;
 mod entity;
 mod error;
+pub mod event;
+pub mod hierarchy;
+pub mod index;
+pub mod name;
+pub mod prefab;
 #[macro_use]
 pub mod query;
+pub mod reflect;
+mod relation;
+mod removed;
+pub mod resource;
+pub mod scene;
+pub mod schedule;
+pub mod snapshot;
 mod world;
 
+pub use bundle::Bundle;
 pub use commands::{CommandBuffer, Commands};
+pub use component::Ref;
 pub use entity::{Entities, Entity};
-pub use error::BorrowMutError;
+pub use error::{BorrowMutError, DynamicError, ReflectError, SceneError, ScheduleError};
+pub use removed::RemovedComponents;
+pub use resource::{Res, ResMut, ResourceId};
 pub use world::World;
 
 #[cfg(test)]
@@ -18,25 +36,28 @@ mod tests {
     use std::{
         alloc::Layout,
         any,
-        cell::Cell,
         collections::{HashMap, HashSet},
         mem, ptr,
-        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
         time::{Duration, Instant},
     };
 
     use crate::{
-        component::{ComponentRegistry, Storage, StorageType},
-        query::{ComponentQuery, Query},
+        component::{ComponentId, ComponentRegistry, ComponentTicks, Storage, StorageType},
+        query::{Added, Changed, ComponentFilter, ComponentQuery, Query, With, Without},
     };
 
     use super::*;
 
     #[test]
     fn component_registry() {
-        struct A(u8);
-        struct B(&'static str);
-        struct C(u16);
+        struct A;
+        struct B;
+        struct C;
 
         let mut reg = ComponentRegistry::default();
         let a_id = reg.register::<A>();
@@ -57,9 +78,6 @@ mod tests {
     #[test]
     fn entities() {
         let mut entities = Entities::default();
-        let _resource_holder = entities.spawn(); // NOTE: the first entity is always the resource
-                                                 // holder in a `World` and `Entities::iter*` takes
-                                                 // this into account.
         let a = entities.spawn();
         assert!(entities.exists(a));
         assert!(entities.despawn(a));
@@ -105,9 +123,199 @@ mod tests {
         }
     }
 
+    #[test]
+    fn entity_iteration() {
+        let mut entities = Entities::default();
+        let es: Vec<_> = (0..10).map(|_| entities.spawn()).collect();
+        for &e in [0, 3, 4, 9].iter().map(|&i| &es[i]) {
+            entities.despawn(e);
+        }
+        let alive = [es[1], es[2], es[5], es[6], es[7], es[8]];
+        assert_eq!(entities.iter().collect::<Vec<_>>(), alive);
+
+        let mut pairs = vec![];
+        for (i, &a) in alive.iter().enumerate() {
+            for &b in &alive[i + 1..] {
+                pairs.push((a, b));
+            }
+        }
+        assert_eq!(entities.iter_combinations().collect::<Vec<_>>(), pairs);
+
+        // Spawned entities reuse ids and are seen by iterators, even by ones created before
+        let mut iter = entities.iter();
+        assert_eq!(iter.next(), Some(es[1]));
+        let reused = entities.spawn();
+        assert!(iter.any(|e| e == reused));
+        assert_eq!(entities.iter().count(), 7);
+
+        let only = Entities::default();
+        only.spawn();
+        assert_eq!(only.iter_combinations().next(), None);
+    }
+
+    #[test]
+    fn retired_entity_ids() {
+        let mut entities = Entities::default();
+        let a = entities.spawn();
+        let b = entities.spawn();
+        entities.set_generation(a.get_id_unchecked(), u32::MAX);
+        let a = entities.with_id_unchecked(a.get_id_unchecked());
+        assert!(entities.exists(a));
+
+        // The id of `a` has used every generation, so it's never used again
+        assert!(entities.despawn(a));
+        assert!(!entities.exists(a));
+        assert!(!entities.despawn(a));
+        let reserved = entities.reserve(3);
+        assert!(reserved
+            .iter()
+            .all(|e| e.get_id_unchecked() > b.get_id_unchecked()));
+        assert_eq!(entities.iter().next(), Some(b));
+        assert_eq!(entities.iter().count(), 4);
+
+        assert!(entities.despawn(b));
+        let reused = entities.reserve(2);
+        assert_eq!(reused[0].get_id_unchecked(), b.get_id_unchecked());
+        assert!(!entities.exists(b));
+        assert!(reused.iter().all(|&e| entities.exists(e)));
+    }
+
+    #[test]
+    fn dangling_entity() {
+        let mut world = World::default();
+        let e = world.spawn();
+        assert_ne!(e, Entity::DANGLING);
+        assert!(!world.entities().exists(Entity::DANGLING));
+        assert!(world.entities().id(Entity::DANGLING).is_none());
+        assert!(!world.add(Entity::DANGLING, 1u32));
+        assert!(world.get::<u32>(Entity::DANGLING).is_none());
+        assert!(!world.despawn(Entity::DANGLING));
+        assert!(!world.relate::<()>(e, Entity::DANGLING));
+    }
+
+    #[test]
+    fn reserve_entities() {
+        let mut world = World::default();
+        let first = world.spawn();
+        world.despawn(first);
+
+        // Reserving from several threads at once gives every thread distinct entities
+        let reserved: Vec<Vec<Entity>> = thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| world.entities().reserve(100)))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        let all: HashSet<Entity> = reserved.iter().flatten().copied().collect();
+        assert_eq!(all.len(), 400);
+        assert!(!all.contains(&first));
+        assert!(all.iter().all(|&e| world.entities().exists(e)));
+
+        let mut command_buffer = CommandBuffer::new();
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        let es = commands.reserve(2);
+        commands.add(es[0], 5u32);
+        command_buffer.apply(&mut world);
+        assert_eq!(world.get::<u32>(es[0]).as_deref(), Some(&5));
+        assert_eq!(world.entities().iter().count(), 402);
+
+        // Entities can be spawned while iterating over the others
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        let spawned: Vec<_> = world
+            .query::<Entity>()
+            .iter()
+            .take(3)
+            .map(|_| commands.spawn())
+            .collect();
+        assert!(spawned.iter().all(|&e| world.entities().exists(e)));
+        assert_eq!(world.entities().iter().count(), 405);
+    }
+
+    #[test]
+    fn entity_iteration_skips_despawned_ids_in_order() {
+        let mut entities = Entities::default();
+        // Enough entities to fill several blocks of slots
+        let es: Vec<_> = (0..1000).map(|_| entities.spawn()).collect();
+        for &e in es.iter().step_by(3) {
+            entities.despawn(e);
+        }
+        // Reused ids are yielded in the order of the ids, not in the order they were spawned
+        let reused: Vec<_> = (0..10).map(|_| entities.spawn()).collect();
+
+        let ids: Vec<_> = entities.iter().map(Entity::get_id_unchecked).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        let mut expected: Vec<_> = es
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, &e)| e)
+            .chain(reused.iter().copied())
+            .collect();
+        expected.sort_by_key(|e| e.get_id_unchecked());
+        assert_eq!(entities.iter().collect::<Vec<_>>(), expected);
+        assert!(es
+            .iter()
+            .step_by(3)
+            .all(|e| !entities.iter().any(|a| a == *e)));
+
+        // Runs of unused ids spanning several words and blocks are skipped
+        let alive: Vec<_> = entities.iter().collect();
+        for &e in &alive[10..alive.len() - 10] {
+            entities.despawn(e);
+        }
+        let expected: Vec<_> = alive[..10]
+            .iter()
+            .chain(&alive[alive.len() - 10..])
+            .copied()
+            .collect();
+        assert_eq!(entities.iter().collect::<Vec<_>>(), expected);
+    }
+
+    /// Compares iterating over 100k entity ids with the bit set of ids in use against collecting
+    /// the unused ids in a `HashSet` first, as `Iter` did before. Run with
+    /// `cargo test -p ecs --release -- --ignored --nocapture entity_iteration_benchmark`.
+    #[test]
+    #[ignore]
+    fn entity_iteration_benchmark() {
+        const RUNS: u32 = 100;
+
+        // Which ids are kept: all but a quarter, then one in a hundred
+        let kept: [fn(usize) -> bool; 2] = [|i| i % 4 != 0, |i| i % 100 == 0];
+        for kept in kept {
+            let mut entities = Entities::default();
+            let es: Vec<_> = (0..100_000).map(|_| entities.spawn()).collect();
+            for (i, &e) in es.iter().enumerate() {
+                if !kept(i) {
+                    entities.despawn(e);
+                }
+            }
+            let alive = entities.iter_with_unused_set().count();
+            assert_eq!(
+                entities.iter().collect::<Vec<_>>(),
+                entities.iter_with_unused_set().collect::<Vec<_>>()
+            );
+
+            let now = Instant::now();
+            for _ in 0..RUNS {
+                assert_eq!(entities.iter().count(), alive);
+            }
+            let bit_set = now.elapsed() / RUNS;
+
+            let now = Instant::now();
+            for _ in 0..RUNS {
+                assert_eq!(entities.iter_with_unused_set().count(), alive);
+            }
+            let hash_set = now.elapsed() / RUNS;
+
+            println!("{alive} of 100k entities alive:");
+            println!("    with the bit set of ids in use: {bit_set:?}");
+            println!("    with a `HashSet` of unused ids: {hash_set:?}");
+        }
+    }
+
     #[test]
     fn vec_storage() {
-        let counter = Rc::new(Cell::new(0));
+        let counter = Arc::new(Count::default());
 
         unsafe fn drop_counter(counter: *mut u8) {
             ptr::drop_in_place(counter as *mut Counter)
@@ -129,6 +337,7 @@ mod tests {
                     storage.set(
                         entities.id(es[i]).unwrap() as usize,
                         Counter::new(counter.clone()),
+                        0,
                     );
                 }
             }
@@ -136,7 +345,7 @@ mod tests {
             for &e in &es[..50] {
                 let index = entities.id(e).unwrap() as usize;
                 assert!(
-                    unsafe { storage.set(index, Counter::new(counter.clone())) }
+                    unsafe { storage.set(index, Counter::new(counter.clone()), 0) }
                         == (index % 2 == 1)
                 );
             }
@@ -145,7 +354,7 @@ mod tests {
                 let index = entities.id(e).unwrap() as usize;
                 unsafe {
                     let c: Option<Counter> = storage.remove(index);
-                    if index % 2 == 0 {
+                    if index.is_multiple_of(2) {
                         assert!(c.is_some());
                     } else {
                         assert!(c.is_none());
@@ -157,6 +366,102 @@ mod tests {
         assert_eq!(0, counter.get());
     }
 
+    #[test]
+    fn sparse_set_storage() {
+        let counter = Arc::new(Count::default());
+
+        unsafe fn drop_counter(counter: *mut u8) {
+            ptr::drop_in_place(counter as *mut Counter)
+        }
+
+        {
+            let mut storage = unsafe {
+                Storage::new(
+                    StorageType::SparseSet,
+                    Layout::new::<Counter>(),
+                    drop_counter,
+                )
+            };
+            for i in (0..100).rev().step_by(3) {
+                assert!(unsafe { storage.set(i, Counter::named(counter.clone(), "a"), 0) });
+            }
+            assert_eq!(34, counter.get());
+            assert_eq!(Some(99), storage.last_set_index());
+            assert!(!unsafe { storage.set(0, Counter::named(counter.clone(), "b"), 0) });
+            assert_eq!(34, counter.get());
+            assert_eq!("b", unsafe { storage.get::<Counter>(0) }.unwrap().1);
+
+            for i in 0..100 {
+                let removed = if i % 2 == 0 {
+                    storage.unset(i)
+                } else {
+                    unsafe { storage.remove::<Counter>(i) }.is_some()
+                };
+                assert_eq!(removed, i % 3 == 0);
+                assert!(unsafe { storage.get::<Counter>(i) }.is_none());
+                for j in (i + 1..100).filter(|j| j % 3 == 0) {
+                    assert!(unsafe { storage.get::<Counter>(j) }.is_some());
+                }
+            }
+            assert_eq!(0, counter.get());
+            assert_eq!(None, storage.last_set_index());
+
+            unsafe {
+                storage.set(1_000, Counter::new(counter.clone()), 0);
+                storage.set(10, Counter::new(counter.clone()), 0);
+            }
+            assert_eq!(2, counter.get());
+        }
+        assert_eq!(0, counter.get());
+    }
+
+    #[test]
+    fn world_with_every_storage_type() {
+        #[derive(Debug, PartialEq)]
+        struct Tag(u32);
+
+        for storage_type in [
+            StorageType::VecStorage,
+            StorageType::SparseSet,
+            StorageType::Archetype,
+        ] {
+            let mut world = World::default();
+            let counter = Arc::new(Count::default());
+            world
+                .component_registry_mut()
+                .register_with_storage::<Tag>(storage_type);
+            world
+                .component_registry_mut()
+                .register_with_storage::<Counter>(storage_type);
+
+            let es: Vec<_> = (0..20).map(|_| world.spawn()).collect();
+            for (i, &e) in es.iter().enumerate().step_by(4) {
+                assert!(world.add(e, Tag(i as u32)));
+                assert!(world.add(e, Counter::new(counter.clone())));
+            }
+            assert!(!world.add(es[4], Tag(4)));
+            assert_eq!(5, counter.get());
+
+            assert_eq!(Some(Tag(8)), world.remove::<Tag>(es[8]));
+            assert_eq!(None, world.remove::<Tag>(es[8]));
+            assert_eq!(None, world.remove::<Tag>(es[9]));
+            assert!(world.despawn(es[12]));
+            assert_eq!(4, counter.get());
+
+            let mut tags = 0;
+            query_iter!(world, (e: Entity, tag: Tag, _c: Counter) => {
+                assert_eq!(es[tag.0 as usize], e);
+                tags += 1;
+            });
+            assert_eq!(3, tags);
+            assert_eq!(Some(&Tag(16)), world.get::<Tag>(es[16]).as_deref());
+            assert!(world.get::<Tag>(es[12]).is_none());
+
+            mem::drop(world);
+            assert_eq!(0, counter.get());
+        }
+    }
+
     #[test]
     fn world() {
         let mut world = World::default();
@@ -184,6 +489,7 @@ mod tests {
             },
         );
         world.add(player, Health(100));
+        assert_eq!(world.get::<Health>(player).map(|h| h.0), Some(100));
 
         assert_eq!(
             Some(&Position {
@@ -191,7 +497,7 @@ mod tests {
                 y: 0.,
                 z: 0.
             }),
-            world.get::<Position>(player)
+            world.get::<Position>(player).as_deref()
         );
 
         assert!(world.get_mut::<Rarity>(player).is_none());
@@ -222,8 +528,14 @@ mod tests {
 
         assert!(world.get_mut::<Rarity>(player).is_none());
 
-        assert_eq!(Some(&Rarity::Common), world.get::<Rarity>(common_sword));
-        assert_eq!(Some(&Rarity::Rare), world.get::<Rarity>(rare_sword));
+        assert_eq!(
+            Some(&Rarity::Common),
+            world.get::<Rarity>(common_sword).as_deref()
+        );
+        assert_eq!(
+            Some(&Rarity::Rare),
+            world.get::<Rarity>(rare_sword).as_deref()
+        );
 
         assert_eq!(
             Some(&Position {
@@ -231,7 +543,7 @@ mod tests {
                 y: 0.,
                 z: 0.
             }),
-            world.get::<Position>(player)
+            world.get::<Position>(player).as_deref()
         );
 
         world.despawn(player);
@@ -244,7 +556,7 @@ mod tests {
                 y: 1.,
                 z: 1.
             }),
-            world.get::<Position>(rare_sword)
+            world.get::<Position>(rare_sword).as_deref()
         );
     }
 
@@ -267,7 +579,10 @@ mod tests {
         let player2 = world.spawn();
         world.add(player2, Health(50));
         assert!(world.get::<Health>(player1).is_none());
-        assert_eq!(Some(Health(50)), world.get::<Health>(player2).copied());
+        assert_eq!(
+            Some(Health(50)),
+            world.get::<Health>(player2).as_deref().copied()
+        );
     }
 
     #[test]
@@ -310,6 +625,216 @@ mod tests {
         assert!(world.get::<Marker>(e4).is_none());
     }
 
+    #[test]
+    fn archetype_storage() {
+        let mut world = World::default();
+        let counter = Arc::new(Count::default());
+
+        #[derive(Debug, PartialEq)]
+        struct Position(i32);
+        struct Marker;
+
+        let reg = world.component_registry_mut();
+        reg.register_with_storage::<Position>(StorageType::Archetype);
+        reg.register_with_storage::<Marker>(StorageType::Archetype);
+        reg.register_with_storage::<Counter>(StorageType::Archetype);
+
+        let es: Vec<_> = (0..10).map(|_| world.spawn()).collect();
+        for (i, &e) in es.iter().enumerate() {
+            assert!(world.add(e, Position(i as i32)));
+            assert!(world.add(e, Counter::new(counter.clone())));
+            if i % 3 == 0 {
+                assert!(world.add(e, Marker));
+            }
+        }
+        assert_eq!(counter.get(), 10);
+        assert_eq!(
+            world.archetypes().iter().filter(|a| !a.is_empty()).count(),
+            2
+        );
+
+        assert!(!world.add(es[1], Position(100)));
+        assert_eq!(
+            world.get::<Position>(es[1]).as_deref(),
+            Some(&Position(100))
+        );
+        world.get_mut::<Position>(es[1]).unwrap().0 = 1;
+
+        assert!(world.remove::<Counter>(es[2]).is_some());
+        assert!(world.remove::<Counter>(es[2]).is_none());
+        assert_eq!(counter.get(), 9);
+        assert!(world.despawn(es[3]));
+        assert_eq!(counter.get(), 8);
+        assert!(world.remove::<Marker>(es[6]).is_some());
+
+        for (i, &e) in es.iter().enumerate() {
+            if i == 3 {
+                assert!(world.get::<Position>(e).is_none());
+                continue;
+            }
+            assert_eq!(
+                world.get::<Position>(e).as_deref(),
+                Some(&Position(i as i32))
+            );
+            assert_eq!(world.get::<Counter>(e).is_some(), i != 2);
+            assert_eq!(world.get::<Marker>(e).is_some(), i % 3 == 0 && i != 6);
+        }
+
+        mem::drop(world);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn bundles() {
+        let mut world = World::default();
+        let counter = Arc::new(Count::default());
+
+        #[derive(Debug, PartialEq)]
+        struct Position(i32);
+        #[derive(Debug, PartialEq)]
+        struct Velocity(i32);
+        #[derive(Debug, PartialEq)]
+        struct Sparse(i32);
+        struct Body {
+            position: Position,
+            velocity: Velocity,
+        }
+        impl_bundle!(Body { position, velocity });
+
+        let reg = world.component_registry_mut();
+        reg.register_with_storage::<Position>(StorageType::Archetype);
+        reg.register_with_storage::<Velocity>(StorageType::Archetype);
+        reg.register_with_storage::<Counter>(StorageType::Archetype);
+        reg.register_with_storage::<Sparse>(StorageType::SparseSet);
+
+        let es: Vec<_> = (0..10)
+            .map(|i| world.spawn_with((Position(i), Velocity(-i), Sparse(i))))
+            .collect();
+        // The entities skip the archetype with only a `Position`
+        assert_eq!(world.archetypes().iter().count(), 1);
+        for (i, &e) in es.iter().enumerate() {
+            let i = i as i32;
+            assert_eq!(world.get::<Position>(e).as_deref(), Some(&Position(i)));
+            assert_eq!(world.get::<Velocity>(e).as_deref(), Some(&Velocity(-i)));
+            assert_eq!(world.get::<Sparse>(e).as_deref(), Some(&Sparse(i)));
+        }
+
+        // Replaces the position and adds the rest
+        let tick = world.increment_change_tick();
+        assert!(world.insert_bundle(es[0], (Position(100), Counter::new(counter.clone()), 1u8)));
+        assert_eq!(
+            world.get::<Position>(es[0]).as_deref(),
+            Some(&Position(100))
+        );
+        assert_eq!(world.get::<u8>(es[0]).as_deref(), Some(&1));
+        assert_eq!(world.changed_since::<Position>(tick - 1), [es[0]]);
+        assert_eq!(world.added_since::<Counter>(tick - 1), [es[0]]);
+        assert_eq!(world.archetypes().iter().count(), 2);
+
+        let (position, velocity) = world.remove_bundle::<(Position, Velocity)>(es[1]).unwrap();
+        assert_eq!((position, velocity), (Position(1), Velocity(-1)));
+        assert!(world.get::<Position>(es[1]).is_none());
+        assert_eq!(world.get::<Sparse>(es[1]).as_deref(), Some(&Sparse(1)));
+        assert!(world.removed::<Velocity>().contains(es[1]));
+        // The entity did not have every component, but the ones it had are still removed
+        assert!(world.remove_bundle::<(Counter, Sparse)>(es[1]).is_none());
+        assert!(world.get::<Sparse>(es[1]).is_none());
+        assert!(world.remove_bundle::<(Counter, Sparse)>(es[0]).is_some());
+        assert_eq!(counter.get(), 0);
+
+        let body = world.remove_bundle::<Body>(es[2]).unwrap();
+        assert_eq!(
+            (&body.position, &body.velocity),
+            (&Position(2), &Velocity(-2))
+        );
+        assert!(world.insert_bundle(es[2], body));
+        assert_eq!(world.get::<Velocity>(es[2]).as_deref(), Some(&Velocity(-2)));
+        let dead = world.spawn();
+        world.despawn(dead);
+        assert!(!world.insert_bundle(dead, (Counter::new(counter.clone()),)));
+        assert!(world.remove_bundle::<Body>(dead).is_none());
+        assert_eq!(counter.get(), 0);
+
+        let mut command_buffer = CommandBuffer::new();
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        let e = commands.spawn_with((Position(7), Counter::new(counter.clone())));
+        commands.insert_bundle(es[3], (Counter::new(counter.clone()),));
+        commands.remove_bundle::<Body>(es[4]);
+        assert_eq!(counter.get(), 2);
+        command_buffer.apply(&mut world);
+        assert_eq!(world.get::<Position>(e).as_deref(), Some(&Position(7)));
+        assert!(world.get::<Counter>(es[3]).is_some());
+        assert!(world.get::<Position>(es[4]).is_none());
+        assert_eq!(world.get::<Sparse>(es[4]).as_deref(), Some(&Sparse(4)));
+
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        commands.spawn_with((Counter::new(counter.clone()), Sparse(0)));
+        assert_eq!(counter.get(), 3);
+        mem::drop(command_buffer);
+        assert_eq!(counter.get(), 2);
+        mem::drop(world);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn query_mixed_storages() {
+        let mut world = World::default();
+
+        struct Position(i32);
+        struct Velocity(i32);
+        struct Tag;
+
+        world
+            .component_registry_mut()
+            .register_with_storage::<Position>(StorageType::Archetype);
+        world
+            .component_registry_mut()
+            .register_with_storage::<Tag>(StorageType::Archetype);
+
+        for i in 0..100 {
+            let e = world.spawn();
+            if i % 2 == 0 {
+                world.add(e, Position(i));
+            }
+            if i % 5 == 0 {
+                world.add(e, Velocity(1));
+            }
+            if i % 10 == 0 {
+                world.add(e, Tag);
+            }
+        }
+
+        query_iter!(world, (p: mut Position, v: Velocity) => {
+            p.0 += v.0;
+        });
+
+        let mut count = 0;
+        let mut with_tag = 0;
+        query_iter!(world, (p: Position, tag: Option<Tag>) => {
+            count += 1;
+            if tag.is_some() {
+                assert_eq!(p.0 % 10, 1);
+                with_tag += 1;
+            }
+        });
+        assert_eq!(count, 50);
+        assert_eq!(with_tag, 10);
+
+        let mut count = 0;
+        query_iter!(world, (e: Entity, _t: Tag, v: Velocity) => {
+            assert!(world.get::<Position>(e).is_some());
+            assert_eq!(v.0, 1);
+            count += 1;
+        });
+        assert_eq!(count, 10);
+
+        let mut count = 0;
+        query_iter_combs!(world, (_t: Tag) => {
+            count += 1;
+        });
+        assert_eq!(count, 10 * 9 / 2);
+    }
+
     #[test]
     fn validate_empty_query() {
         assert!(Query::new(vec![]).is_ok());
@@ -402,7 +927,7 @@ mod tests {
         ])
         .unwrap();
         {
-            let mut res = world.query(&usize_query);
+            let mut res = world.query_raw(&usize_query);
             assert_eq!(
                 *unsafe { res.get(a)[0].cast::<usize>().as_ref().unwrap() },
                 0
@@ -413,7 +938,7 @@ mod tests {
             );
         }
         {
-            let mut res = world.query(&both_query);
+            let mut res = world.query_raw(&both_query);
             assert!(unsafe { res.try_get(a) }.is_none());
             let (int, float) = unsafe {
                 if let [int, float] = res.get(b)[..] {
@@ -429,7 +954,7 @@ mod tests {
             assert_eq!(2., *float);
         }
         {
-            let mut res = world.query(&usize_query);
+            let mut res = world.query_raw(&usize_query);
             assert_eq!(
                 *unsafe { res.get(a)[0].cast::<usize>().as_ref().unwrap() },
                 0
@@ -444,14 +969,14 @@ mod tests {
     #[test]
     fn multiple_queries_at_the_same_time() {
         let mut world = World::default();
-        struct Name(String);
-        struct Health(u8);
+        struct Name;
+        struct Health;
         let chungus = world.spawn();
-        world.add(chungus, Name("Big chungus".into()));
-        world.add(chungus, Health(200));
+        world.add(chungus, Name);
+        world.add(chungus, Health);
         let ant = world.spawn();
-        world.add(ant, Name("Mr. Ant".into()));
-        world.add(ant, Health(8));
+        world.add(ant, Name);
+        world.add(ant, Health);
 
         let name_query = Query::new(vec![ComponentQuery {
             id: world.component_registry().id::<Name>().unwrap(),
@@ -471,35 +996,35 @@ mod tests {
             optional: false,
         }])
         .unwrap();
-        let r1 = world.query(&name_query);
-        let r2 = world.query(&name_query);
-        let r3 = world.query(&health_query);
+        let r1 = world.query_raw(&name_query);
+        let r2 = world.query_raw(&name_query);
+        let r3 = world.query_raw(&health_query);
         mem::drop(r1);
         mem::drop(r2);
-        let r4 = world.query(&mut_name_query);
+        let r4 = world.query_raw(&mut_name_query);
         mem::drop(r3);
         mem::drop(r4);
 
-        let r5 = world.query(&name_query);
-        let r6 = world.query(&name_query);
+        let r5 = world.query_raw(&name_query);
+        let r6 = world.query_raw(&name_query);
         assert_eq!(
             BorrowMutError::new(world.component_registry().id::<Name>().unwrap()),
-            world.try_query(&mut_name_query).unwrap_err()
+            world.try_query_raw(&mut_name_query).unwrap_err()
         );
         mem::drop(r6);
         assert_eq!(
             BorrowMutError::new(world.component_registry().id::<Name>().unwrap()),
-            world.try_query(&mut_name_query).unwrap_err()
+            world.try_query_raw(&mut_name_query).unwrap_err()
         );
         mem::drop(r5);
-        assert!(world.try_query(&mut_name_query).is_ok());
+        assert!(world.try_query_raw(&mut_name_query).is_ok());
     }
 
     #[test]
     fn mutable_queries_must_be_exclusive() {
         let mut world = World::default();
-        struct Name(String);
-        struct Health(u8);
+        struct Name;
+        struct Health;
         let name_id = world.component_registry_mut().register::<Name>();
         let health_id = world.component_registry_mut().register::<Health>();
 
@@ -523,24 +1048,24 @@ mod tests {
         }])
         .unwrap();
 
-        let r = world.query(&q1);
+        let r = world.query_raw(&q1);
         assert_eq!(
             BorrowMutError::new(name_id),
-            world.try_query(&q1).unwrap_err(),
+            world.try_query_raw(&q1).unwrap_err(),
         );
         mem::drop(r);
 
-        let r = world.query(&q2);
+        let r = world.query_raw(&q2);
         assert_eq!(
             BorrowMutError::new(health_id),
-            world.try_query(&q1).unwrap_err(),
+            world.try_query_raw(&q1).unwrap_err(),
         );
         mem::drop(r);
 
-        let r = world.query(&q1);
+        let r = world.query_raw(&q1);
         assert_eq!(
             BorrowMutError::new(health_id),
-            world.try_query(&q2).unwrap_err(),
+            world.try_query_raw(&q2).unwrap_err(),
         );
         mem::drop(r);
     }
@@ -597,94 +1122,1794 @@ mod tests {
     }
 
     #[test]
-    fn iterate_over_query() {
+    fn typed_query() {
         let mut world = World::default();
-        struct Position(f32);
-        struct Velocity(f32);
-        let pos_id = world.component_registry_mut().register::<Position>();
-        let vel_id = world.component_registry_mut().register::<Velocity>();
-
-        for i in 0..1000 {
-            let entity = world.spawn();
-            world.add(entity, Position(i as f32));
-            world.add(entity, Velocity(1.5));
-        }
+        struct Name(&'static str);
+        struct Speed(f32);
+        struct Boost(f32);
+        struct Unused;
+        let sanic = world.spawn();
+        world.add(sanic, Name("Sanic"));
+        world.add(sanic, Speed(100.0));
+        world.add(sanic, Boost(2.0));
+        let mario = world.spawn();
+        world.add(mario, Name("Mario"));
+        world.add(mario, Speed(50.0));
+        let luigi = world.spawn();
+        world.add(luigi, Name("Luigi"));
 
-        let q = Query::new(vec![
-            ComponentQuery {
-                id: pos_id,
-                mutable: true,
-                optional: false,
-            },
-            ComponentQuery {
-                id: vel_id,
-                mutable: false,
-                optional: false,
-            },
-        ])
-        .unwrap();
-        let mut q = world.query(&q);
-        for (pos, vel) in unsafe {
-            q.iter().map(|(_e, comps)| {
-                if let [pos, vel] = comps[..] {
-                    (
-                        pos.cast::<Position>().as_mut().unwrap(),
-                        vel.cast::<Velocity>().as_ref().unwrap(),
-                    )
-                } else {
-                    panic!();
-                }
-            })
-        } {
-            pos.0 += vel.0;
+        for (speed, boost) in world.query::<(&mut Speed, Option<&Boost>)>().iter() {
+            speed.0 *= boost.map_or(1.0, |b| b.0);
         }
-        mem::drop(q);
 
-        let q = Query::new(vec![ComponentQuery {
-            id: pos_id,
-            mutable: false,
-            optional: false,
-        }])
-        .unwrap();
-        let mut q = world.query(&q);
-        for (i, pos) in unsafe {
-            q.iter()
-                .map(|(_e, comps)| {
-                    if let [pos] = comps[..] {
-                        pos.cast::<Position>().as_ref().unwrap()
-                    } else {
-                        panic!();
-                    }
-                })
-                .enumerate()
-        } {
-            assert_eq!(i as f32 + 1.5, pos.0);
-        }
+        let mut query = world.query::<(Entity, &Name, &Speed)>();
+        let mut found: Vec<_> = query.iter().map(|(e, n, s)| (e, n.0, s.0)).collect();
+        found.sort_by(|a, b| a.1.cmp(b.1));
+        assert_eq!(found, [(mario, "Mario", 50.0), (sanic, "Sanic", 200.0)]);
+        assert_eq!(query.get(sanic).map(|(_, n, _)| n.0), Some("Sanic"));
+        assert!(query.get(luigi).is_none());
+        drop(query);
+
+        // components which have never been registered
+        assert_eq!(world.query::<(&Name, &Unused)>().iter().count(), 0);
+        assert_eq!(world.query::<(&Name, Option<&Unused>)>().iter().count(), 3);
+        assert_eq!(world.query::<()>().iter().count(), 3);
     }
 
     #[test]
-    fn resources() {
+    fn typed_query_borrows() {
         let mut world = World::default();
-        struct Time {
-            now: Instant,
-            dt: Duration,
-        }
-        struct Gravity {
-            accel_x: f32,
-            accel_y: f32,
-        }
-        world.add_resource(Time {
-            now: Instant::now(),
-            dt: Duration::from_millis(16),
-        });
-        world.add_resource(Gravity {
-            accel_x: 0.,
-            accel_y: -9.818,
-        });
+        struct A;
+        struct B;
+        let e = world.spawn();
+        world.add(e, A);
+        world.add(e, B);
+        let a_id = world.component_registry().id::<A>().unwrap();
+        let b_id = world.component_registry().id::<B>().unwrap();
 
-        assert_eq!(16_000, world.resource::<Time>().unwrap().dt.as_micros());
-        assert_eq!(0., world.resource::<Gravity>().unwrap().accel_x);
-        assert_eq!(-9.818, world.resource::<Gravity>().unwrap().accel_y);
+        assert_eq!(
+            world.try_query::<(&mut A, &A)>().err(),
+            Some(BorrowMutError::new(a_id))
+        );
+
+        let q1 = world.query::<(&A, &B)>();
+        let q2 = world.query::<&A>();
+        assert_eq!(
+            world.try_query::<&mut B>().err(),
+            Some(BorrowMutError::new(b_id))
+        );
+        drop(q1);
+        assert!(world.try_query::<&mut B>().is_ok());
+        drop(q2);
+
+        let mut q = world.query::<(&mut A, Option<&mut B>)>();
+        let mut pairs = 0;
+        q.for_each_combination(|_, _| pairs += 1);
+        assert_eq!(pairs, 0);
+        assert!(q.get(e).unwrap().1.is_some());
+    }
+
+    #[test]
+    fn typed_query_combinations() {
+        #[derive(Debug, PartialEq)]
+        struct Pos(i32);
+        struct Vel(i32);
+
+        let mut world = World::default();
+        let es: Vec<_> = (0..4)
+            .map(|i| world.spawn_with((Pos(i * 5), Vel(0))))
+            .collect();
+        world.spawn_with((Pos(100),));
+
+        let mut pairs = Vec::new();
+        world
+            .query::<(Entity, &Pos, &mut Vel)>()
+            .for_each_combination(|(a, p1, v1), (b, p2, v2)| {
+                pairs.push((a, b));
+                let d = (p2.0 - p1.0).signum();
+                v1.0 += d;
+                v2.0 -= d;
+            });
+        assert_eq!(pairs.len(), 6);
+        assert!(pairs.iter().all(|(a, b)| a != b));
+        assert!(pairs.iter().all(|&(a, b)| !pairs.contains(&(b, a))));
+        let mut query = world.query::<(Entity, &Vel)>();
+        let read: Vec<_> = query
+            .iter_combinations()
+            .map(|((a, _), (b, _))| (a, b))
+            .collect();
+        assert_eq!(read, pairs);
+
+        world
+            .query::<(&mut Pos, &Vel)>()
+            .for_each(|(p, v)| p.0 += v.0);
+        let positions: Vec<_> = es.iter().map(|&e| world.get::<Pos>(e).unwrap().0).collect();
+        assert_eq!(positions, [3, 6, 9, 12]);
+    }
+
+    #[test]
+    fn parallel_query() {
+        struct Position(u32);
+        struct Velocity(u32);
+        struct Frozen;
+        struct Unused;
+        for storage_type in [
+            StorageType::VecStorage,
+            StorageType::SparseSet,
+            StorageType::Archetype,
+        ] {
+            let mut world = World::default();
+            world
+                .component_registry_mut()
+                .register_with_storage::<Position>(storage_type);
+            let es: Vec<_> = (0..1000)
+                .map(|i| {
+                    let e = world.spawn();
+                    world.add(e, Position(i));
+                    if i.is_multiple_of(2) {
+                        world.add(e, Velocity(i));
+                    }
+                    if i.is_multiple_of(3) {
+                        world.add(e, Frozen);
+                    }
+                    e
+                })
+                .collect();
+            world.increment_change_tick();
+
+            let visited = AtomicUsize::new(0);
+            world
+                .query_filtered::<(Entity, &mut Position, &Velocity), Without<Frozen>>()
+                .par_iter()
+                .chunk_size(7)
+                .for_each(|(e, p, v)| {
+                    assert_eq!(es[p.0 as usize], e);
+                    p.0 += v.0;
+                    visited.fetch_add(1, Ordering::SeqCst);
+                });
+            let moved = |i: usize| i.is_multiple_of(2) && !i.is_multiple_of(3);
+            assert_eq!(
+                visited.load(Ordering::SeqCst),
+                (0..1000).filter(|&i| moved(i)).count()
+            );
+            for (i, &e) in es.iter().enumerate() {
+                let expected = if moved(i) { 2 * i } else { i };
+                assert_eq!(world.get::<Position>(e).unwrap().0 as usize, expected);
+            }
+            assert_eq!(
+                world
+                    .changed_since::<Position>(world.change_tick() - 1)
+                    .len(),
+                visited.load(Ordering::SeqCst)
+            );
+
+            let mut query = world.query::<(&Position, Option<&Unused>)>();
+            let sum = AtomicUsize::new(0);
+            query.par_for_each(|(p, _)| {
+                sum.fetch_add(p.0 as usize, Ordering::SeqCst);
+            });
+            assert_eq!(
+                sum.load(Ordering::SeqCst),
+                query.iter().map(|(p, _)| p.0 as usize).sum::<usize>()
+            );
+            world
+                .query::<(&Position, &Unused)>()
+                .par_for_each(|_| panic!("No entity has an `Unused`"));
+        }
+    }
+
+    #[test]
+    fn query_with_and_without_filters() {
+        let mut world = World::default();
+        struct Health(u32);
+        struct Player;
+        struct Dead;
+        struct Unused;
+        world
+            .component_registry_mut()
+            .register_with_storage::<Dead>(StorageType::SparseSet);
+        let player = world.spawn();
+        world.add(player, Health(10));
+        world.add(player, Player);
+        let enemies: Vec<_> = (0..3)
+            .map(|i| {
+                let e = world.spawn();
+                world.add(e, Health(i));
+                e
+            })
+            .collect();
+        world.add(enemies[0], Dead);
+
+        let mut players = world.query_filtered::<Entity, With<Player>>();
+        assert_eq!(players.iter().collect::<Vec<_>>(), [player]);
+        drop(players);
+
+        // Filters do not conflict with mutable access to the same component in the query
+        for health in world
+            .query_filtered::<&mut Health, (Without<Player>, Without<Dead>)>()
+            .iter()
+        {
+            health.0 += 100;
+        }
+        let healths: Vec<_> = world.query::<&Health>().iter().map(|h| h.0).collect();
+        assert_eq!(healths, [10, 0, 101, 102]);
+
+        assert_eq!(
+            world
+                .query_filtered::<&Health, Without<Unused>>()
+                .iter()
+                .count(),
+            4
+        );
+        assert_eq!(
+            world
+                .query_filtered::<&Health, With<Unused>>()
+                .iter()
+                .count(),
+            0
+        );
+
+        let health_id = world.component_registry().id::<Health>().unwrap();
+        let dead_id = world.component_registry().id::<Dead>().unwrap();
+        let q = Query::with_filters(
+            vec![ComponentQuery {
+                id: health_id,
+                mutable: false,
+                optional: false,
+            }],
+            vec![ComponentFilter::With(dead_id)],
+        )
+        .unwrap();
+        let mut res = world.query_raw(&q);
+        let found: Vec<_> = unsafe { res.iter() }.map(|(e, _)| e).collect();
+        assert_eq!(found, [enemies[0]]);
+    }
+
+    #[test]
+    fn added_and_changed_filters() {
+        let mut world = World::default();
+        #[derive(Debug, PartialEq)]
+        struct Position(i32);
+        struct Velocity(i32);
+        world
+            .component_registry_mut()
+            .register_with_storage::<Velocity>(StorageType::Archetype);
+        let es: Vec<_> = (0..4)
+            .map(|i| {
+                let e = world.spawn();
+                world.add(e, Position(i));
+                e
+            })
+            .collect();
+
+        let added = |world: &World| {
+            world
+                .query_filtered::<Entity, Added<Position>>()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        let changed = |world: &World| {
+            world
+                .query_filtered::<Entity, Changed<Position>>()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(added(&world), es);
+        assert_eq!(changed(&world), es);
+
+        world.increment_change_tick();
+        assert!(added(&world).is_empty());
+        assert!(changed(&world).is_empty());
+
+        world.get_mut::<Position>(es[1]).unwrap().0 += 1;
+        world.add(es[2], Position(20));
+        world.add(es[3], Velocity(1));
+        assert!(added(&world).is_empty());
+        assert_eq!(changed(&world), [es[1], es[2]]);
+
+        world.increment_change_tick();
+        for (p, v) in world.query::<(&mut Position, &Velocity)>().iter() {
+            p.0 += v.0;
+        }
+        assert_eq!(changed(&world), [es[3]]);
+        assert_eq!(
+            world
+                .query_filtered::<Entity, Added<Velocity>>()
+                .iter()
+                .count(),
+            0
+        );
+
+        // Moving between archetypes keeps the ticks
+        world.increment_change_tick();
+        world.add(es[3], Velocity(2));
+        world.add(es[0], Velocity(1));
+        assert_eq!(
+            world
+                .query_filtered::<Entity, Added<Velocity>>()
+                .iter()
+                .collect::<Vec<_>>(),
+            [es[0]]
+        );
+        assert_eq!(
+            world
+                .query_filtered::<Entity, Changed<Velocity>>()
+                .iter()
+                .count(),
+            2
+        );
+        assert_eq!(world.get::<Position>(es[3]).as_deref(), Some(&Position(4)));
+    }
+
+    #[test]
+    fn change_ticks() {
+        let mut world = World::default();
+        struct Transform(f32);
+        let es: Vec<_> = (0..3)
+            .map(|i| {
+                let e = world.spawn();
+                world.add(e, Transform(i as f32));
+                e
+            })
+            .collect();
+        let start = world.change_tick();
+        let ticks = |world: &World, e: Entity| {
+            let id = world.entities().id(e).unwrap() as usize;
+            world
+                .component_registry()
+                .component::<Transform>()
+                .unwrap()
+                .storage
+                .get_entity_ticks(id, None)
+                .unwrap()
+        };
+        assert_eq!(ticks(&world, es[0]), ComponentTicks::new(start));
+
+        let t1 = world.increment_change_tick();
+        world.get_mut::<Transform>(es[0]).unwrap().0 += 1.;
+        let t2 = world.increment_change_tick();
+        world.query::<&mut Transform>().get(es[1]).unwrap().0 += 1.;
+        world.increment_change_tick();
+        assert_eq!(
+            ticks(&world, es[0]),
+            ComponentTicks {
+                added: start,
+                changed: t1
+            }
+        );
+        assert_eq!(ticks(&world, es[1]).changed, t2);
+
+        assert_eq!(world.changed_since::<Transform>(start), [es[0], es[1]]);
+        assert_eq!(world.changed_since::<Transform>(t1), [es[1]]);
+        assert!(world.changed_since::<Transform>(t2).is_empty());
+        assert_eq!(world.added_since::<Transform>(start - 1), es);
+        assert!(world.added_since::<Transform>(start).is_empty());
+
+        // Reading does not count as changing
+        let _ = world.query::<&Transform>().iter().count();
+        let _ = world.get::<Transform>(es[2]);
+        assert!(world.changed_since::<Transform>(t2).is_empty());
+
+        let mut q = world
+            .query_filtered::<Entity, Changed<Transform>>()
+            .since(t1);
+        assert_eq!(q.iter().collect::<Vec<_>>(), [es[1]]);
+    }
+
+    #[test]
+    fn removed_components_and_despawned_entities() {
+        let mut world = World::default();
+        struct Position;
+        struct Tag;
+        struct Collider;
+        world
+            .component_registry_mut()
+            .register_with_storage::<Tag>(StorageType::SparseSet);
+        world
+            .component_registry_mut()
+            .register_with_storage::<Collider>(StorageType::Archetype);
+        let es: Vec<_> = (0..4)
+            .map(|_| {
+                let e = world.spawn();
+                world.add(e, Position);
+                world.add(e, Tag);
+                world.add(e, Collider);
+                e
+            })
+            .collect();
+
+        assert!(world.remove::<Tag>(es[0]).is_some());
+        assert!(world.remove::<Tag>(es[0]).is_none());
+        assert!(world.remove::<Collider>(es[1]).is_some());
+        world.despawn(es[2]);
+        assert_eq!(
+            world.removed::<Position>().iter().collect::<Vec<_>>(),
+            [es[2]]
+        );
+        assert_eq!(
+            world.removed::<Tag>().into_iter().collect::<Vec<_>>(),
+            [es[0], es[2]]
+        );
+        assert_eq!(world.removed::<Collider>().len(), 2);
+        assert!(world.removed::<Collider>().contains(es[1]));
+        assert!(world.removed::<Collider>().contains(es[2]));
+        assert!(world.removed::<u32>().is_empty());
+        assert_eq!(world.despawned(), [es[2]]);
+
+        assert_eq!(
+            world.drain_removed::<Tag>().collect::<Vec<_>>(),
+            [es[0], es[2]]
+        );
+        assert!(world.removed::<Tag>().is_empty());
+        assert_eq!(world.drain_removed::<u32>().count(), 0);
+
+        // Despawning through a command buffer is logged when it is applied
+        let mut buffer = CommandBuffer::new();
+        Commands::new(&mut buffer, world.entities()).despawn(es[3]);
+        assert_eq!(world.despawned(), [es[2]]);
+        buffer.apply(&mut world);
+        assert_eq!(world.drain_despawned().collect::<Vec<_>>(), [es[2], es[3]]);
+        assert!(world.despawned().is_empty());
+        assert!(world.removed::<Position>().contains(es[3]));
+
+        world.clear_removals();
+        assert!(world.removed::<Position>().is_empty());
+        assert!(world.removed::<Collider>().is_empty());
+    }
+
+    #[test]
+    fn hierarchy() {
+        use crate::hierarchy::{Children, Parent};
+
+        let mut world = World::default();
+        let [a, b, c, d, e] = [(); 5].map(|_| world.spawn());
+        assert!(world.set_parent(b, a));
+        assert!(world.set_parent(c, a));
+        assert!(world.set_parent(d, b));
+        assert!(world.set_parent(e, d));
+        assert_eq!(*world.children(a), [b, c]);
+        assert_eq!(world.parent(d), Some(b));
+        assert_eq!(world.parent(a), None);
+
+        // No cycles
+        assert!(!world.set_parent(a, e));
+        assert!(!world.set_parent(b, b));
+
+        assert!(world.set_parent(d, c));
+        assert_eq!(*world.children(c), [d]);
+        assert!(world.get::<Children>(b).is_none());
+        assert_eq!(world.remove_parent(c), Some(a));
+        assert_eq!(*world.children(a), [b]);
+        assert!(world.set_parent(c, a));
+
+        // The children of a despawned entity become roots
+        assert!(world.despawn(d));
+        assert!(world.get::<Children>(c).is_none());
+        assert_eq!(world.parent(e), None);
+
+        assert!(world.set_parent(e, c));
+        assert!(world.despawn_recursive(c));
+        for entity in [c, e] {
+            assert!(!world.entities().exists(entity));
+        }
+        assert_eq!(*world.children(a), [b]);
+        assert_eq!(world.query::<&Parent>().iter().count(), 1);
+        assert!(!world.despawn_recursive(c));
+        assert!(!world.set_parent(c, a));
+    }
+
+    #[test]
+    fn relations() {
+        struct Targets;
+        struct Owns;
+
+        let mut world = World::default();
+        let [turret, other_turret, enemy, other_enemy] = [(); 4].map(|_| world.spawn());
+
+        assert!(world.relate::<Targets>(turret, enemy));
+        assert!(world.relate::<Targets>(turret, other_enemy));
+        assert!(world.relate::<Targets>(other_turret, enemy));
+        assert!(world.relate::<Targets>(turret, enemy));
+        assert!(world.relate::<Owns>(enemy, turret));
+        assert_eq!(world.related::<Targets>(turret), [enemy, other_enemy]);
+        assert_eq!(world.related_to::<Targets>(enemy), [turret, other_turret]);
+        assert_eq!(world.related::<Owns>(enemy), [turret]);
+        assert_eq!(world.related_to::<Owns>(turret), [enemy]);
+        assert!(world.is_related::<Targets>(turret, enemy));
+        assert!(!world.is_related::<Targets>(enemy, turret));
+        assert!(world.related::<Owns>(turret).is_empty());
+
+        assert!(world.unrelate::<Targets>(turret, other_enemy));
+        assert!(!world.unrelate::<Targets>(turret, other_enemy));
+        assert!(!world.unrelate::<Owns>(turret, enemy));
+        assert!(world.related_to::<Targets>(other_enemy).is_empty());
+
+        // Despawning either side removes the relations
+        world.despawn(enemy);
+        assert!(world.related::<Targets>(turret).is_empty());
+        assert!(world.related::<Targets>(other_turret).is_empty());
+        assert!(world.related_to::<Owns>(turret).is_empty());
+        assert!(!world.relate::<Targets>(turret, enemy));
+
+        let mut command_buffer = CommandBuffer::new();
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        let new_enemy = commands.spawn();
+        world.relate::<Targets>(turret, new_enemy);
+        world.relate::<Targets>(other_turret, new_enemy);
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        commands.despawn(turret);
+        command_buffer.apply(&mut world);
+        assert!(world.related::<Targets>(turret).is_empty());
+        assert_eq!(world.related_to::<Targets>(new_enemy), [other_turret]);
+
+        // A new entity in the same slot is not related to anything
+        let reused = world.spawn();
+        assert!(world.related::<Targets>(reused).is_empty());
+        assert!(world.related_to::<Owns>(reused).is_empty());
+    }
+
+    #[test]
+    fn clone_entities() {
+        #[derive(Debug, PartialEq, Clone)]
+        struct Position(f32, f32);
+        #[derive(Clone)]
+        struct Tag;
+        struct NotCloned;
+
+        let counter = Arc::new(Count::default());
+        for storage_type in [
+            StorageType::VecStorage,
+            StorageType::SparseSet,
+            StorageType::Archetype,
+        ] {
+            let mut world = World::default();
+            let registry = world.component_registry_mut();
+            registry.register_with_storage::<Counter>(storage_type);
+            registry.register_clone::<Counter>();
+            registry.register_clone::<Position>();
+            registry.register_clone::<Tag>();
+
+            let e = world.spawn_with((
+                Counter::named(counter.clone(), "a"),
+                Position(1.0, 2.0),
+                Tag,
+                NotCloned,
+            ));
+            let clone = world.clone_entity(e).unwrap();
+            assert_ne!(clone, e);
+            assert_eq!(counter.get(), 2);
+            assert_eq!(world.get::<Counter>(clone).unwrap().1, "a");
+            assert_eq!(
+                world.get::<Position>(clone).as_deref(),
+                Some(&Position(1.0, 2.0))
+            );
+            assert!(world.get::<Tag>(clone).is_some());
+            assert!(world.get::<NotCloned>(clone).is_none());
+
+            world.get_mut::<Position>(clone).unwrap().0 = 5.0;
+            assert_eq!(
+                world.get::<Position>(e).as_deref(),
+                Some(&Position(1.0, 2.0))
+            );
+
+            // The columns the clones are read from grow while cloning
+            for _ in 0..10 {
+                world.clone_entity(e).unwrap();
+            }
+            assert_eq!(counter.get(), 12);
+            assert_eq!(world.query::<(&Position, &Tag)>().iter().count(), 12);
+
+            world.despawn(e);
+            assert!(world.clone_entity(e).is_none());
+            mem::drop(world);
+            assert_eq!(counter.get(), 0);
+        }
+    }
+
+    #[test]
+    fn prefabs() {
+        use crate::prefab::Prefab;
+
+        #[derive(Debug, PartialEq, Clone)]
+        struct Position(f32, f32);
+        #[derive(Debug, PartialEq, Clone)]
+        struct Health(u32);
+
+        let counter = Arc::new(Count::default());
+        let mut prefab = Prefab::new()
+            .with(Position(0.0, 0.0))
+            .with(Health(10))
+            .with(Counter::named(counter.clone(), "template"));
+        prefab.insert(Health(100));
+        assert_eq!(prefab.get::<Health>(), Some(&Health(100)));
+        assert_eq!(counter.get(), 1);
+
+        let mut world = World::default();
+        let a = prefab.spawn(&mut world);
+        let overrides = (0..5).map(|i| {
+            (
+                Position(i as f32, 0.0),
+                Counter::named(counter.clone(), "override"),
+            )
+        });
+        let batch = prefab.spawn_batch(&mut world, overrides);
+        assert_eq!(counter.get(), 7);
+        assert_eq!(world.get::<Counter>(a).unwrap().1, "template");
+        for (i, &e) in batch.iter().enumerate() {
+            assert_eq!(
+                world.get::<Position>(e).as_deref(),
+                Some(&Position(i as f32, 0.0))
+            );
+            assert_eq!(world.get::<Health>(e).as_deref(), Some(&Health(100)));
+            assert_eq!(world.get::<Counter>(e).unwrap().1, "override");
+        }
+        assert_eq!(world.query::<(&Position, &Health)>().iter().count(), 6);
+
+        // Changing the prefab does not change the entities spawned from it
+        prefab.get_mut::<Health>().unwrap().0 = 1;
+        assert_eq!(world.get::<Health>(a).as_deref(), Some(&Health(100)));
+        assert_eq!(prefab.remove::<Counter>().map(|c| c.1), Some("template"));
+        assert_eq!(counter.get(), 6);
+        let b = prefab.spawn(&mut world);
+        assert_eq!(world.get::<Health>(b).as_deref(), Some(&Health(1)));
+        assert!(world.get::<Counter>(b).is_none());
+
+        mem::drop(prefab);
+        mem::drop(world);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn dynamic_components() {
+        use crate::dynamic::{DynamicComponent, FieldType, Schema, Value};
+
+        #[derive(Debug, PartialEq)]
+        struct Name(&'static str);
+
+        let mut world = World::default();
+        let registry = world.component_registry_mut();
+        let health = registry.register_dynamic(
+            Schema::new("Health")
+                .with_field("alive", FieldType::Bool)
+                .with_field("current", FieldType::F64)
+                .with_field("max", FieldType::U32),
+        );
+        let target = registry.register_dynamic_with_storage(
+            Schema::new("Target").with_field("entity", FieldType::Entity),
+            StorageType::Archetype,
+        );
+        let name = registry.register::<Name>();
+        assert_eq!(registry.id_by_name("Health"), Some(health));
+        assert_eq!(registry.id_by_name("Target"), Some(target));
+        assert_eq!(registry.id_by_name(any::type_name::<Name>()), Some(name));
+        assert_eq!(registry.id_by_name("Mana"), None);
+
+        let schema = registry[health].info.schema().unwrap().clone();
+        assert_eq!(schema.layout(), Layout::from_size_align(24, 8).unwrap());
+        let mut component = DynamicComponent::new(schema.clone());
+        assert_eq!(component.get("alive"), Some(Value::Bool(false)));
+        component.set("alive", Value::Bool(true)).unwrap();
+        component.set("current", Value::F64(7.5)).unwrap();
+        component.set("max", Value::U32(10)).unwrap();
+        assert_eq!(
+            component.set("max", Value::F32(1.0)),
+            Err(DynamicError::WrongType {
+                field: "max".to_owned(),
+                expected: FieldType::U32,
+                found: FieldType::F32
+            })
+        );
+        assert_eq!(
+            component.set("min", Value::U32(1)),
+            Err(DynamicError::NoField("min".to_owned()))
+        );
+        assert_eq!(
+            DynamicComponent::from_bytes(schema, vec![0; 3]),
+            Err(DynamicError::WrongSize {
+                expected: 24,
+                found: 3
+            })
+        );
+
+        let a = world.spawn_with((Name("a"),));
+        let b = world.spawn_with((Name("b"),));
+        assert!(world.add_dynamic(a, health, component.bytes()));
+        let schema = world.component_registry()[target].info.schema().unwrap();
+        let mut target_a = DynamicComponent::new(schema.clone());
+        target_a.set("entity", Value::Entity(a)).unwrap();
+        assert!(world.add_dynamic(b, target, target_a.bytes()));
+        assert_eq!(
+            world.get_dynamic(b, target).unwrap().get("entity"),
+            Some(Value::Entity(a))
+        );
+        assert!(world.get_dynamic(b, health).is_none());
+        assert!(world.get_dynamic(a, name).is_none());
+        world
+            .get_dynamic_mut(a, health)
+            .unwrap()
+            .set("current", Value::F64(2.0))
+            .unwrap();
+        assert_eq!(
+            world.get_dynamic(a, health).unwrap().get("current"),
+            Some(Value::F64(2.0))
+        );
+
+        // Dynamic components are queried like any other by their ids
+        let query = Query::new(vec![
+            ComponentQuery {
+                id: name,
+                mutable: false,
+                optional: false,
+            },
+            ComponentQuery {
+                id: health,
+                mutable: true,
+                optional: true,
+            },
+        ])
+        .unwrap();
+        let mut response = world.query_dynamic(&query);
+        let mut rows = 0;
+        for mut row in response.iter() {
+            let name = unsafe { &*row.ptr(0).cast::<Name>() };
+            match row.get_mut(1) {
+                Some(mut health) => {
+                    assert_eq!(*name, Name("a"));
+                    health.set("max", Value::U32(20)).unwrap();
+                }
+                None => assert_eq!(*name, Name("b")),
+            }
+            rows += 1;
+        }
+        assert_eq!(rows, 2);
+        assert_eq!(response.get(b).unwrap().id(1), health);
+        mem::drop(response);
+
+        let removed = world.remove_dynamic(a, health).unwrap();
+        assert_eq!(removed.get("max"), Some(Value::U32(20)));
+        assert_eq!(removed.get("current"), Some(Value::F64(2.0)));
+        assert!(world.remove_dynamic(a, health).is_none());
+        assert_eq!(world.get::<Name>(a).as_deref(), Some(&Name("a")));
+        assert!(world.remove_dynamic(b, target).is_some());
+        assert!(world.get_dynamic(b, target).is_none());
+    }
+
+    #[test]
+    fn names() {
+        use crate::name::Name;
+
+        let mut world = World::default();
+        assert_eq!(world.find_by_name("player"), None);
+        let player = world.spawn_with((Name::new("player"),));
+        let enemy = world.spawn_with((Name::new("enemy"),));
+        let other = world.spawn_with((Name::new("enemy"),));
+        assert_eq!(world.find_by_name("player"), Some(player));
+        assert_eq!(world.find_by_name("enemy"), Some(enemy));
+
+        // Renaming through a query is picked up by the index
+        for name in world.query::<&mut Name>().iter() {
+            if name.as_str() == "player" {
+                name.set("hero");
+            }
+        }
+        assert_eq!(world.find_by_name("player"), None);
+        assert_eq!(world.find_by_name("hero"), Some(player));
+
+        world.despawn(enemy);
+        assert_eq!(world.find_by_name("enemy"), Some(other));
+        world.remove::<Name>(other);
+        assert_eq!(world.find_by_name("enemy"), None);
+        world.add(other, Name::new("enemy"));
+        assert_eq!(world.find_by_name("enemy"), Some(other));
+    }
+
+    #[test]
+    fn indices() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        struct Team(u32);
+        struct Unit {
+            team: Team,
+            hp: u32,
+        }
+
+        let mut world = World::default();
+        let units: Vec<Entity> = (0..10)
+            .map(|i| {
+                let unit = Unit {
+                    team: Team(i % 3),
+                    hp: 10,
+                };
+                world.spawn_with((unit,))
+            })
+            .collect();
+        // Components added before the index are indexed too
+        world.add_index(|unit: &Unit| unit.team);
+        let expected = [units[0], units[3], units[6], units[9]];
+        assert_eq!(world.index::<Unit, Team>().get(&Team(0)), &expected);
+        assert_eq!(world.index::<Unit, Team>().get(&Team(3)), &[]);
+        assert_eq!(world.index::<Unit, Team>().key(units[4]), Some(&Team(1)));
+
+        // Changing other fields keeps the entity in place
+        world.increment_change_tick();
+        world.get_mut::<Unit>(units[0]).unwrap().hp = 5;
+        world.get_mut::<Unit>(units[3]).unwrap().team = Team(3);
+        assert_eq!(
+            world.index::<Unit, Team>().get(&Team(0)),
+            &[units[0], units[6], units[9]]
+        );
+        assert_eq!(world.index::<Unit, Team>().get(&Team(3)), &[units[3]]);
+
+        // Changes during the same tick as a lookup are picked up
+        world.get_mut::<Unit>(units[6]).unwrap().team = Team(3);
+        assert_eq!(
+            world.index::<Unit, Team>().get(&Team(3)),
+            &[units[3], units[6]]
+        );
+
+        // Removed components are left out, and cleared once they make up most of the index
+        for &unit in &units[..8] {
+            world.despawn(unit);
+        }
+        assert_eq!(world.index::<Unit, Team>().get(&Team(0)), &[units[9]]);
+        world.increment_change_tick();
+        world.spawn_with((Unit {
+            team: Team(1),
+            hp: 1,
+        },));
+        let index = world.index::<Unit, Team>();
+        assert_eq!(index.indexed(), 3);
+        assert_eq!(index.key(units[3]), None);
+    }
+
+    #[test]
+    fn move_entities_between_worlds() {
+        #[derive(Debug, PartialEq)]
+        struct A(u32);
+        #[derive(Debug, PartialEq)]
+        struct B(u64);
+        #[derive(Debug, PartialEq)]
+        struct C(&'static str);
+
+        // The worlds give different ids to the same kinds of components
+        let mut play = World::default();
+        let registry = play.component_registry_mut();
+        registry.register_with_storage::<A>(StorageType::Archetype);
+        registry.register_with_storage::<B>(StorageType::SparseSet);
+        let mut preview = World::default();
+        preview
+            .component_registry_mut()
+            .register_with_storage::<B>(StorageType::Archetype);
+        assert_ne!(
+            play.component_registry().id::<B>(),
+            preview.component_registry().id::<B>()
+        );
+
+        let counter = Arc::new(Count::default());
+        let parent = play.spawn();
+        let e = play.spawn_with((A(1), B(2), C("c"), Counter::new(counter.clone())));
+        let other = play.spawn_with((A(3), B(4)));
+        play.set_parent(e, parent);
+        play.relate::<()>(other, e);
+
+        let moved = play.move_entity_to(&mut preview, e).unwrap();
+        assert!(!play.entities().exists(e));
+        assert!(play.children(parent).is_empty());
+        assert!(play.related::<()>(other).is_empty());
+        assert_eq!(play.get::<A>(other).as_deref(), Some(&A(3)));
+        assert_eq!(play.get::<B>(other).as_deref(), Some(&B(4)));
+        assert_eq!(play.despawned(), &[e]);
+
+        assert_eq!(preview.get::<A>(moved).as_deref(), Some(&A(1)));
+        assert_eq!(preview.get::<B>(moved).as_deref(), Some(&B(2)));
+        assert_eq!(preview.get::<C>(moved).as_deref(), Some(&C("c")));
+        assert_eq!(preview.parent(moved), None);
+        let registry = preview.component_registry();
+        assert_eq!(
+            registry.component::<A>().unwrap().info.name(),
+            any::type_name::<A>()
+        );
+        assert_eq!(
+            registry.component::<A>().unwrap().storage.storage_type(),
+            StorageType::Archetype
+        );
+        assert_eq!(play.move_entity_to(&mut preview, e), None);
+
+        // Components are dropped exactly once
+        assert_eq!(counter.get(), 1);
+        let back = preview.move_entity_to(&mut play, moved).unwrap();
+        assert_eq!(play.get::<Counter>(back).map(|c| c.1), Some(""));
+        mem::drop(preview);
+        assert_eq!(counter.get(), 1);
+        play.despawn(back);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn append_worlds() {
+        use crate::{
+            dynamic::{FieldType, Schema, Value},
+            name::Name,
+            scene::{EntityMap, MapEntities},
+        };
+
+        #[derive(Debug, PartialEq)]
+        struct Target(Entity);
+        impl MapEntities for Target {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0 = map.map(self.0);
+            }
+        }
+        struct Likes;
+
+        let schema = || Schema::new("Mana").with_field("amount", FieldType::U32);
+        let mut world = World::default();
+        let counter = Arc::new(Count::default());
+        let existing = world.spawn_with((Name::new("existing"), Counter::new(counter.clone())));
+        let mana = world.component_registry_mut().register_dynamic(schema());
+
+        let mut staging = World::default();
+        let registry = staging.component_registry_mut();
+        registry.register_map_entities::<Target>();
+        let staging_mana = registry.register_dynamic(schema());
+        assert_ne!(mana, staging_mana);
+        let root = staging.spawn_with((Name::new("root"),));
+        let child = staging.spawn_with((Name::new("child"), Counter::new(counter.clone())));
+        staging.set_parent(child, root);
+        staging.add(root, Target(child));
+        staging.add(child, Target(root));
+        staging.relate::<Likes>(child, root);
+        let bytes = 7u32.to_ne_bytes();
+        staging.add_dynamic(root, staging_mana, &bytes);
+        staging.add_resource(5u32);
+
+        let map = world.append(&mut staging);
+        assert_eq!(map.len(), 2);
+        assert_eq!(staging.entities().iter().count(), 0);
+        assert_eq!(staging.resource::<u32>().as_deref(), Some(&5));
+        assert_eq!(world.resource::<u32>().as_deref(), None);
+
+        let (root, child) = (map.map(root), map.map(child));
+        assert_eq!(world.find_by_name("root"), Some(root));
+        assert_eq!(world.find_by_name("child"), Some(child));
+        assert_eq!(world.find_by_name("existing"), Some(existing));
+        assert_eq!(world.parent(child), Some(root));
+        assert_eq!(*world.children(root), [child]);
+        assert_eq!(world.get::<Target>(root).as_deref(), Some(&Target(child)));
+        assert_eq!(world.get::<Target>(child).as_deref(), Some(&Target(root)));
+        assert!(world.is_related::<Likes>(child, root));
+        assert_eq!(
+            world.get_dynamic(root, mana).unwrap().get("amount"),
+            Some(Value::U32(7))
+        );
+
+        assert_eq!(counter.get(), 2);
+        mem::drop(staging);
+        assert_eq!(counter.get(), 2);
+        mem::drop(world);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn appending_dynamic_components_with_other_schemas_panics() {
+        use crate::dynamic::{FieldType, Schema};
+
+        let mut world = World::default();
+        world
+            .component_registry_mut()
+            .register_dynamic(Schema::new("Mana").with_field("amount", FieldType::U32));
+        let mut other = World::default();
+        let mana = other
+            .component_registry_mut()
+            .register_dynamic(Schema::new("Mana").with_field("amount", FieldType::F32));
+        let e = other.spawn();
+        other.add_dynamic(e, mana, &[0; 4]);
+        world.append(&mut other);
+    }
+
+    #[test]
+    fn snapshots() {
+        use crate::dynamic::{FieldType, Schema, Value};
+
+        #[derive(Debug, PartialEq, Clone)]
+        struct Position(f32);
+        struct NotCloned;
+        struct Likes;
+
+        let counter = Arc::new(Count::default());
+        for storage_type in [
+            StorageType::VecStorage,
+            StorageType::SparseSet,
+            StorageType::Archetype,
+        ] {
+            let mut world = World::default();
+            let registry = world.component_registry_mut();
+            registry.register_with_storage::<Counter>(storage_type);
+            registry.register_clone::<Counter>();
+            registry.register_clone::<Position>();
+            let health = registry.register_dynamic_with_storage(
+                Schema::new("Health").with_field("current", FieldType::U32),
+                storage_type,
+            );
+
+            let a = world.spawn_with((
+                Counter::named(counter.clone(), "a"),
+                Position(0.0),
+                NotCloned,
+            ));
+            let b = world.spawn_with((Position(1.0),));
+            let c = world.spawn_with((Counter::named(counter.clone(), "c"),));
+            world.set_parent(b, a);
+            world.relate::<Likes>(b, a);
+            world.add_dynamic(a, health, &3u32.to_ne_bytes());
+
+            world.increment_change_tick();
+            let snapshot = world.snapshot();
+            assert!(snapshot.exists(c));
+            assert_eq!(counter.get(), 4);
+
+            world.increment_change_tick();
+            world.get_mut::<Position>(a).unwrap().0 = 5.0;
+            world.remove::<Counter>(a);
+            world.add(b, Counter::named(counter.clone(), "b"));
+            world.despawn(c);
+            let d = world.spawn_with((Counter::named(counter.clone(), "d"), Position(2.0)));
+            world.remove_parent(b);
+            world.unrelate::<Likes>(b, a);
+            world
+                .get_dynamic_mut(a, health)
+                .unwrap()
+                .set("current", Value::U32(9))
+                .unwrap();
+            assert!(!snapshot.exists(d));
+            assert_eq!(counter.get(), 4);
+
+            // Restoring twice gives the same world
+            for _ in 0..2 {
+                world.restore(&snapshot);
+                assert!(world.entities().exists(c));
+                assert!(!world.entities().exists(d));
+                assert_eq!(world.get::<Counter>(a).map(|c| c.1), Some("a"));
+                assert!(world.get::<Counter>(b).is_none());
+                assert_eq!(world.get::<Counter>(c).map(|c| c.1), Some("c"));
+                assert_eq!(world.get::<Position>(a).as_deref(), Some(&Position(0.0)));
+                assert_eq!(world.get::<Position>(b).as_deref(), Some(&Position(1.0)));
+                assert!(world.get::<NotCloned>(a).is_some());
+                assert_eq!(world.parent(b), Some(a));
+                assert_eq!(*world.children(a), [b]);
+                assert!(world.is_related::<Likes>(b, a));
+                assert_eq!(
+                    world.get_dynamic(a, health).unwrap().get("current"),
+                    Some(Value::U32(3))
+                );
+                assert_eq!(counter.get(), 4);
+            }
+            // Only the components which had changed are rewritten, and count as changed
+            assert_eq!(world.changed_since::<Position>(snapshot.change_tick()), [a]);
+
+            mem::drop(snapshot);
+            assert_eq!(counter.get(), 2);
+            mem::drop(world);
+            assert_eq!(counter.get(), 0);
+        }
+    }
+
+    /// How long `World::snapshot` takes for 10k entities with two cloned components, run with
+    /// `cargo test -p ecs --release -- --ignored --nocapture snapshot_benchmark`.
+    #[test]
+    #[ignore]
+    fn snapshot_benchmark() {
+        const RUNS: u32 = 100;
+
+        #[derive(Clone)]
+        struct Position([f32; 3]);
+        #[derive(Clone)]
+        struct Velocity([f32; 3]);
+
+        for storage_type in [
+            StorageType::VecStorage,
+            StorageType::SparseSet,
+            StorageType::Archetype,
+        ] {
+            let mut world = World::default();
+            let registry = world.component_registry_mut();
+            registry.register_with_storage::<Position>(storage_type);
+            registry.register_with_storage::<Velocity>(storage_type);
+            registry.register_clone::<Position>();
+            registry.register_clone::<Velocity>();
+            for _ in 0..10_000 {
+                world.spawn_with((Position([0.0; 3]), Velocity([1.0; 3])));
+            }
+
+            let now = Instant::now();
+            for _ in 0..RUNS - 1 {
+                world.snapshot();
+            }
+            let snapshot = world.snapshot();
+            println!("{:?}: {:?}", storage_type, now.elapsed() / RUNS);
+
+            for (position, velocity) in world.query::<(&mut Position, &Velocity)>().iter() {
+                position.0 = velocity.0;
+            }
+            world.restore(&snapshot);
+            assert!(world
+                .query::<&Position>()
+                .iter()
+                .all(|position| position.0 == [0.0; 3]));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn restoring_snapshot_of_other_world_panics() {
+        #[derive(Clone)]
+        struct A;
+        #[derive(Clone)]
+        struct B;
+
+        let mut world = World::default();
+        world.component_registry_mut().register_clone::<A>();
+        let snapshot = world.snapshot();
+        let mut other = World::default();
+        other.component_registry_mut().register_clone::<B>();
+        other.restore(&snapshot);
+    }
+
+    #[test]
+    #[should_panic]
+    fn looking_up_unknown_index_panics() {
+        let world = World::default();
+        world.index::<u32, u32>();
+    }
+
+    #[test]
+    fn scene() {
+        use crate::scene::{EntityMap, MapEntities, Scene, SceneDeserializer, SceneRegistry};
+        use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Position(f32, f32);
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Target(Entity);
+        impl MapEntities for Target {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0 = map.map(self.0);
+            }
+        }
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Gravity(f32);
+        struct NotSaved;
+
+        let mut registry = SceneRegistry::new();
+        registry
+            .register_named::<Position>("Position")
+            .register_mapped_named::<Target>("Target")
+            .register::<Gravity>();
+
+        let mut world = World::default();
+        world.add_resource(Gravity(9.81));
+        let a = world.spawn();
+        world.add(a, Position(1.0, 2.0));
+        world.add(a, NotSaved);
+        let b = world.spawn();
+        world.add(b, Target(a));
+        world.set_parent(b, a);
+
+        let scene = Scene::from_world(&world, &registry);
+        assert_eq!(scene.entities().len(), 2);
+        assert_eq!(scene.resource::<Gravity>(), Some(&Gravity(9.81)));
+        assert_eq!(
+            scene.entities()[0].component_names().collect::<Vec<_>>(),
+            ["Children", "Position"]
+        );
+
+        let ron = scene.to_ron().unwrap();
+        let from_ron = Scene::from_ron(&ron, &registry).unwrap();
+        let json = serde_json::to_string(&scene).unwrap();
+        let from_json = SceneDeserializer::new(&registry)
+            .deserialize(&mut serde_json::Deserializer::from_str(&json))
+            .unwrap();
+        #[cfg(feature = "binary")]
+        let from_binary = Scene::from_binary(&scene.to_binary().unwrap(), &registry).unwrap();
+
+        let scenes = [
+            scene,
+            from_ron,
+            from_json,
+            #[cfg(feature = "binary")]
+            from_binary,
+        ];
+        for scene in scenes {
+            let mut other = World::default();
+            let existing = other.spawn();
+            other.add(existing, Position(0.0, 0.0));
+
+            let map = scene.spawn(&mut other);
+            assert_eq!(map.len(), 2);
+            let (a2, b2) = (map.get(a).unwrap(), map.get(b).unwrap());
+            assert!(a2 != existing && b2 != existing);
+            assert_eq!(
+                other.get::<Position>(a2).as_deref(),
+                Some(&Position(1.0, 2.0))
+            );
+            assert!(other.get::<NotSaved>(a2).is_none());
+            assert_eq!(other.get::<Target>(b2).as_deref(), Some(&Target(a2)));
+            assert_eq!(other.parent(b2), Some(a2));
+            assert_eq!(*other.children(a2), [b2]);
+            assert_eq!(other.resource::<Gravity>().as_deref(), Some(&Gravity(9.81)));
+            assert_eq!(
+                other.get::<Position>(existing).as_deref(),
+                Some(&Position(0.0, 0.0))
+            );
+        }
+
+        assert!(matches!(
+            Scene::from_ron(&ron, &SceneRegistry::new()),
+            Err(SceneError::Parse(_))
+        ));
+
+        // Each entity may only be listed once
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let entities = value["entities"].as_array_mut().unwrap();
+        entities.push(entities[0].clone());
+        let error = SceneDeserializer::new(&registry)
+            .deserialize(&mut serde_json::Deserializer::from_str(&value.to_string()))
+            .unwrap_err();
+        assert!(error.to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn reflect() {
+        use crate::reflect::{TypeKind, Typed};
+
+        #[derive(Debug, PartialEq)]
+        struct Vec3 {
+            x: f32,
+            y: f32,
+            z: f32,
+        }
+        impl_reflect!(Vec3 { x, y, z });
+        struct Transform {
+            position: Vec3,
+            scale: f32,
+        }
+        impl_reflect!(Transform { position, scale });
+        struct Body {
+            transform: Transform,
+            mass: f32,
+            name: String,
+        }
+        impl_reflect!(Body {
+            transform,
+            mass,
+            name
+        });
+        #[derive(Debug, PartialEq)]
+        enum Shape {
+            Sphere(f32),
+            Cube(Vec3),
+        }
+        impl_reflect!(
+            enum Shape {
+                Sphere,
+                Cube,
+            }
+        );
+
+        let info = Body::info();
+        let names: Vec<_> = info.fields().iter().map(|f| f.name).collect();
+        assert_eq!(names, ["transform", "mass", "name"]);
+        let mass = info.field("mass").unwrap();
+        assert_eq!(mass.type_id, any::TypeId::of::<f32>());
+        assert_eq!(mass.offset, Some(mem::offset_of!(Body, mass)));
+        assert!(matches!(Shape::info().kind, TypeKind::Enum(v) if v.len() == 2));
+
+        let mut world = World::default();
+        let body_id = world.component_registry_mut().register_reflect::<Body>();
+        let shape_id = world.component_registry_mut().register_reflect::<Shape>();
+        let other_id = world.component_registry_mut().register::<u32>();
+        let e = world.spawn();
+        world.add(
+            e,
+            Body {
+                transform: Transform {
+                    position: Vec3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    scale: 1.0,
+                },
+                mass: 1.0,
+                name: "body".to_string(),
+            },
+        );
+        world.add(e, Shape::Sphere(0.5));
+        world.add(e, 1u32);
+        assert_eq!(world.reflect_components(e), [body_id, shape_id]);
+        assert!(world.get_reflect(e, other_id).is_none());
+        let empty = world.spawn();
+        assert!(world.get_reflect(empty, body_id).is_none());
+
+        let body = world.get_reflect(e, body_id).unwrap();
+        assert_eq!(
+            body.get_field("mass").unwrap().downcast_ref(),
+            Some(&1.0f32)
+        );
+        let x = body.get_field("transform.position.x").unwrap();
+        assert_eq!(x.downcast_ref(), Some(&0.0f32));
+        assert!(body.get_field("transform.nothing").is_none());
+        drop(body);
+
+        let tick = world.increment_change_tick();
+        let body = world.get_reflect_mut(e, body_id).unwrap();
+        body.set_field("transform.position.y", 2.0f32).unwrap();
+        body.set_field("name", "renamed".to_string()).unwrap();
+        assert_eq!(
+            body.set_field("mass", 2.0f64),
+            Err(ReflectError::WrongType {
+                path: "mass".to_string(),
+                expected: "f32",
+                found: "f64",
+            })
+        );
+        assert_eq!(
+            body.set_field("transform.nothing", 2.0f32),
+            Err(ReflectError::NoField("transform.nothing".to_string()))
+        );
+        let body = world.get::<Body>(e).unwrap();
+        assert_eq!(
+            body.transform.position,
+            Vec3 {
+                x: 0.0,
+                y: 2.0,
+                z: 0.0
+            }
+        );
+        assert_eq!(body.transform.scale, 1.0);
+        assert_eq!(body.name, "renamed");
+        assert_eq!(body.mass, 1.0);
+        assert_eq!(world.changed_since::<Body>(tick - 1), [e]);
+        drop(body);
+
+        let shape = world.get_reflect_mut(e, shape_id).unwrap();
+        assert_eq!(shape.variant(), Some("Sphere"));
+        assert!(shape.get_field("Cube").is_none());
+        shape.set_field("Sphere", 1.5f32).unwrap();
+        assert_eq!(world.get::<Shape>(e).as_deref(), Some(&Shape::Sphere(1.5)));
+
+        world.add(
+            e,
+            Shape::Cube(Vec3 {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            }),
+        );
+        let shape = world.get_reflect(e, shape_id).unwrap();
+        assert_eq!(shape.variant(), Some("Cube"));
+    }
+
+    #[test]
+    fn schedule_order() {
+        use crate::schedule::{Schedule, Stage, System};
+
+        // The systems all write to the log, so they're never run in parallel
+        struct Log;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let system = |name: &'static str| {
+            let log = log.clone();
+            System::new(name, move |_: &World| log.lock().unwrap().push(name))
+                .writes_resource::<Log>()
+        };
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::RenderPrep, system("render"))
+            .add_system(Stage::Update, system("c").after("b"))
+            .add_system(Stage::Update, system("a"))
+            .add_system(Stage::Update, system("b").before("a").after("pre"))
+            .add_system(Stage::PreUpdate, system("pre").before("render"))
+            .add_system(Stage::Update, system("d"));
+        assert_eq!(
+            schedule.run_order(Stage::Update).unwrap(),
+            ["b", "c", "a", "d"]
+        );
+
+        let mut world = World::default();
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), ["pre", "b", "c", "a", "d", "render"]);
+
+        schedule.add_system(Stage::Update, system("e").before("f"));
+        assert_eq!(
+            schedule.sort(),
+            Err(ScheduleError::UnknownSystem {
+                system: "e".into(),
+                other: "f".into()
+            })
+        );
+        schedule.add_system(Stage::Update, system("f").after("c").before("b"));
+        assert_eq!(
+            schedule.sort(),
+            Err(ScheduleError::Cycle(vec![
+                "c".into(),
+                "a".into(),
+                "b".into(),
+                "f".into()
+            ]))
+        );
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::PreUpdate, system("a"))
+            .add_system(Stage::PostUpdate, system("b").before("a"));
+        assert_eq!(
+            schedule.sort(),
+            Err(ScheduleError::WrongStage {
+                system: "b".into(),
+                other: "a".into()
+            })
+        );
+    }
+
+    #[test]
+    fn system_access() {
+        use crate::schedule::System;
+
+        struct A;
+        struct B;
+        struct Res;
+        let system = |name| System::new(name, |_: &World| {});
+        let read_a = system("read a").reads::<A>().reads_resource::<Res>();
+        let write_a = system("write a").writes::<A>();
+        let write_b = system("write b").writes::<B>().reads_resource::<Res>();
+        let write_res = system("write res").writes_resource::<Res>();
+        let exclusive = System::exclusive("exclusive", |_| {});
+
+        assert!(write_a.access().reads::<A>());
+        assert!(!read_a.access().writes::<A>());
+        assert!(read_a.conflicts_with(&write_a));
+        assert!(!read_a.conflicts_with(&write_b));
+        assert!(!read_a.conflicts_with(&read_a));
+        assert!(read_a.conflicts_with(&write_res));
+        assert!(write_b.conflicts_with(&write_res));
+        assert!(!write_a.conflicts_with(&write_res));
+        assert!(exclusive.conflicts_with(&read_a));
+    }
+
+    #[test]
+    fn parallel_schedule() {
+        use crate::schedule::{Schedule, Stage, System};
+
+        struct A(u32);
+        struct B(u32);
+        struct Sum;
+
+        let mut world = World::default();
+        for i in 0..100 {
+            let e = world.spawn();
+            world.add(e, A(i));
+            world.add(e, B(0));
+        }
+        let sum = Arc::new(AtomicUsize::new(0));
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(
+                Stage::Update,
+                System::new("increment a", |world: &World| {
+                    world.query::<&mut A>().for_each(|a| a.0 += 1);
+                })
+                .writes::<A>(),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("increment b", |world: &World| {
+                    world.query::<&mut B>().for_each(|b| b.0 += 1);
+                })
+                .writes::<B>(),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("sum", {
+                    let sum = sum.clone();
+                    move |world: &World| {
+                        let a = world.query::<&A>().iter().map(|a| a.0 as usize).sum();
+                        sum.store(a, Ordering::SeqCst);
+                    }
+                })
+                .reads::<A>()
+                .writes_resource::<Sum>(),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("read b", |world: &World| {
+                    assert!(world.query::<&B>().iter().all(|b| b.0 == 1));
+                })
+                .reads::<B>()
+                .after("increment b"),
+            )
+            .add_system(
+                Stage::Update,
+                System::exclusive("spawn", |world: &mut World| {
+                    world.spawn();
+                }),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("read a", |_: &World| {}).reads::<A>(),
+            );
+        assert_eq!(
+            schedule.run_batches(Stage::Update).unwrap(),
+            [
+                vec!["increment a", "increment b"],
+                vec!["sum", "read b"],
+                vec!["spawn"],
+                vec!["read a"],
+            ]
+        );
+
+        schedule.run(&mut world);
+        assert_eq!(sum.load(Ordering::SeqCst), (1..=100).sum::<usize>());
+        assert_eq!(world.entities().iter().count(), 101);
+    }
+
+    #[test]
+    fn non_send_systems_run_on_the_scheduling_thread() {
+        use crate::schedule::{Schedule, Stage, System};
+        use std::rc::Rc;
+
+        struct A(u32);
+        let mut world = World::default();
+        world.spawn_with((A(1),));
+        world.add_non_send_resource(Rc::new(5u32));
+        world.add_resource(0u32);
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(
+                Stage::Update,
+                System::new("read rc", |world: &World| {
+                    let rc = world.res::<Rc<u32>>().unwrap();
+                    *world.res_mut::<u32>().unwrap() += **rc;
+                })
+                .reads_non_send_resource::<Rc<u32>>()
+                .writes_resource::<u32>(),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("increment a", |world: &World| {
+                    world.query::<&mut A>().for_each(|a| a.0 += 1);
+                })
+                .writes::<A>(),
+            );
+        assert!(schedule.system("read rc").unwrap().1.is_non_send());
+        // Without the declaration both systems would share a batch and run on the thread pool
+        assert_eq!(
+            schedule.run_batches(Stage::Update).unwrap(),
+            [vec!["read rc"], vec!["increment a"]]
+        );
+        schedule.run(&mut world);
+        assert_eq!(*world.resource::<u32>().unwrap(), 5);
+        assert_eq!(world.query::<&A>().iter().next().unwrap().0, 2);
+    }
+
+    #[test]
+    fn borrow_from_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<World>();
+
+        struct A(u32);
+        struct B(u32);
+        let mut world = World::default();
+        for i in 0..10 {
+            let e = world.spawn();
+            world.add(e, A(i));
+            world.add(e, B(i));
+        }
+
+        let a = world.query::<&A>();
+        thread::scope(|scope| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        assert!(world.try_query::<&mut A>().is_err());
+                        // Only succeeds if no other thread is writing to `B` at the same time
+                        if let Ok(mut query) = world.try_query::<(&A, &mut B)>() {
+                            query.for_each(|(a, b)| b.0 = a.0 + 1);
+                        }
+                        world.query::<&A>().iter().map(|a| a.0).sum::<u32>()
+                    })
+                })
+                .collect();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), 45);
+            }
+        });
+        assert!(world
+            .query::<(&A, &B)>()
+            .iter()
+            .all(|(a, b)| b.0 == a.0 + 1));
+        drop(a);
+        assert!(world.try_query::<&mut A>().is_ok());
+        assert!(world.try_query::<&mut B>().is_ok());
+    }
+
+    #[test]
+    fn mutable_borrow_excludes_other_threads() {
+        use std::sync::Barrier;
+
+        struct A(u32);
+        let mut world = World::default();
+        for i in 0..10 {
+            world.spawn_with((A(i),));
+        }
+        let id = world.component_registry().id::<A>().unwrap();
+
+        let borrowed = Barrier::new(2);
+        let checked = Barrier::new(2);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut query = world.query::<&mut A>();
+                borrowed.wait();
+                query.for_each(|a| a.0 += 1);
+                checked.wait();
+            });
+            scope.spawn(|| {
+                borrowed.wait();
+                let registry = world.component_registry();
+                assert!(registry.try_borrow(id, false).is_none());
+                assert!(registry.try_borrow(id, true).is_none());
+                assert!(world.try_query::<&A>().is_err());
+                // The metadata can still be read while the storage is borrowed
+                assert!(registry.infos().any(|info| info.id() == id));
+                checked.wait();
+            });
+        });
+        assert_eq!(world.query::<&A>().iter().map(|a| a.0).sum::<u32>(), 55);
+    }
+
+    #[test]
+    fn iterate_over_query() {
+        let mut world = World::default();
+        struct Position(f32);
+        struct Velocity(f32);
+        let pos_id = world.component_registry_mut().register::<Position>();
+        let vel_id = world.component_registry_mut().register::<Velocity>();
+
+        for i in 0..1000 {
+            let entity = world.spawn();
+            world.add(entity, Position(i as f32));
+            world.add(entity, Velocity(1.5));
+        }
+
+        let q = Query::new(vec![
+            ComponentQuery {
+                id: pos_id,
+                mutable: true,
+                optional: false,
+            },
+            ComponentQuery {
+                id: vel_id,
+                mutable: false,
+                optional: false,
+            },
+        ])
+        .unwrap();
+        let mut q = world.query_raw(&q);
+        for (pos, vel) in unsafe {
+            q.iter().map(|(_e, comps)| {
+                if let [pos, vel] = comps[..] {
+                    (
+                        pos.cast::<Position>().as_mut().unwrap(),
+                        vel.cast::<Velocity>().as_ref().unwrap(),
+                    )
+                } else {
+                    panic!();
+                }
+            })
+        } {
+            pos.0 += vel.0;
+        }
+        mem::drop(q);
+
+        let q = Query::new(vec![ComponentQuery {
+            id: pos_id,
+            mutable: false,
+            optional: false,
+        }])
+        .unwrap();
+        let mut q = world.query_raw(&q);
+        for (i, pos) in unsafe {
+            q.iter()
+                .map(|(_e, comps)| {
+                    if let [pos] = comps[..] {
+                        pos.cast::<Position>().as_ref().unwrap()
+                    } else {
+                        panic!();
+                    }
+                })
+                .enumerate()
+        } {
+            assert_eq!(i as f32 + 1.5, pos.0);
+        }
+    }
+
+    #[test]
+    fn query_iterates_smallest_storage() {
+        let mut world = World::default();
+        struct Position;
+        struct Tag;
+        struct Group;
+        let pos_id = world.component_registry_mut().register::<Position>();
+        let tag_id = world
+            .component_registry_mut()
+            .register_with_storage::<Tag>(StorageType::SparseSet);
+        let group_id = world
+            .component_registry_mut()
+            .register_with_storage::<Group>(StorageType::Archetype);
+
+        for i in 0..100_000 {
+            let e = world.spawn();
+            world.add(e, Position);
+            if i % 10_000 == 0 {
+                world.add(e, Tag);
+            }
+            if i % 1_000 == 0 {
+                world.add(e, Group);
+            }
+        }
+
+        let query = |ids: &[ComponentId]| {
+            Query::new(
+                ids.iter()
+                    .map(|&id| ComponentQuery {
+                        id,
+                        mutable: false,
+                        optional: false,
+                    })
+                    .collect(),
+            )
+            .unwrap()
+        };
+
+        for (ids, candidates, matches) in [
+            (vec![pos_id], 100_000, 100_000),
+            (vec![pos_id, tag_id], 10, 10),
+            (vec![tag_id, pos_id], 10, 10),
+            (vec![pos_id, group_id], 100, 100),
+            (vec![pos_id, group_id, tag_id], 10, 10),
+        ] {
+            let q = query(&ids);
+            let mut res = world.query_raw(&q);
+            let iter = unsafe { res.iter() };
+            assert_eq!(iter.size_hint().1, Some(candidates));
+            assert_eq!(iter.count(), matches);
+        }
+    }
+
+    #[test]
+    fn resources() {
+        let mut world = World::default();
+        struct Time {
+            now: Instant,
+            dt: Duration,
+        }
+        struct Gravity {
+            accel_x: f32,
+            accel_y: f32,
+        }
+        world.add_resource(Time {
+            now: Instant::now(),
+            dt: Duration::from_millis(16),
+        });
+        world.add_resource(Gravity {
+            accel_x: 0.,
+            accel_y: -9.818,
+        });
+
+        assert_eq!(16_000, world.resource::<Time>().unwrap().dt.as_micros());
+        assert_eq!(0., world.resource::<Gravity>().unwrap().accel_x);
+        assert_eq!(-9.818, world.resource::<Gravity>().unwrap().accel_y);
 
         let time = world.resource_mut::<Time>().unwrap();
         let now = Instant::now();
@@ -695,6 +2920,225 @@ mod tests {
         assert_eq!(15_000, dt);
     }
 
+    #[test]
+    fn resource_borrows() {
+        #[derive(Debug, PartialEq)]
+        struct Gravity(f32);
+        struct Score(u32);
+        struct Velocity(f32);
+
+        let mut world = World::default();
+        let gravity = world.add_resource(Gravity(-9.81));
+        world.add_resource(Score(0));
+        world.spawn_with((Velocity(0.0),));
+        // Resources are not stored on an entity
+        assert_eq!(world.entities().iter().count(), 1);
+        assert_eq!(world.resource_id::<Gravity>(), Some(gravity));
+
+        {
+            let mut query = world.query::<&mut Velocity>();
+            let g = world.res::<Gravity>().unwrap();
+            let mut score = world.res_mut::<Score>().unwrap();
+            for velocity in query.iter() {
+                velocity.0 += g.0;
+                score.0 += 1;
+            }
+            assert_eq!(
+                *world.resource_by_id::<Gravity>(gravity).unwrap(),
+                Gravity(-9.81)
+            );
+        }
+        assert_eq!(world.resource::<Score>().unwrap().0, 1);
+        world.resource_by_id_mut::<Gravity>(gravity).unwrap().0 = 0.0;
+        assert_eq!(world.remove_resource::<Gravity>(), Some(Gravity(0.0)));
+        assert!(world.res::<Gravity>().is_none());
+        assert!(world.resource_by_id::<Gravity>(gravity).is_none());
+
+        // Resources without `Send` can only be used on their own thread
+        let rc = std::rc::Rc::new(5);
+        let rc_id = world.add_non_send_resource(rc.clone());
+        assert_eq!(**world.res::<std::rc::Rc<i32>>().unwrap(), 5);
+        assert!(!world.resources().info(rc_id).is_send());
+        assert!(world.resources().info(gravity).is_send());
+        thread::scope(|s| {
+            let world = &world;
+            let res = s.spawn(move || world.resource::<std::rc::Rc<i32>>().is_some());
+            assert!(res.join().is_err());
+            // Send resources still work
+            s.spawn(move || assert!(world.res::<Score>().is_some()));
+        });
+        mem::drop(world);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn dropping_non_send_resource_on_another_thread_panics() {
+        let mut world = World::default();
+        world.add_non_send_resource(std::rc::Rc::new(0));
+        world.add_resource(0u32);
+        // The resource is leaked rather than dropped on the wrong thread
+        assert!(thread::spawn(move || mem::drop(world)).join().is_err());
+
+        // Removing the resource first lets the world be dropped anywhere
+        let mut world = World::default();
+        world.add_non_send_resource(std::rc::Rc::new(0));
+        assert!(world.remove_resource::<std::rc::Rc<i32>>().is_some());
+        thread::spawn(move || mem::drop(world)).join().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn borrowing_borrowed_resource_panics() {
+        let mut world = World::default();
+        world.add_resource(0u32);
+        let _a = world.res::<u32>();
+        let _b = world.res_mut::<u32>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn mutably_borrowing_read_resource_panics() {
+        let mut world = World::default();
+        world.add_resource(0u32);
+        let _a = world.resource::<u32>();
+        let _b = world.res_mut::<u32>();
+    }
+
+    #[test]
+    fn raw_resources() {
+        let counter = Arc::new(Count::default());
+        let mut world = World::default();
+        let id = unsafe {
+            world.resources_mut().register_raw(
+                "counter".into(),
+                Layout::new::<Counter>(),
+                |ptr| ptr.cast::<Counter>().drop_in_place(),
+                true,
+            )
+        };
+        assert!(!world.resources().contains(id));
+        assert_eq!(world.resources().info(id).type_id(), None);
+        let mut counter_a = mem::ManuallyDrop::new(Counter::named(counter.clone(), "a"));
+        unsafe {
+            world
+                .resources_mut()
+                .insert_ptr(id, (&mut *counter_a as *mut Counter).cast());
+        }
+        let borrow = world.resources().borrow(id, false).unwrap();
+        assert_eq!(unsafe { borrow.ptr().cast::<Counter>().as_ref() }.1, "a");
+        drop(borrow);
+
+        // Replacing the resource drops the old one
+        let mut counter_b = mem::ManuallyDrop::new(Counter::named(counter.clone(), "b"));
+        unsafe {
+            world
+                .resources_mut()
+                .insert_ptr(id, (&mut *counter_b as *mut Counter).cast());
+        }
+        assert_eq!(counter.get(), 1);
+        mem::drop(world);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn events() {
+        use crate::event::{EventReader, Events};
+        use crate::schedule::{Schedule, Stage, System};
+
+        #[derive(Debug, PartialEq)]
+        struct Hit(u32);
+
+        let mut world = World::default();
+        world.add_event::<Hit>();
+        let mut early = EventReader::<Hit>::new();
+        world.send_event(Hit(1));
+        let mut late = world.resource::<Events<Hit>>().unwrap().reader();
+        world.event_writer().send_batch([Hit(2), Hit(3)]);
+
+        let events = world.resource::<Events<Hit>>().unwrap();
+        assert_eq!(early.len(&events), 3);
+        assert_eq!(
+            early.read(&events).collect::<Vec<_>>(),
+            [&Hit(1), &Hit(2), &Hit(3)]
+        );
+        assert!(early.is_empty(&events));
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), [&Hit(2), &Hit(3)]);
+        drop(events);
+
+        // Events are kept for one more update, then dropped
+        world.update_events();
+        world.send_event(Hit(4));
+        let events = world.resource::<Events<Hit>>().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(early.read(&events).collect::<Vec<_>>(), [&Hit(4)]);
+        let mut new = EventReader::<Hit>::new();
+        assert_eq!(new.len(&events), 4);
+        drop(events);
+        world.update_events();
+        world.update_events();
+        assert!(world.resource::<Events<Hit>>().unwrap().is_empty());
+        assert!(new
+            .read(&world.resource::<Events<Hit>>().unwrap())
+            .next()
+            .is_none());
+
+        // A reader which did not read for two updates misses events
+        world.send_event(Hit(5));
+        world.update_events();
+        world.send_event(Hit(6));
+        world.update_events();
+        let events = world.resource::<Events<Hit>>().unwrap();
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), [&Hit(6)]);
+        drop(events);
+
+        // Adding the same event again keeps the events
+        world.add_event::<Hit>();
+        assert_eq!(world.resource::<Events<Hit>>().unwrap().len(), 1);
+
+        // Systems send and read events in any order
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = EventReader::<Hit>::new();
+        let log = received.clone();
+        let mut sent = 10;
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(
+                Stage::PreUpdate,
+                System::exclusive("events", World::update_events),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("read", move |world: &World| {
+                    let events = world.res::<Events<Hit>>().unwrap();
+                    log.lock()
+                        .unwrap()
+                        .extend(reader.read(&events).map(|h| h.0));
+                })
+                .reads_resource::<Events<Hit>>(),
+            )
+            .add_system(
+                Stage::Update,
+                System::new("send", move |world: &World| {
+                    world.event_writer().send(Hit(sent));
+                    sent += 1;
+                })
+                .writes_resource::<Events<Hit>>()
+                .after("read"),
+            );
+        schedule.run(&mut world);
+        assert!(received.lock().unwrap().is_empty());
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(*received.lock().unwrap(), [10, 11]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn sending_unknown_event_panics() {
+        struct Hit;
+        World::default().send_event(Hit);
+    }
+
     #[test]
     #[should_panic]
     fn borrowing_borrowed_component_panics() {
@@ -709,13 +3153,39 @@ mod tests {
         }])
         .unwrap();
         // This creates a mutable borrow on `usize`s
-        let q = world.query(&q);
+        let q = world.query_raw(&q);
         // And this another one
         world.get::<usize>(entity);
         // While the first borrow still exists
         mem::drop(q);
     }
 
+    #[test]
+    fn component_refs_borrow_components() {
+        let mut world = World::default();
+        let e = world.spawn();
+        world.add(e, 1usize);
+        let child = world.spawn();
+        world.set_parent(child, e);
+        let id = world.component_registry().id::<usize>().unwrap();
+
+        let value = world.get::<usize>(e).unwrap();
+        assert_eq!(
+            world.try_query::<&mut usize>().err(),
+            Some(BorrowMutError::new(id))
+        );
+        assert!(world.try_query::<&usize>().is_ok());
+        assert_eq!(*value, 1);
+        drop(value);
+        world.query::<&mut usize>().for_each(|v| *v = 2);
+        assert_eq!(world.get::<usize>(e).as_deref(), Some(&2));
+
+        let children = world.children(e);
+        assert!(world.try_query::<&mut hierarchy::Children>().is_err());
+        drop(children);
+        assert!(world.try_query::<&mut hierarchy::Children>().is_ok());
+    }
+
     #[test]
     fn command_buffer_despawn_entities() {
         struct Health(u8);
@@ -754,7 +3224,7 @@ mod tests {
         let mut world = World::default();
         let e1 = world.spawn();
         let e2 = world.spawn();
-        let counter = Rc::new(Cell::new(0));
+        let counter = Arc::new(Count::default());
         let mut command_buffer = CommandBuffer::new();
         let mut commands = Commands::new(&mut command_buffer, world.entities());
 
@@ -773,7 +3243,7 @@ mod tests {
     #[test]
     fn add_component_to_newly_created_entity_through_commands() {
         let mut world = World::default();
-        let counter = Rc::new(Cell::new(0));
+        let counter = Arc::new(Count::default());
         let mut command_buffer = CommandBuffer::new();
         let mut commands = Commands::new(&mut command_buffer, world.entities());
 
@@ -861,10 +3331,10 @@ mod tests {
             p.1 += v.1;
         });
 
-        assert_eq!(world.get::<Pos>(e1), Some(&Pos(2, 0)));
-        assert_eq!(world.get::<Pos>(e2), Some(&Pos(0, 2)));
-        assert_eq!(world.get::<Pos>(e3), Some(&Pos(-2, 0)));
-        assert_eq!(world.get::<Pos>(e4), Some(&Pos(0, -2)));
+        assert_eq!(world.get::<Pos>(e1).as_deref(), Some(&Pos(2, 0)));
+        assert_eq!(world.get::<Pos>(e2).as_deref(), Some(&Pos(0, 2)));
+        assert_eq!(world.get::<Pos>(e3).as_deref(), Some(&Pos(-2, 0)));
+        assert_eq!(world.get::<Pos>(e4).as_deref(), Some(&Pos(0, -2)));
     }
 
     #[test]
@@ -885,12 +3355,15 @@ mod tests {
             count += 1;
         });
         assert_eq!(count, 2);
+        query_iter!(world, (parent: Parent) => {
+            assert!(world.entities().exists(parent.0));
+        });
     }
 
     #[test]
     fn drop_command_buffer_while_it_owns_components() {
         let world = World::default();
-        let counter = Rc::new(Cell::new(0));
+        let counter = Arc::new(Count::default());
         {
             let mut command_buffer = CommandBuffer::new();
             let mut commands = Commands::new(&mut command_buffer, world.entities());
@@ -903,6 +3376,157 @@ mod tests {
         assert_eq!(counter.get(), 0);
     }
 
+    #[test]
+    fn drop_command_buffer_while_it_owns_bundles() {
+        let world = World::default();
+        let counter = Arc::new(Count::default());
+        {
+            let mut command_buffer = CommandBuffer::new();
+            let mut commands = Commands::new(&mut command_buffer, world.entities());
+
+            let e1 = commands.spawn_with((Counter::named(counter.clone(), "a"), 1u32));
+            commands.insert_bundle(e1, (Counter::named(counter.clone(), "b"),));
+            assert_eq!(counter.get(), 2);
+        }
+
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn drop_command_buffer_while_it_owns_resources() {
+        let world = World::default();
+        let counter = Arc::new(Count::default());
+        {
+            let mut command_buffer = CommandBuffer::new();
+            let mut commands = Commands::new(&mut command_buffer, world.entities());
+
+            commands.add_resource(Counter::named(counter.clone(), "a"));
+            assert_eq!(counter.get(), 1);
+        }
+
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn drop_command_buffer_while_it_owns_closures() {
+        let world = World::default();
+        let counter = Arc::new(Count::default());
+        {
+            let mut command_buffer = CommandBuffer::new();
+            let mut commands = Commands::new(&mut command_buffer, world.entities());
+
+            let captured = Counter::named(counter.clone(), "a");
+            commands.run(move |world| {
+                world.add_resource(captured);
+            });
+            assert_eq!(counter.get(), 1);
+        }
+
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn drop_command_buffer_with_removals() {
+        let mut world = World::default();
+        let e1 = world.spawn();
+        world.add(e1, 1u32);
+        world.add_resource(1u64);
+        {
+            let mut command_buffer = CommandBuffer::new();
+            let mut commands = Commands::new(&mut command_buffer, world.entities());
+
+            commands.remove::<u32>(e1);
+            commands.remove_bundle::<(u32,)>(e1);
+            commands.remove_resource::<u64>();
+        }
+
+        assert_eq!(world.get::<u32>(e1).as_deref(), Some(&1));
+        assert_eq!(world.resource::<u64>().as_deref(), Some(&1));
+    }
+
+    #[test]
+    fn deferred_commands() {
+        let mut world = World::default();
+        let counter = Arc::new(Count::default());
+        let e1 = world.spawn();
+        world.add(e1, Counter::named(counter.clone(), "a"));
+        world.add(e1, 1u32);
+        world.add_resource(Counter::named(counter.clone(), "resource"));
+
+        let mut command_buffer = CommandBuffer::new();
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        commands.remove::<Counter>(e1);
+        commands.add_resource(2u32);
+        commands.add_resource(Counter::named(counter.clone(), "new resource"));
+        let e2 = commands.spawn();
+        commands.run(move |world| {
+            let value = *world.resource::<u32>().unwrap();
+            world.add(e2, value);
+        });
+        assert_eq!(counter.get(), 3);
+        command_buffer.apply(&mut world);
+
+        // The replaced resource and the removed component are dropped
+        assert_eq!(counter.get(), 1);
+        assert_eq!(world.resource::<Counter>().unwrap().1, "new resource");
+        assert!(world.get::<Counter>(e1).is_none());
+        assert_eq!(world.get::<u32>(e1).as_deref(), Some(&1));
+        assert_eq!(world.get::<u32>(e2).as_deref(), Some(&2));
+
+        let mut commands = Commands::new(&mut command_buffer, world.entities());
+        commands.remove_resource::<Counter>();
+        command_buffer.apply(&mut world);
+        assert!(world.resource::<Counter>().is_none());
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn command_buffer_in_system() {
+        use crate::schedule::{Schedule, Stage, System};
+
+        fn assert_send<T: Send>() {}
+        assert_send::<CommandBuffer>();
+
+        struct Speed(u32);
+        struct Boosted;
+        let mut world = World::default();
+        world.spawn_with((Speed(1),));
+        world.spawn_with((Speed(5),));
+
+        // The system changes the structure of the world while its query is borrowed, through a
+        // buffer applied once the schedule is done
+        let buffer = Arc::new(Mutex::new(CommandBuffer::new()));
+        let system_buffer = buffer.clone();
+        let mut schedule = Schedule::new();
+        schedule.add_system(
+            Stage::Update,
+            System::new("boost", move |world: &World| {
+                let mut buffer = system_buffer.lock().unwrap();
+                let mut commands = Commands::new(&mut buffer, world.entities());
+                for (entity, speed) in world.query::<(Entity, &Speed)>().iter() {
+                    if speed.0 > 2 {
+                        commands.add(entity, Boosted);
+                    } else {
+                        commands.despawn(entity);
+                    }
+                }
+                let e = commands.spawn();
+                commands.add(e, Speed(10));
+            })
+            .reads::<Speed>(),
+        );
+        schedule.run(&mut world);
+        buffer.lock().unwrap().apply(&mut world);
+
+        let mut speeds: Vec<_> = world
+            .query::<(&Speed, Option<&Boosted>)>()
+            .iter()
+            .map(|(speed, boosted)| (speed.0, boosted.is_some()))
+            .collect();
+        speeds.sort();
+        assert_eq!(speeds, [(5, true), (10, false)]);
+    }
+
     #[test]
     fn cannot_get_component_of_old_entity() {
         let mut world = World::default();
@@ -917,7 +3541,7 @@ mod tests {
             optional: true,
         }])
         .unwrap();
-        let mut res = world.query(&q);
+        let mut res = world.query_raw(&q);
         assert!(unsafe { res.try_get(a).is_none() });
         assert!(unsafe { res.try_get(b).is_some() });
     }
@@ -998,20 +3622,32 @@ mod tests {
         assert_eq!(c, 20 * 19 / 2);
     }
 
-    #[derive(Debug)]
-    struct Counter(Rc<Cell<usize>>, &'static str);
+    #[derive(Debug, Default)]
+    struct Count(AtomicUsize);
+    impl Count {
+        fn get(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    struct Counter(Arc<Count>, &'static str);
     impl Counter {
-        fn new(rc: Rc<Cell<usize>>) -> Self {
-            Self::named(rc, "")
+        fn new(count: Arc<Count>) -> Self {
+            Self::named(count, "")
         }
-        fn named(rc: Rc<Cell<usize>>, name: &'static str) -> Self {
-            rc.set(rc.get() + 1);
-            Self(rc, name)
+        fn named(count: Arc<Count>, name: &'static str) -> Self {
+            count.0.fetch_add(1, Ordering::SeqCst);
+            Self(count, name)
+        }
+    }
+    impl Clone for Counter {
+        fn clone(&self) -> Self {
+            Self::named(self.0.clone(), self.1)
         }
     }
     impl Drop for Counter {
         fn drop(&mut self) {
-            self.0.set(self.0.get() - 1)
+            self.0 .0.fetch_sub(1, Ordering::SeqCst);
         }
     }
 }
@@ -0,0 +1,163 @@
+use std::{
+    alloc::Layout,
+    any::{self, Any, TypeId},
+    fmt,
+};
+
+use crate::{
+    bundle::Bundle,
+    component::{clone_ptr, CloneFn, ComponentBuffer, ComponentId, ComponentRegistry},
+    Entity, World,
+};
+
+/// A template of components from which any amount of entities can be spawned, each getting
+/// clones of the components. Components can be overridden for every entity spawned, e.g. to give
+/// each one its own position.
+/// # Examples
+/// ```
+/// # use ecs::{prefab::Prefab, World};
+/// #[derive(Clone)]
+/// struct Health(u32);
+/// #[derive(Clone)]
+/// struct Position(f32, f32);
+///
+/// let enemy = Prefab::new().with(Health(100)).with(Position(0.0, 0.0));
+///
+/// let mut world = World::default();
+/// let a = enemy.spawn(&mut world);
+/// let b = enemy.spawn_with(&mut world, (Position(5.0, 0.0),));
+/// assert_eq!(world.get::<Position>(a).unwrap().0, 0.0);
+/// assert_eq!(world.get::<Position>(b).unwrap().0, 5.0);
+/// assert_eq!(world.get::<Health>(b).unwrap().0, 100);
+/// ```
+#[derive(Default)]
+pub struct Prefab {
+    components: Vec<PrefabComponent>,
+}
+
+struct PrefabComponent {
+    name: &'static str,
+    type_id: TypeId,
+    value: Box<dyn Any + Send + Sync>,
+    layout: Layout,
+    clone: CloneFn,
+    // Returns the id of the component kind, registering it if needed
+    id: fn(&mut ComponentRegistry) -> ComponentId,
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `component` to the template, replacing the component of the same kind if there is
+    /// one.
+    pub fn with<T: Clone + Send + Sync + 'static>(mut self, component: T) -> Self {
+        self.insert(component);
+        self
+    }
+
+    /// Same as `with` for a prefab which is borrowed.
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, component: T) -> &mut Self {
+        let component = PrefabComponent {
+            name: any::type_name::<T>(),
+            type_id: TypeId::of::<T>(),
+            value: Box::new(component),
+            layout: Layout::new::<T>(),
+            clone: clone_ptr::<T>,
+            id: component_id::<T>,
+        };
+        match self.position::<T>() {
+            Some(i) => self.components[i] = component,
+            None => self.components.push(component),
+        }
+        self
+    }
+
+    /// Removes the component of kind `T` from the template.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let component = self.components.remove(self.position::<T>()?);
+        component.value.downcast().ok().map(|c| *c)
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        let i = self.position::<T>()?;
+        self.components[i].value.downcast_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        let i = self.position::<T>()?;
+        self.components[i].value.downcast_mut()
+    }
+
+    /// Spawns a new entity with clones of the components in the template.
+    pub fn spawn(&self, world: &mut World) -> Entity {
+        self.spawn_with(world, ())
+    }
+
+    /// Spawns a new entity with clones of the components in the template, except for those of
+    /// the kinds in `overrides`, which it gets instead. Like `World::spawn_with`, the entity is
+    /// moved to its archetype only once.
+    pub fn spawn_with<B: Bundle>(&self, world: &mut World, overrides: B) -> Entity {
+        let overridden = world.bundle_ids::<B>();
+        let registry = world.component_registry_mut();
+        let template: Vec<(&PrefabComponent, ComponentId)> = self
+            .components
+            .iter()
+            .map(|c| (c, (c.id)(registry)))
+            .filter(|(_, id)| !overridden.contains(id))
+            .collect();
+
+        let buffer = ComponentBuffer::new(template.iter().map(|(c, _)| c.layout));
+        for (i, (c, _)) in template.iter().enumerate() {
+            let value = &*c.value as *const (dyn Any + Send + Sync) as *const u8;
+            // Safety: `clone` clones the type of `value`
+            unsafe { (c.clone)(value, buffer.get(i)) };
+        }
+        let mut ids: Vec<ComponentId> = template.iter().map(|&(_, id)| id).collect();
+        ids.extend_from_slice(&overridden);
+
+        let entity = world.spawn();
+        // Safety: the buffer holds a clone of a component of every kind in the template, in
+        // order, followed by the components of the bundle
+        unsafe {
+            world.insert_components(entity.get_id_unchecked(), &ids, |mut f| {
+                (0..buffer.len()).for_each(|i| f(buffer.get(i)));
+                overrides.take_components(&mut f);
+            });
+        }
+        entity
+    }
+
+    /// Spawns an entity for every bundle in `overrides`, see `spawn_with`.
+    pub fn spawn_batch<B: Bundle>(
+        &self,
+        world: &mut World,
+        overrides: impl IntoIterator<Item = B>,
+    ) -> Vec<Entity> {
+        overrides
+            .into_iter()
+            .map(|o| self.spawn_with(world, o))
+            .collect()
+    }
+
+    fn position<T: 'static>(&self) -> Option<usize> {
+        self.components
+            .iter()
+            .position(|c| c.type_id == TypeId::of::<T>())
+    }
+}
+
+impl fmt::Debug for Prefab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.components.iter().map(|c| c.name))
+            .finish()
+    }
+}
+
+fn component_id<T: Send + Sync + 'static>(registry: &mut ComponentRegistry) -> ComponentId {
+    registry
+        .id::<T>()
+        .unwrap_or_else(|| registry.register::<T>())
+}
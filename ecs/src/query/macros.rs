@@ -14,7 +14,7 @@ macro_rules! query_iter {
         $crate::_query_definition!($world, v, ($($query)*));
         let q = $crate::query::Query::new(v).expect("Query violates rusts borrow rules");
 
-        let mut res = $world.query(&q);
+        let mut res = $world.query_raw(&q);
 
         #[allow(unused_variables)]
         for (e, comps) in unsafe { res.iter() } {
@@ -41,7 +41,7 @@ macro_rules! query_iter_combs {
         $crate::_query_definition!($world, v, ($($query)*));
         let q = $crate::query::Query::new(v).expect("Query violates rusts borrow rules");
 
-        let mut res = $world.query(&q);
+        let mut res = $world.query_raw(&q);
 
         #[allow(unused_variables)]
         for ((e1, comps1), (e2, comps2)) in unsafe { res.iter_combinations() } {
@@ -60,19 +60,19 @@ macro_rules! _query_definition {
     }};
     // opt
     ( $world:expr, $vec:expr, ($name:tt: Option<$type:ty>, $($tail:tt)*) ) => {{
-        $vec.push(ComponentQuery {
+        $vec.push($crate::query::ComponentQuery {
             id: $world.component_registry().id::<$type>().expect(&format!(
                     "Tried querying for unregistered type {}",
                     std::any::type_name::<$type>(),
             )),
             mutable: false,
-            optional: false,
+            optional: true,
         });
         $crate::_query_definition!($world, $vec, ($($tail)*));
     }};
     // opt mut
     ( $world:expr, $vec:expr, ($name:tt: mut Option<$type:ty>, $($tail:tt)*) ) => {{
-        $vec.push(ComponentQuery {
+        $vec.push($crate::query::ComponentQuery {
             id: $world.component_registry().id::<$type>().expect(&format!(
                     "Tried querying for unregistered type {}",
                     std::any::type_name::<$type>(),
@@ -122,7 +122,7 @@ macro_rules! _query_definition {
     }};
     // mut opt
     ( $world:expr, $vec:expr, ($name:tt: mut Option<$type:ty>) ) => {{
-        $vec.push(ComponentQuery {
+        $vec.push($crate::query::ComponentQuery {
             id: $world.component_registry().id::<$type>().expect(&format!(
                     "Tried querying for unregistered type {}",
                     std::any::type_name::<$type>(),
@@ -226,7 +226,7 @@ macro_rules! _query_defvars_combs {
             $crate::query::_as_opt_mut_lt($lt, $comps1[0].cast::<$type>()),
             $crate::query::_as_opt_mut_lt($lt, $comps2[0].cast::<$type>()),
         ) };
-        $crate::_query_defvars_combs!($comps[1..], $lt, $entity, ($($tail)*));
+        $crate::_query_defvars_combs!($comps1[1..], $comps2[1..], $lt, $entity, ($($tail)*));
     };
     // comp
     ( $comps1:expr, $comps2:expr, $lt:expr, $entity:expr, ($name:tt: $type:ty, $($tail:tt)*) ) => {
@@ -249,6 +249,20 @@ macro_rules! _query_defvars_combs {
     ( $comps1:expr, $comps2:expr, $lt:expr, $entity:expr, ($name:tt: Entity) ) => {
         let $name = $entity;
     };
+    // opt
+    ( $comps1:expr, $comps2:expr, $lt:expr, $entity:expr, ($name:tt: Option<$type:ty>) ) => {
+        let $name = unsafe { (
+            $crate::query::_as_opt_ref_lt($lt, $comps1[0].cast::<$type>()),
+            $crate::query::_as_opt_ref_lt($lt, $comps2[0].cast::<$type>()),
+        ) };
+    };
+    // opt mut
+    ( $comps1:expr, $comps2:expr, $lt:expr, $entity:expr, ($name:tt: mut Option<$type:ty>) ) => {
+        let $name = unsafe { (
+            $crate::query::_as_opt_mut_lt($lt, $comps1[0].cast::<$type>()),
+            $crate::query::_as_opt_mut_lt($lt, $comps2[0].cast::<$type>()),
+        ) };
+    };
     // comp
     ( $comps1:expr, $comps2:expr, $lt:expr, $entity:expr, ($name:tt: $type:ty) ) => {
         let $name = unsafe { (
@@ -1,22 +1,33 @@
-use std::collections::HashSet;
+use rayon::prelude::*;
+use std::{borrow::Cow, collections::HashSet};
 
 use crate::{
-    component::{ComponentEntryRef, ComponentId},
-    entity::{Iter as EntityIter, IterCombinations as EntityIterCombinations},
+    component::{
+        Archetype, Archetypes, ComponentEntryRef, ComponentId, Indices, Location, Storage,
+        StorageType,
+    },
+    entity::Iter as EntityIter,
     BorrowMutError, Entity, World,
 };
 
 pub mod macros;
+mod typed;
 
 pub use self::macros::*;
+pub use self::typed::{
+    Added, Changed, ComponentAccess, ReadOnlyWorldQuery, TypedIter, TypedIterCombinations,
+    TypedParIter, TypedQuery, With, Without, WorldFilter, WorldQuery,
+};
 
 /// Represents a valid query for components without multiple mutable access to the same type of
-/// component.
-/// NOTE: there's currently no way of for example having one query for `mut A` on entities with a
-/// `B` and another for `mut A` on entities without a `B`, even though that would be safe.
+/// component, optionally restricted to the entities matching a set of filters.
+/// NOTE: one query for `mut A` on entities with a `B` and another for `mut A` on entities without
+/// a `B` can be expressed with filters, but since borrows are tracked per kind of component they
+/// can still not be alive at the same time, even though that would be safe.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Query {
     components: Vec<ComponentQuery>,
+    filters: Vec<ComponentFilter>,
 }
 
 /// Represents one part of a query.
@@ -27,8 +38,45 @@ pub struct ComponentQuery {
     pub optional: bool,
 }
 
+/// A part of a query which restricts which entities match, without giving access to any
+/// component. Filters only read the storages of the components, so they never conflict with the
+/// components of the same query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentFilter {
+    /// Only entities with the component match.
+    With(ComponentId),
+    /// Only entities without the component match.
+    Without(ComponentId),
+    /// Only entities whose component was added during the current change tick match, or after the
+    /// tick given to `QueryResponse::since`.
+    Added(ComponentId),
+    /// Only entities whose component was added or accessed mutably during the current change tick
+    /// match, or after the tick given to `QueryResponse::since`.
+    Changed(ComponentId),
+}
+
+impl ComponentFilter {
+    pub fn id(&self) -> ComponentId {
+        match *self {
+            Self::With(id) | Self::Without(id) | Self::Added(id) | Self::Changed(id) => id,
+        }
+    }
+
+    /// Returns `true` if only entities with the component can match the filter.
+    pub fn requires_component(&self) -> bool {
+        !matches!(self, Self::Without(_))
+    }
+}
+
 impl Query {
     pub fn new(components: Vec<ComponentQuery>) -> Result<Self, BorrowMutError> {
+        Self::with_filters(components, Vec::new())
+    }
+
+    pub fn with_filters(
+        components: Vec<ComponentQuery>,
+        filters: Vec<ComponentFilter>,
+    ) -> Result<Self, BorrowMutError> {
         let mut mutable_acces_to = HashSet::new();
         for c in components.iter().filter(|c| c.mutable) {
             if !mutable_acces_to.insert(c.id) {
@@ -40,7 +88,10 @@ impl Query {
                 return Err(BorrowMutError::new(c.id));
             }
         }
-        Ok(Self { components })
+        Ok(Self {
+            components,
+            filters,
+        })
     }
 
     /// Returns `true` if no part of this query requires mutable access
@@ -52,25 +103,55 @@ impl Query {
     pub fn components(&self) -> &[ComponentQuery] {
         self.components.as_ref()
     }
+
+    /// Get a reference to the query's filters.
+    pub fn filters(&self) -> &[ComponentFilter] {
+        self.filters.as_ref()
+    }
 }
 
 #[derive(Debug)]
 pub struct QueryResponse<'w, 'q> {
     world: &'w World,
+    // The entries of the components in the query, followed by the entries of filtered components
+    // which are not in the query.
     entries: Vec<ComponentEntryRef>,
-    query: &'q Query,
+    // The index into `entries` for every filter.
+    filter_entries: Vec<usize>,
+    query: Cow<'q, Query>,
+    // Filters look for changes made after `last_change_tick`.
+    last_change_tick: u32,
 }
 
 impl<'w, 'q> QueryResponse<'w, 'q> {
-    pub(crate) fn new(world: &'w World, query: &'q Query, entries: Vec<ComponentEntryRef>) -> Self {
-        debug_assert!(query.components().len() == entries.len());
+    pub(crate) fn new(
+        world: &'w World,
+        query: Cow<'q, Query>,
+        entries: Vec<ComponentEntryRef>,
+        filter_entries: Vec<usize>,
+    ) -> Self {
+        debug_assert!(query.components().len() <= entries.len());
+        debug_assert!(query.filters().len() == filter_entries.len());
         Self {
             world,
             entries,
+            filter_entries,
             query,
+            last_change_tick: world.change_tick() - 1,
         }
     }
 
+    /// Makes `Added` and `Changed` filters look for changes made after `tick`, instead of only
+    /// those made during the current change tick of the world.
+    pub fn since(mut self, tick: u32) -> Self {
+        self.last_change_tick = tick;
+        self
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
     /// Same as `try_get` but panics if `None` would be returned.
     /// # Safety
     /// See documentation for `try_get`
@@ -81,6 +162,7 @@ impl<'w, 'q> QueryResponse<'w, 'q> {
 
     /// Returns a slice of pointers to the components requsted if `entity` matches the query.
     /// Otherwise `None` is returned. The order of the components are the same as in the query.
+    /// Components in the query marked as mutable are marked as changed.
     ///
     /// # Safety
     /// All pointers returned are technically mutable **BUT** modifying the pointers to components
@@ -90,18 +172,52 @@ impl<'w, 'q> QueryResponse<'w, 'q> {
         self.world
             .entities()
             .id(entity)
-            .and_then(|index| self.try_get_by_index(index))
+            .and_then(|index| self.try_get_by_index(index, self.world.archetypes().location(index)))
     }
 
-    unsafe fn try_get_by_index(&mut self, index: u32) -> Option<Vec<*mut u8>> {
-        let mut res = Vec::with_capacity(self.entries.len());
+    /// `location` must be the location of the entity with id `index` in the world's archetypes.
+    unsafe fn try_get_by_index(
+        &self,
+        index: u32,
+        location: Option<Location>,
+    ) -> Option<Vec<*mut u8>> {
+        for (&i, filter) in self.filter_entries.iter().zip(self.query.filters()) {
+            let ticks = self.entries[i]
+                .get()
+                .storage
+                .get_entity_ticks(index as usize, location);
+            let matches = match filter {
+                ComponentFilter::With(_) => ticks.is_some(),
+                ComponentFilter::Without(_) => ticks.is_none(),
+                ComponentFilter::Added(_) => {
+                    ticks.is_some_and(|t| t.is_added_since(self.last_change_tick))
+                }
+                ComponentFilter::Changed(_) => {
+                    ticks.is_some_and(|t| t.is_changed_since(self.last_change_tick))
+                }
+            };
+            if !matches {
+                return None;
+            }
+        }
+
+        let mut res = Vec::with_capacity(self.query.components().len());
         for (e, cq) in self.entries.iter().zip(self.query.components().iter()) {
-            let ptr = e.get().storage.get_ptr(index as usize) as *mut u8;
+            let ptr = e.get().storage.get_entity_ptr(index as usize, location) as *mut u8;
             if ptr.is_null() && !cq.optional {
                 return None;
             }
             res.push(ptr);
         }
+
+        let change_tick = self.world.change_tick();
+        for (e, cq) in self.entries.iter().zip(self.query.components().iter()) {
+            if cq.mutable {
+                e.get()
+                    .storage
+                    .set_changed(index as usize, location, change_tick);
+            }
+        }
         Some(res)
     }
 
@@ -116,64 +232,230 @@ impl<'w, 'q> QueryResponse<'w, 'q> {
     pub unsafe fn iter_combinations<'a>(&'a mut self) -> IterCombinations<'a, 'w, 'q> {
         IterCombinations::new(self)
     }
+
+    /// Calls `f` with every entity matching the query and the pointers to its components. The
+    /// entities that might match are split into chunks of `chunk_size`, which are checked and
+    /// passed to `f` in parallel on the rayon thread pool. If `chunk_size` is `None` the entities
+    /// are split into a few chunks per thread.
+    ///
+    /// # Safety
+    /// See documentation for `try_get`. Every entity is only passed to `f` once, but `f` is called
+    /// from several threads at the same time.
+    pub unsafe fn par_for_each(
+        &mut self,
+        chunk_size: Option<usize>,
+        f: impl Fn(Entity, Vec<*mut u8>) + Send + Sync,
+    ) {
+        let candidates: Vec<_> = Candidates::new(self).collect();
+        let chunk_size = chunk_size
+            .unwrap_or_else(|| candidates.len() / (rayon::current_num_threads() * 4))
+            .max(1);
+        let res = &*self;
+        candidates.par_chunks(chunk_size).for_each(|chunk| {
+            for &(index, location) in chunk {
+                if let Some(comps) = res.try_get_by_index(index, location) {
+                    f(res.world.entities().with_id_unchecked(index), comps);
+                }
+            }
+        });
+    }
+}
+
+// Makes the world stay borrowed until the response is dropped, not only until it is last used, so
+// the world can not be changed while its components are borrowed. See the same for `Ref`.
+impl Drop for QueryResponse<'_, '_> {
+    fn drop(&mut self) {}
+}
+
+/// The entities that might match a query, as entity ids together with their locations in the
+/// world's archetypes. To visit as few entities as possible, the candidates are taken from the
+/// smallest storage out of the components the query requires.
+enum Candidates<'a> {
+    /// Every entity in the world.
+    Entities {
+        iter: EntityIter<'a>,
+        archetypes: &'a Archetypes,
+    },
+    /// Only the entities with a component in an indexed storage.
+    Indices {
+        iter: Indices<'a>,
+        archetypes: &'a Archetypes,
+    },
+    /// Only the entities in archetypes containing all archetype stored components the query
+    /// requires.
+    Archetypes {
+        archetypes: Vec<&'a Archetype>,
+        row: usize,
+    },
+}
+
+impl<'a> Candidates<'a> {
+    /// The candidates must not outlive `res`.
+    fn new<'w: 'a>(res: &QueryResponse<'w, '_>) -> Self {
+        let archetypes = res.world.archetypes();
+        let required_components = res
+            .entries
+            .iter()
+            .zip(res.query.components())
+            .filter(|(_, cq)| !cq.optional)
+            .map(|(e, cq)| (e, cq.id));
+        let required_by_filters = res
+            .filter_entries
+            .iter()
+            .zip(res.query.filters())
+            .filter(|(_, f)| f.requires_component())
+            .map(|(&i, f)| (&res.entries[i], f.id()));
+
+        let mut required_archetype_components = Vec::new();
+        let mut smallest: Option<&'a Storage> = None;
+        for (e, id) in required_components.chain(required_by_filters) {
+            // SAFETY: `e` is owned by `res`, which outlives the candidates.
+            let storage = unsafe { &e.get_unbounded().storage };
+            if storage.storage_type() == StorageType::Archetype {
+                required_archetype_components.push(id);
+            } else if smallest.is_none_or(|s| storage.len() < s.len()) {
+                smallest = Some(storage);
+            }
+        }
+
+        let matching: Option<Vec<_>> = (!required_archetype_components.is_empty()).then(|| {
+            archetypes
+                .matching(&required_archetype_components)
+                // Archetypes are popped from the back, so reverse to iterate them in order
+                .rev()
+                .collect()
+        });
+
+        match (smallest, matching) {
+            (Some(storage), Some(matching))
+                if matching.iter().map(|a| a.len()).sum::<usize>() < storage.len() =>
+            {
+                Self::Archetypes {
+                    archetypes: matching,
+                    row: 0,
+                }
+            }
+            (Some(storage), _) => Self::Indices {
+                iter: storage.indices(),
+                archetypes,
+            },
+            (None, Some(matching)) => Self::Archetypes {
+                archetypes: matching,
+                row: 0,
+            },
+            (None, None) => Self::Entities {
+                iter: res.world.entities().iter(),
+                archetypes,
+            },
+        }
+    }
+}
+
+impl<'a> Iterator for Candidates<'a> {
+    type Item = (u32, Option<Location>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Entities { iter, archetypes } => {
+                let index = iter.next()?.get_id_unchecked();
+                Some((index, archetypes.location(index)))
+            }
+            Self::Indices { iter, archetypes } => {
+                let index = iter.next()? as u32;
+                Some((index, archetypes.location(index)))
+            }
+            Self::Archetypes { archetypes, row } => loop {
+                let archetype = archetypes.last()?;
+                if let Some(&index) = archetype.entities().get(*row) {
+                    let location = Location {
+                        archetype: archetype.id(),
+                        row: *row,
+                    };
+                    *row += 1;
+                    return Some((index, Some(location)));
+                }
+                archetypes.pop();
+                *row = 0;
+            },
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Entities { .. } => (0, None),
+            Self::Indices { iter, .. } => iter.size_hint(),
+            Self::Archetypes { archetypes, row } => {
+                let len = archetypes.iter().map(|a| a.len()).sum::<usize>() - *row;
+                (len, Some(len))
+            }
+        }
+    }
 }
 
 pub struct Iter<'a, 'w, 'q> {
     res: &'a mut QueryResponse<'w, 'q>,
-    entity_iter: EntityIter<'w>,
+    candidates: Candidates<'a>,
 }
 
 impl<'a, 'w, 'q> Iter<'a, 'w, 'q> {
     pub fn new(res: &'a mut QueryResponse<'w, 'q>) -> Self {
-        let entity_iter = res.world.entities().iter();
-        Self { res, entity_iter }
+        let candidates = Candidates::new(res);
+        Self { res, candidates }
     }
 }
 
-// TODO: for sparse components this could be optimized
 impl<'a, 'r, 'q> Iterator for Iter<'a, 'r, 'q> {
     type Item = (Entity, Vec<*mut u8>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.entity_iter.next().and_then(|e| unsafe {
-            self.res
-                .try_get_by_index(self.entity_iter.entities().id(e).unwrap())
-                .map(|comps| (e, comps))
-        })
+        loop {
+            let (index, location) = self.candidates.next()?;
+            if let Some(comps) = unsafe { self.res.try_get_by_index(index, location) } {
+                let entity = self.res.world.entities().with_id_unchecked(index);
+                return Some((entity, comps));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.candidates.size_hint().1)
     }
 }
 
 pub struct IterCombinations<'a, 'w, 'q> {
-    res: &'a mut QueryResponse<'w, 'q>,
-    entity_iter: EntityIterCombinations<'w>,
+    _res: &'a mut QueryResponse<'w, 'q>,
+    matches: Vec<(Entity, Vec<*mut u8>)>,
+    curr_a: usize,
+    curr_b: usize,
 }
 
 impl<'a, 'w, 'q> IterCombinations<'a, 'w, 'q> {
     pub fn new(res: &'a mut QueryResponse<'w, 'q>) -> Self {
-        let entity_iter = res.world.entities().iter_combinations();
-        Self { res, entity_iter }
+        let matches = Iter::new(res).collect();
+        Self {
+            _res: res,
+            matches,
+            curr_a: 0,
+            curr_b: 1,
+        }
     }
 }
 
-// TODO: for sparse components this could be optimized
 impl<'a, 'r, 'q> Iterator for IterCombinations<'a, 'r, 'q> {
     type Item = ((Entity, Vec<*mut u8>), (Entity, Vec<*mut u8>));
 
     fn next(&mut self) -> Option<Self::Item> {
-        // SAFETY: we know that `EntityIterCombinations` won't return the same entity in both
-        // values of the tuple, so we know that the components are safe to access.
-        loop {
-            let (e1, e2) = self.entity_iter.next()?;
-            if let (Some(comps1), Some(comps2)) = unsafe {
-                (
-                    self.res
-                        .try_get_by_index(self.entity_iter.entities().id(e1).unwrap()),
-                    self.res
-                        .try_get_by_index(self.entity_iter.entities().id(e2).unwrap()),
-                )
-            } {
-                return Some(((e1, comps1), (e2, comps2)));
+        // SAFETY: every matching entity is only in `matches` once and `curr_a < curr_b`, so we
+        // know that the components in both values of the tuple are safe to access.
+        while self.curr_a < self.matches.len() {
+            if self.curr_b < self.matches.len() {
+                let b = self.curr_b;
+                self.curr_b += 1;
+                return Some((self.matches[self.curr_a].clone(), self.matches[b].clone()));
             }
+            self.curr_a += 1;
+            self.curr_b = self.curr_a + 1;
         }
+        None
     }
 }
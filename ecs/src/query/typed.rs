@@ -0,0 +1,480 @@
+use std::{borrow::Cow, marker::PhantomData, ptr::NonNull, slice};
+
+use crate::{
+    component::{ComponentId, ComponentRegistry},
+    BorrowMutError, Entity, World,
+};
+
+use super::{ComponentFilter, ComponentQuery, Iter, Query, QueryResponse};
+
+/// One component accessed by a `WorldQuery`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComponentAccess {
+    /// `None` if the type of component is not registered, in which case no entity can have it.
+    pub id: Option<ComponentId>,
+    pub mutable: bool,
+    pub optional: bool,
+}
+
+/// A type which can be used to query a `World` for components without any unsafe code, e.g.
+/// `(Entity, &mut Position, &Velocity, Option<&Mass>)`. See `World::query`.
+///
+/// # Safety
+/// `fetch` must consume exactly one pointer for every `ComponentAccess` added by `access`, in the
+/// same order, and must only write through pointers to components accessed mutably.
+pub unsafe trait WorldQuery {
+    type Item<'r>;
+
+    /// Adds the components this query accesses to `access`.
+    fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>);
+
+    /// Turns the pointers to the components of `entity` into the item of this query. Pointers to
+    /// optional components are null if `entity` does not have them.
+    ///
+    /// # Safety
+    /// The pointers must be valid for `'r` and follow the rules of the accesses from `access`.
+    unsafe fn fetch<'r>(entity: Entity, ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r>;
+}
+
+unsafe impl WorldQuery for Entity {
+    type Item<'r> = Entity;
+
+    fn access(_registry: &ComponentRegistry, _access: &mut Vec<ComponentAccess>) {}
+
+    unsafe fn fetch<'r>(entity: Entity, _ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r> {
+        entity
+    }
+}
+
+unsafe impl<T: 'static> WorldQuery for &T {
+    type Item<'r> = &'r T;
+
+    fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess {
+            id: registry.id::<T>(),
+            mutable: false,
+            optional: false,
+        });
+    }
+
+    unsafe fn fetch<'r>(_entity: Entity, ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r> {
+        &*(*ptrs.next().unwrap()).cast::<T>()
+    }
+}
+
+unsafe impl<T: 'static> WorldQuery for &mut T {
+    type Item<'r> = &'r mut T;
+
+    fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess {
+            id: registry.id::<T>(),
+            mutable: true,
+            optional: false,
+        });
+    }
+
+    unsafe fn fetch<'r>(_entity: Entity, ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r> {
+        &mut *(*ptrs.next().unwrap()).cast::<T>()
+    }
+}
+
+unsafe impl<T: 'static> WorldQuery for Option<&T> {
+    type Item<'r> = Option<&'r T>;
+
+    fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess {
+            id: registry.id::<T>(),
+            mutable: false,
+            optional: true,
+        });
+    }
+
+    unsafe fn fetch<'r>(_entity: Entity, ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r> {
+        NonNull::new((*ptrs.next().unwrap()).cast::<T>()).map(|ptr| &*ptr.as_ptr())
+    }
+}
+
+unsafe impl<T: 'static> WorldQuery for Option<&mut T> {
+    type Item<'r> = Option<&'r mut T>;
+
+    fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess {
+            id: registry.id::<T>(),
+            mutable: true,
+            optional: true,
+        });
+    }
+
+    unsafe fn fetch<'r>(_entity: Entity, ptrs: &mut slice::Iter<'_, *mut u8>) -> Self::Item<'r> {
+        NonNull::new((*ptrs.next().unwrap()).cast::<T>()).map(|ptr| &mut *ptr.as_ptr())
+    }
+}
+
+macro_rules! impl_world_query_for_tuple {
+    ($($name:ident),*) => {
+        unsafe impl<$($name: WorldQuery),*> WorldQuery for ($($name,)*) {
+            type Item<'r> = ($($name::Item<'r>,)*);
+
+            #[allow(unused_variables)]
+            fn access(registry: &ComponentRegistry, access: &mut Vec<ComponentAccess>) {
+                $($name::access(registry, access);)*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            unsafe fn fetch<'r>(
+                entity: Entity,
+                ptrs: &mut slice::Iter<'_, *mut u8>,
+            ) -> Self::Item<'r> {
+                ($($name::fetch(entity, ptrs),)*)
+            }
+        }
+    };
+}
+
+impl_world_query_for_tuple!();
+impl_world_query_for_tuple!(A);
+impl_world_query_for_tuple!(A, B);
+impl_world_query_for_tuple!(A, B, C);
+impl_world_query_for_tuple!(A, B, C, D);
+impl_world_query_for_tuple!(A, B, C, D, E);
+impl_world_query_for_tuple!(A, B, C, D, E, F);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G, H);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G, H, I);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// A `WorldQuery` which only reads components, so items of the same entity may be alive at the same
+/// time, e.g. `(Entity, &Position)`. See `TypedQuery::iter_combinations`.
+///
+/// # Safety
+/// `access` must only add immutable accesses.
+pub unsafe trait ReadOnlyWorldQuery: WorldQuery {}
+
+unsafe impl ReadOnlyWorldQuery for Entity {}
+unsafe impl<T: 'static> ReadOnlyWorldQuery for &T {}
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Option<&T> {}
+
+macro_rules! impl_read_only_world_query_for_tuple {
+    ($($name:ident),*) => {
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> ReadOnlyWorldQuery for ($($name,)*) {}
+    };
+}
+
+impl_read_only_world_query_for_tuple!();
+impl_read_only_world_query_for_tuple!(A);
+impl_read_only_world_query_for_tuple!(A, B);
+impl_read_only_world_query_for_tuple!(A, B, C);
+impl_read_only_world_query_for_tuple!(A, B, C, D);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G, H);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G, H, I);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_read_only_world_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// A type which can be used to filter the entities a `WorldQuery` matches, e.g.
+/// `(With<Player>, Without<Dead>)`. See `World::query_filtered`.
+pub trait WorldFilter {
+    /// Adds the filters to `filters`. Returns `false` if no entity can match the filters, e.g.
+    /// because a component which is required by a filter is not registered.
+    fn filters(registry: &ComponentRegistry, filters: &mut Vec<ComponentFilter>) -> bool;
+}
+
+/// Only matches entities with a `T`, without accessing it.
+pub struct With<T>(PhantomData<T>);
+
+/// Only matches entities without a `T`.
+pub struct Without<T>(PhantomData<T>);
+
+/// Only matches entities whose `T` was added during the current change tick, or after the tick
+/// given to `TypedQuery::since`. See `World::change_tick`.
+pub struct Added<T>(PhantomData<T>);
+
+/// Only matches entities whose `T` was added or accessed mutably during the current change tick,
+/// or after the tick given to `TypedQuery::since`. See `World::change_tick`.
+pub struct Changed<T>(PhantomData<T>);
+
+macro_rules! impl_world_filter_requiring_component {
+    ($($filter:ident),*) => {
+        $(
+            impl<T: 'static> WorldFilter for $filter<T> {
+                fn filters(
+                    registry: &ComponentRegistry,
+                    filters: &mut Vec<ComponentFilter>,
+                ) -> bool {
+                    match registry.id::<T>() {
+                        Some(id) => {
+                            filters.push(ComponentFilter::$filter(id));
+                            true
+                        }
+                        None => false,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_world_filter_requiring_component!(With, Added, Changed);
+
+impl<T: 'static> WorldFilter for Without<T> {
+    fn filters(registry: &ComponentRegistry, filters: &mut Vec<ComponentFilter>) -> bool {
+        // no entity can have a component which is not registered
+        if let Some(id) = registry.id::<T>() {
+            filters.push(ComponentFilter::Without(id));
+        }
+        true
+    }
+}
+
+macro_rules! impl_world_filter_for_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: WorldFilter),*> WorldFilter for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn filters(registry: &ComponentRegistry, filters: &mut Vec<ComponentFilter>) -> bool {
+                true $(& $name::filters(registry, filters))*
+            }
+        }
+    };
+}
+
+impl_world_filter_for_tuple!();
+impl_world_filter_for_tuple!(A);
+impl_world_filter_for_tuple!(A, B);
+impl_world_filter_for_tuple!(A, B, C);
+impl_world_filter_for_tuple!(A, B, C, D);
+impl_world_filter_for_tuple!(A, B, C, D, E);
+impl_world_filter_for_tuple!(A, B, C, D, E, F);
+impl_world_filter_for_tuple!(A, B, C, D, E, F, G);
+impl_world_filter_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// The response to a query made with `World::query` or `World::query_filtered`. Borrows the
+/// components it accesses until it is dropped.
+pub struct TypedQuery<'w, Q: WorldQuery, F: WorldFilter = ()> {
+    res: QueryResponse<'w, 'static>,
+    // `false` for every access whose component type is not registered
+    registered: Vec<bool>,
+    // `true` if a required component is not registered
+    matches_nothing: bool,
+    _marker: PhantomData<(Q, F)>,
+}
+
+impl<'w, Q: WorldQuery, F: WorldFilter> TypedQuery<'w, Q, F> {
+    pub(crate) fn new(world: &'w World) -> Result<Self, BorrowMutError> {
+        let mut access = Vec::new();
+        Q::access(world.component_registry(), &mut access);
+        let mut filters = Vec::new();
+        let filters_may_match = F::filters(world.component_registry(), &mut filters);
+
+        let query = Query::with_filters(
+            access
+                .iter()
+                .filter_map(|a| {
+                    a.id.map(|id| ComponentQuery {
+                        id,
+                        mutable: a.mutable,
+                        optional: a.optional,
+                    })
+                })
+                .collect(),
+            filters,
+        )?;
+        let res = world.borrow_query(Cow::Owned(query))?;
+
+        Ok(Self {
+            res,
+            registered: access.iter().map(|a| a.id.is_some()).collect(),
+            matches_nothing: !filters_may_match
+                || access.iter().any(|a| a.id.is_none() && !a.optional),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Makes `Added` and `Changed` filters look for changes made after `tick`, instead of only
+    /// those made during the current change tick of the world. Useful to find everything that
+    /// changed since the last time a system ran.
+    pub fn since(mut self, tick: u32) -> Self {
+        self.res = self.res.since(tick);
+        self
+    }
+
+    /// Returns the components of `entity` if it matches the query.
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if self.matches_nothing {
+            return None;
+        }
+        let ptrs = unsafe { self.res.try_get(entity)? };
+        Some(unsafe { fetch::<Q>(entity, ptrs, &self.registered) })
+    }
+
+    /// Iterates over all entities matching the query.
+    pub fn iter(&mut self) -> TypedIter<'_, 'w, Q> {
+        TypedIter {
+            inner: (!self.matches_nothing).then(|| unsafe { self.res.iter() }),
+            registered: &self.registered,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Calls `f` with the components of every pair of entities matching the query. If `f` is
+    /// called with the components of `(A, B)`, then it is not called with those of `(B, A)`.
+    /// Unlike with an iterator the components can not be kept after the call, so mutable access
+    /// to the components of an entity is never handed out twice.
+    pub fn for_each_combination(&mut self, mut f: impl FnMut(Q::Item<'_>, Q::Item<'_>)) {
+        if self.matches_nothing {
+            return;
+        }
+        let registered = &self.registered;
+        // SAFETY: the query response makes sure the components are borrowed correctly for as long
+        // as it is borrowed, the two entities of a pair are never the same and the components of
+        // a pair can not outlive the call to `f`.
+        unsafe {
+            for ((e1, ptrs1), (e2, ptrs2)) in self.res.iter_combinations() {
+                f(
+                    fetch::<Q>(e1, ptrs1, registered),
+                    fetch::<Q>(e2, ptrs2, registered),
+                );
+            }
+        }
+    }
+
+    /// Calls `f` with the components of every entity matching the query.
+    pub fn for_each(&mut self, f: impl FnMut(Q::Item<'_>)) {
+        self.iter().for_each(f)
+    }
+
+    /// Iterates over all entities matching the query in parallel, by splitting them into chunks
+    /// which are handled by different threads. Every entity is still only visited once, so
+    /// mutable access to its components is never shared.
+    pub fn par_iter(&mut self) -> TypedParIter<'_, 'w, Q, F> {
+        TypedParIter {
+            query: self,
+            chunk_size: None,
+        }
+    }
+
+    /// Calls `f` with the components of every entity matching the query, in parallel. See
+    /// `par_iter`.
+    pub fn par_for_each(&mut self, f: impl Fn(Q::Item<'_>) + Send + Sync) {
+        self.par_iter().for_each(f)
+    }
+}
+
+impl<'w, Q: ReadOnlyWorldQuery, F: WorldFilter> TypedQuery<'w, Q, F> {
+    /// Iterates over all pairs of entities matching the query. If `(A, B)` is yielded, then
+    /// `(B, A)` is not. Only for queries which do not access components mutably, since the items
+    /// of an entity are yielded many times. See `for_each_combination` for mutable access.
+    pub fn iter_combinations(&mut self) -> TypedIterCombinations<'_, 'w, Q> {
+        TypedIterCombinations {
+            inner: (!self.matches_nothing).then(|| unsafe { self.res.iter_combinations() }),
+            registered: &self.registered,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A parallel iterator over the entities matching a `TypedQuery`. See `TypedQuery::par_iter`.
+pub struct TypedParIter<'a, 'w, Q: WorldQuery, F: WorldFilter> {
+    query: &'a mut TypedQuery<'w, Q, F>,
+    chunk_size: Option<usize>,
+}
+
+impl<'a, 'w, Q: WorldQuery, F: WorldFilter> TypedParIter<'a, 'w, Q, F> {
+    /// Sets the number of entities handled by a thread at a time. By default the entities are
+    /// split into a few chunks per thread in the rayon thread pool.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Calls `f` with the components of every entity matching the query. `f` is called from
+    /// several threads at the same time.
+    pub fn for_each(self, f: impl Fn(Q::Item<'_>) + Send + Sync) {
+        if self.query.matches_nothing {
+            return;
+        }
+        let registered = &self.query.registered;
+        // SAFETY: the query response makes sure the components are borrowed correctly for as
+        // long as it is borrowed, every entity is only passed on once and components are
+        // `Send + Sync`.
+        unsafe {
+            self.query
+                .res
+                .par_for_each(self.chunk_size, |entity, ptrs| {
+                    f(fetch::<Q>(entity, ptrs, registered))
+                });
+        }
+    }
+}
+
+/// Inserts null pointers for the accesses that are not registered, since those are left out of
+/// the `Query`, and fetches the item.
+unsafe fn fetch<'r, Q: WorldQuery>(
+    entity: Entity,
+    mut ptrs: Vec<*mut u8>,
+    registered: &[bool],
+) -> Q::Item<'r> {
+    if ptrs.len() != registered.len() {
+        let mut found = ptrs.into_iter();
+        ptrs = registered
+            .iter()
+            .map(|&r| {
+                if r {
+                    found.next().unwrap()
+                } else {
+                    std::ptr::null_mut()
+                }
+            })
+            .collect();
+    }
+    Q::fetch(entity, &mut ptrs.iter())
+}
+
+pub struct TypedIter<'a, 'w, Q: WorldQuery> {
+    inner: Option<Iter<'a, 'w, 'static>>,
+    registered: &'a [bool],
+    _marker: PhantomData<Q>,
+}
+
+impl<'a, 'w, Q: WorldQuery> Iterator for TypedIter<'a, 'w, Q> {
+    type Item = Q::Item<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entity, ptrs) = self.inner.as_mut()?.next()?;
+        // SAFETY: the query response makes sure the components are borrowed correctly for as
+        // long as it is borrowed, and every entity is only yielded once.
+        Some(unsafe { fetch::<Q>(entity, ptrs, self.registered) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner
+            .as_ref()
+            .map_or((0, Some(0)), |inner| inner.size_hint())
+    }
+}
+
+pub struct TypedIterCombinations<'a, 'w, Q: ReadOnlyWorldQuery> {
+    inner: Option<super::IterCombinations<'a, 'w, 'static>>,
+    registered: &'a [bool],
+    _marker: PhantomData<Q>,
+}
+
+impl<'a, 'w, Q: ReadOnlyWorldQuery> Iterator for TypedIterCombinations<'a, 'w, Q> {
+    type Item = (Q::Item<'a>, Q::Item<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ((e1, ptrs1), (e2, ptrs2)) = self.inner.as_mut()?.next()?;
+        // SAFETY: the query response makes sure the components are borrowed correctly for as long
+        // as it is borrowed, and the components are only read
+        Some(unsafe {
+            (
+                fetch::<Q>(e1, ptrs1, self.registered),
+                fetch::<Q>(e2, ptrs2, self.registered),
+            )
+        })
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    any::{self, Any, TypeId},
+    fmt,
+};
+
+use crate::{name::Name, Entity, ReflectError};
+
+/// Describes one field of a reflected type. See `TypeInfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    /// The offset of the field in bytes from the start of the value, or `None` for the variants
+    /// of enums.
+    pub offset: Option<usize>,
+}
+
+impl FieldInfo {
+    /// Describes the field `name` of `S` at `offset`, with the type returned by `_get`. Used by
+    /// `impl_reflect!` to find the types of the fields.
+    pub fn new<S, F: Reflect>(name: &'static str, offset: usize, _get: fn(&S) -> &F) -> Self {
+        Self {
+            name,
+            type_name: any::type_name::<F>(),
+            type_id: TypeId::of::<F>(),
+            offset: Some(offset),
+        }
+    }
+
+    /// Describes the variant `name` of the enum `S`, holding the type returned by `_get`.
+    pub fn variant<S, F: Reflect>(name: &'static str, _get: fn(&S) -> Option<&F>) -> Self {
+        Self {
+            name,
+            type_name: any::type_name::<F>(),
+            type_id: TypeId::of::<F>(),
+            offset: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeKind {
+    /// A value without fields, e.g. a number.
+    Value,
+    /// A struct with named or numbered fields.
+    Struct(Vec<FieldInfo>),
+    /// An enum where every variant holds one value. The variants are reflected as fields, of
+    /// which only the current variant can be accessed.
+    Enum(Vec<FieldInfo>),
+}
+
+/// Describes a reflected type, see `Reflect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeInfo {
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    pub kind: TypeKind,
+}
+
+impl TypeInfo {
+    pub fn of<T: 'static>(kind: TypeKind) -> Self {
+        Self {
+            type_name: any::type_name::<T>(),
+            type_id: TypeId::of::<T>(),
+            kind,
+        }
+    }
+
+    pub fn fields(&self) -> &[FieldInfo] {
+        match &self.kind {
+            TypeKind::Value => &[],
+            TypeKind::Struct(fields) | TypeKind::Enum(fields) => fields,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields().iter().find(|f| f.name == name)
+    }
+}
+
+/// A type whose `TypeInfo` is known without a value of it.
+pub trait Typed {
+    fn info() -> TypeInfo;
+}
+
+/// A value whose fields can be inspected and modified at runtime by name, e.g. by an editor that
+/// does not know about the type statically. Implemented with `impl_reflect!` and
+/// `impl_reflect_value!`. Fields of fields can be accessed by paths like `"position.x"`, see
+/// `get_field` and `set_field`.
+pub trait Reflect: Any + Send + Sync {
+    fn type_info(&self) -> TypeInfo;
+
+    /// Returns the field called `name`, which for enums is only the current variant.
+    fn field(&self, name: &str) -> Option<&dyn Reflect>;
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect>;
+
+    /// The name of the current variant for enums.
+    fn variant(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl dyn Reflect {
+    /// Returns the field at `path`, where the names of fields of fields are separated by `.`.
+    /// The empty path is the value itself.
+    pub fn get_field(&self, path: &str) -> Option<&dyn Reflect> {
+        split_path(path).try_fold(self, |value, name| value.field(name))
+    }
+
+    pub fn get_field_mut(&mut self, path: &str) -> Option<&mut dyn Reflect> {
+        split_path(path).try_fold(self, |value, name| value.field_mut(name))
+    }
+
+    /// Sets the field at `path` to `value`, see `get_field`.
+    pub fn set_field<T: Reflect>(&mut self, path: &str, value: T) -> Result<(), ReflectError> {
+        let field = self
+            .get_field_mut(path)
+            .ok_or_else(|| ReflectError::NoField(path.to_string()))?;
+        let found = field.type_info().type_name;
+        let field = field
+            .downcast_mut::<T>()
+            .ok_or_else(|| ReflectError::WrongType {
+                path: path.to_string(),
+                expected: found,
+                found: any::type_name::<T>(),
+            })?;
+        *field = value;
+        Ok(())
+    }
+
+    pub fn is<T: 'static>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+}
+
+impl fmt::Debug for dyn Reflect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.type_info();
+        match info.kind {
+            TypeKind::Value => f.write_str(info.type_name),
+            TypeKind::Struct(fields) => {
+                let mut s = f.debug_struct(info.type_name);
+                for field in fields {
+                    s.field(field.name, &self.field(field.name).unwrap());
+                }
+                s.finish()
+            }
+            TypeKind::Enum(_) => {
+                let mut t = f.debug_tuple(self.variant().unwrap_or(info.type_name));
+                if let Some(value) = self.variant().and_then(|v| self.field(v)) {
+                    t.field(&value);
+                }
+                t.finish()
+            }
+        }
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('.').filter(|name| !name.is_empty())
+}
+
+/// The reflection of a kind of component, registered with
+/// `ComponentRegistry::register_reflect`. Turns pointers to components into `&dyn Reflect`.
+#[derive(Clone)]
+pub struct ReflectComponent {
+    type_info: TypeInfo,
+    from_ptr: fn(*mut u8) -> *mut dyn Reflect,
+}
+
+impl ReflectComponent {
+    pub fn new<T: Reflect + Typed>() -> Self {
+        Self {
+            type_info: T::info(),
+            from_ptr: |ptr| ptr.cast::<T>(),
+        }
+    }
+
+    pub fn type_info(&self) -> &TypeInfo {
+        &self.type_info
+    }
+
+    /// # Safety
+    /// `ptr` must point to a valid component of this kind, which is not written to for `'a`.
+    pub unsafe fn as_reflect<'a>(&self, ptr: *const u8) -> &'a dyn Reflect {
+        &*(self.from_ptr)(ptr as *mut u8)
+    }
+
+    /// # Safety
+    /// `ptr` must point to a valid component of this kind, which is not accessed in any other way
+    /// for `'a`.
+    pub unsafe fn as_reflect_mut<'a>(&self, ptr: *mut u8) -> &'a mut dyn Reflect {
+        &mut *(self.from_ptr)(ptr)
+    }
+}
+
+impl fmt::Debug for ReflectComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReflectComponent")
+            .field("type_info", &self.type_info)
+            .finish()
+    }
+}
+
+impl PartialEq for ReflectComponent {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_info == other.type_info
+    }
+}
+
+impl Eq for ReflectComponent {}
+
+/// Implements `Reflect` and `Typed` for a struct, listing the fields which are reflected, or for
+/// an enum where every variant holds one value, listing the variants.
+/// # Examples
+/// ```
+/// # use ecs::{impl_reflect, reflect::Reflect};
+/// struct Health {
+///     current: f32,
+///     max: f32,
+/// }
+/// impl_reflect!(Health { current, max });
+///
+/// enum Shape {
+///     Circle(f32),
+///     Square(f32),
+/// }
+/// impl_reflect!(enum Shape { Circle, Square });
+///
+/// let mut health = Health { current: 1.0, max: 1.0 };
+/// let reflected: &mut dyn Reflect = &mut health;
+/// reflected.set_field("current", 0.5f32).unwrap();
+/// assert_eq!(health.current, 0.5);
+///
+/// let shape: &dyn Reflect = &Shape::Square(2.0);
+/// assert_eq!(shape.variant(), Some("Square"));
+/// assert_eq!(shape.get_field("Square").unwrap().downcast_ref::<f32>(), Some(&2.0));
+/// assert!(shape.get_field("Circle").is_none());
+/// ```
+#[macro_export]
+macro_rules! impl_reflect {
+    (enum $ty:ty { $($variant:ident),* $(,)? }) => {
+        impl $crate::reflect::Typed for $ty {
+            fn info() -> $crate::reflect::TypeInfo {
+                $crate::reflect::TypeInfo::of::<$ty>($crate::reflect::TypeKind::Enum(vec![$(
+                    $crate::reflect::FieldInfo::variant(stringify!($variant), |v: &$ty| match v {
+                        Self::$variant(value) => Some(value),
+                        #[allow(unreachable_patterns)]
+                        _ => None,
+                    }),
+                )*]))
+            }
+        }
+
+        impl $crate::reflect::Reflect for $ty {
+            fn type_info(&self) -> $crate::reflect::TypeInfo {
+                <$ty as $crate::reflect::Typed>::info()
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn $crate::reflect::Reflect> {
+                match self {
+                    $(Self::$variant(value) if name == stringify!($variant) => Some(value),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn $crate::reflect::Reflect> {
+                match self {
+                    $(Self::$variant(value) if name == stringify!($variant) => Some(value),)*
+                    _ => None,
+                }
+            }
+
+            fn variant(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant(_) => Some(stringify!($variant)),)*
+                }
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+        }
+    };
+    ($ty:ty { $($field:tt),* $(,)? }) => {
+        impl $crate::reflect::Typed for $ty {
+            fn info() -> $crate::reflect::TypeInfo {
+                $crate::reflect::TypeInfo::of::<$ty>($crate::reflect::TypeKind::Struct(vec![$(
+                    $crate::reflect::FieldInfo::new(
+                        stringify!($field),
+                        std::mem::offset_of!($ty, $field),
+                        |v: &$ty| &v.$field,
+                    ),
+                )*]))
+            }
+        }
+
+        impl $crate::reflect::Reflect for $ty {
+            fn type_info(&self) -> $crate::reflect::TypeInfo {
+                <$ty as $crate::reflect::Typed>::info()
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn $crate::reflect::Reflect> {
+                match name {
+                    $(stringify!($field) => Some(&self.$field),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn $crate::reflect::Reflect> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+        }
+    };
+}
+
+/// Implements `Reflect` and `Typed` for types without reflected fields, e.g. numbers.
+#[macro_export]
+macro_rules! impl_reflect_value {
+    ($($ty:ty),* $(,)?) => {$(
+        impl $crate::reflect::Typed for $ty {
+            fn info() -> $crate::reflect::TypeInfo {
+                $crate::reflect::TypeInfo::of::<$ty>($crate::reflect::TypeKind::Value)
+            }
+        }
+
+        impl $crate::reflect::Reflect for $ty {
+            fn type_info(&self) -> $crate::reflect::TypeInfo {
+                <$ty as $crate::reflect::Typed>::info()
+            }
+
+            fn field(&self, _: &str) -> Option<&dyn $crate::reflect::Reflect> {
+                None
+            }
+
+            fn field_mut(&mut self, _: &str) -> Option<&mut dyn $crate::reflect::Reflect> {
+                None
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+        }
+    )*};
+}
+
+impl_reflect_value!(
+    bool, char, f32, f64, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, String, Entity, Name,
+);
+
+// The vectors and quaternions of vek can not be reflected outside of this crate
+#[cfg(feature = "vek")]
+mod vek_impls {
+    use vek::{
+        quaternion::repr_c::Quaternion,
+        vec::repr_c::{Vec2, Vec3, Vec4},
+    };
+
+    impl_reflect!(Vec2<f32> { x, y });
+    impl_reflect!(Vec3<f32> { x, y, z });
+    impl_reflect!(Vec4<f32> { x, y, z, w });
+    impl_reflect!(Quaternion<f32> { x, y, z, w });
+}
@@ -0,0 +1,98 @@
+use std::{
+    any::TypeId,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use crate::Entity;
+
+/// The relations between entities, kept by the `World`. A relation of kind `R` goes from a
+/// source entity to a target entity, where `R` is any type used only to tell kinds of relations
+/// apart. Relations are stored in both directions so the sources of a target can be looked up as
+/// quickly as the targets of a source. See `World::relate`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Relations {
+    kinds: HashMap<TypeId, RelationStorage>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct RelationStorage {
+    targets: HashMap<Entity, Vec<Entity>>,
+    sources: HashMap<Entity, Vec<Entity>>,
+}
+
+impl Relations {
+    /// Returns `false` if `source` already was related to `target`.
+    pub(crate) fn insert(&mut self, kind: TypeId, source: Entity, target: Entity) -> bool {
+        let storage = self.kinds.entry(kind).or_default();
+        let targets = storage.targets.entry(source).or_default();
+        if targets.contains(&target) {
+            return false;
+        }
+        targets.push(target);
+        storage.sources.entry(target).or_default().push(source);
+        true
+    }
+
+    /// Returns `false` if `source` was not related to `target`.
+    pub(crate) fn remove(&mut self, kind: TypeId, source: Entity, target: Entity) -> bool {
+        match self.kinds.get_mut(&kind) {
+            Some(storage) => {
+                remove_from(&mut storage.targets, source, target)
+                    && remove_from(&mut storage.sources, target, source)
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn targets(&self, kind: TypeId, source: Entity) -> &[Entity] {
+        self.kinds
+            .get(&kind)
+            .and_then(|s| s.targets.get(&source))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub(crate) fn sources(&self, kind: TypeId, target: Entity) -> &[Entity] {
+        self.kinds
+            .get(&kind)
+            .and_then(|s| s.sources.get(&target))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Removes every relation, returning their kinds, sources and targets.
+    pub(crate) fn drain(&mut self) -> Vec<(TypeId, Entity, Entity)> {
+        let mut relations = Vec::new();
+        for (kind, storage) in self.kinds.drain() {
+            for (source, targets) in storage.targets {
+                relations.extend(targets.into_iter().map(|target| (kind, source, target)));
+            }
+        }
+        relations
+    }
+
+    /// Removes every relation of every kind from or to `entity`, e.g. since it was despawned.
+    pub(crate) fn remove_entity(&mut self, entity: Entity) {
+        for storage in self.kinds.values_mut() {
+            for target in storage.targets.remove(&entity).into_iter().flatten() {
+                remove_from(&mut storage.sources, target, entity);
+            }
+            for source in storage.sources.remove(&entity).into_iter().flatten() {
+                remove_from(&mut storage.targets, source, entity);
+            }
+        }
+    }
+}
+
+/// Removes `value` from the entities related to `key`, keeping the order of the rest.
+fn remove_from(map: &mut HashMap<Entity, Vec<Entity>>, key: Entity, value: Entity) -> bool {
+    let Entry::Occupied(mut entry) = map.entry(key) else {
+        return false;
+    };
+    let Some(index) = entry.get().iter().position(|&e| e == value) else {
+        return false;
+    };
+    entry.get_mut().remove(index);
+    if entry.get().is_empty() {
+        entry.remove();
+    }
+    true
+}
@@ -0,0 +1,89 @@
+use std::{collections::HashMap, iter, marker::PhantomData, slice, vec};
+
+use crate::{component::ComponentId, Entity};
+
+/// A log of the components removed from entities and the entities despawned, kept by the `World`
+/// until it is cleared. See `World::clear_removals`.
+#[derive(Debug, Default)]
+pub(crate) struct Removals {
+    components: HashMap<ComponentId, Vec<Entity>>,
+    despawned: Vec<Entity>,
+}
+
+impl Removals {
+    pub(crate) fn component_removed(&mut self, component_id: ComponentId, entity: Entity) {
+        self.components
+            .entry(component_id)
+            .or_default()
+            .push(entity);
+    }
+
+    pub(crate) fn entity_despawned(&mut self, entity: Entity) {
+        self.despawned.push(entity);
+    }
+
+    pub(crate) fn removed(&self, component_id: ComponentId) -> &[Entity] {
+        self.components
+            .get(&component_id)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub(crate) fn drain_removed(&mut self, component_id: ComponentId) -> vec::Drain<'_, Entity> {
+        self.components.entry(component_id).or_default().drain(..)
+    }
+
+    pub(crate) fn despawned(&self) -> &[Entity] {
+        &self.despawned
+    }
+
+    pub(crate) fn drain_despawned(&mut self) -> vec::Drain<'_, Entity> {
+        self.despawned.drain(..)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.components.values_mut().for_each(Vec::clear);
+        self.despawned.clear();
+    }
+}
+
+/// The entities which have had their `T` removed since the removals were last cleared, either by
+/// `World::remove` or by being despawned. The same entity may occur more than once if it was given
+/// a new `T` which was removed again. See `World::removed`.
+pub struct RemovedComponents<'w, T> {
+    entities: &'w [Entity],
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'w, T> RemovedComponents<'w, T> {
+    pub(crate) fn new(entities: &'w [Entity]) -> Self {
+        Self {
+            entities,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&self) -> iter::Copied<slice::Iter<'w, Entity>> {
+        self.entities.iter().copied()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<'w, T> IntoIterator for RemovedComponents<'w, T> {
+    type Item = Entity;
+    type IntoIter = iter::Copied<slice::Iter<'w, Entity>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
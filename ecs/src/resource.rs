@@ -0,0 +1,428 @@
+use std::{
+    alloc::{self, Layout},
+    any::{self, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    thread::{self, ThreadId},
+};
+
+use crate::component::BorrowStatus;
+
+/// Identifies a kind of resource in `Resources`. Looking a resource up by its id is cheaper than
+/// by its type, see `World::resource_by_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceId(u32);
+
+/// Basic metadata about a kind of resource.
+#[derive(Debug)]
+pub struct ResourceInfo {
+    name: Cow<'static, str>,
+    type_id: Option<TypeId>,
+    id: ResourceId,
+    layout: Layout,
+    // The thread non-send resources belong to
+    thread: Option<ThreadId>,
+}
+
+impl ResourceInfo {
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// The type of the resource, or `None` if it was registered with `Resources::register_raw`.
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.type_id
+    }
+
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns `false` for non-send resources, which can only be accessed from the thread that
+    /// registered them.
+    pub fn is_send(&self) -> bool {
+        self.thread.is_none()
+    }
+}
+
+/// A kind of resource together with the resource itself, if it currently exists. Every kind of
+/// resource has its own allocation, which is kept when the resource is removed.
+struct ResourceEntry {
+    info: ResourceInfo,
+    ptr: NonNull<u8>,
+    drop: unsafe fn(*mut u8),
+    present: bool,
+    borrowed: BorrowStatus,
+}
+
+impl ResourceEntry {
+    /// Panics if the resource is non-send and this is not its thread.
+    fn check_thread(&self) {
+        if let Some(thread) = self.info.thread {
+            assert_eq!(
+                thread,
+                thread::current().id(),
+                "Tried to access the non-send resource {} from another thread",
+                self.info.name
+            );
+        }
+    }
+}
+
+impl Drop for ResourceEntry {
+    fn drop(&mut self) {
+        if self.present {
+            if self
+                .info
+                .thread
+                .is_some_and(|t| t != thread::current().id())
+            {
+                // Dropping it here would be unsound, so it is leaked instead
+                if !thread::panicking() {
+                    panic!(
+                        "Tried to drop the non-send resource {} on another thread",
+                        self.info.name
+                    );
+                }
+            } else {
+                unsafe { (self.drop)(self.ptr.as_ptr()) };
+            }
+        }
+        if self.info.layout.size() != 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.info.layout) };
+        }
+    }
+}
+
+/// The resources of a `World`. *Resources* are like components, but associated with the world
+/// instead of an entity. They are stored apart from the components with their own borrow
+/// tracking, so they can be borrowed with `Res` and `ResMut` while components are being queried.
+///
+/// Resources which are not `Send` can be added with `World::add_non_send_resource`. They can only
+/// be accessed and dropped on the thread they were added from, which is checked at runtime.
+///
+/// Resources which are not `'static` have no safe API, since nothing would tie them to the
+/// lifetime of the world. They can only be registered with the unsafe `register_raw` and accessed
+/// through pointers.
+#[derive(Default)]
+pub struct Resources {
+    // Indexed by ResourceId's
+    entries: Vec<ResourceEntry>,
+
+    rust_types: HashMap<TypeId, ResourceId>,
+}
+
+// SAFETY: send resources are `Send + Sync`, and non-send resources are only accessed and dropped on
+// their own thread. Borrows are tracked atomically.
+unsafe impl Send for Resources {}
+unsafe impl Sync for Resources {}
+
+impl Resources {
+    /// Registers a rust type as a kind of resource, or returns its id if it already is registered.
+    pub fn register<T>(&mut self) -> ResourceId
+    where
+        T: Send + Sync + 'static,
+    {
+        self.register_typed::<T>(None)
+    }
+
+    /// Same as `register` for resources which are not `Send` or `Sync`. They can only be accessed
+    /// from the current thread.
+    pub fn register_non_send<T: 'static>(&mut self) -> ResourceId {
+        self.register_typed::<T>(Some(thread::current().id()))
+    }
+
+    fn register_typed<T: 'static>(&mut self, thread: Option<ThreadId>) -> ResourceId {
+        unsafe fn drop_ptr<T>(ptr: *mut u8) {
+            ptr.cast::<T>().drop_in_place();
+        }
+
+        if let Some(id) = self.id::<T>() {
+            return id;
+        }
+        let id = unsafe {
+            self.register_raw_with_thread(
+                Cow::Borrowed(any::type_name::<T>()),
+                Layout::new::<T>(),
+                drop_ptr::<T>,
+                thread,
+            )
+        };
+        self.entries[id.0 as usize].info.type_id = Some(TypeId::of::<T>());
+        self.rust_types.insert(TypeId::of::<T>(), id);
+        id
+    }
+
+    /// Registers a kind of resource without a rust type, e.g. one which is not `'static`. Its
+    /// resources can only be accessed through pointers, see `insert_ptr` and `borrow`. Every
+    /// call registers a new kind of resource.
+    /// # Safety
+    /// `drop` must be a valid drop function for values with `layout`. Unless `send` is `false`,
+    /// the values must be `Send + Sync`.
+    pub unsafe fn register_raw(
+        &mut self,
+        name: Cow<'static, str>,
+        layout: Layout,
+        drop: unsafe fn(*mut u8),
+        send: bool,
+    ) -> ResourceId {
+        let thread = (!send).then(|| thread::current().id());
+        self.register_raw_with_thread(name, layout, drop, thread)
+    }
+
+    unsafe fn register_raw_with_thread(
+        &mut self,
+        name: Cow<'static, str>,
+        layout: Layout,
+        drop: unsafe fn(*mut u8),
+        thread: Option<ThreadId>,
+    ) -> ResourceId {
+        let id = ResourceId(self.entries.len().try_into().unwrap());
+        let ptr = if layout.size() == 0 {
+            // A well aligned dangling pointer
+            NonNull::new(layout.align() as *mut u8).unwrap()
+        } else {
+            NonNull::new(alloc::alloc(layout)).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        self.entries.push(ResourceEntry {
+            info: ResourceInfo {
+                name,
+                type_id: None,
+                id,
+                layout,
+                thread,
+            },
+            ptr,
+            drop,
+            present: false,
+            borrowed: BorrowStatus::default(),
+        });
+        id
+    }
+
+    pub fn id<T: 'static>(&self) -> Option<ResourceId> {
+        self.rust_types.get(&TypeId::of::<T>()).copied()
+    }
+
+    pub fn info(&self, id: ResourceId) -> &ResourceInfo {
+        &self.entries[id.0 as usize].info
+    }
+
+    /// The metadata of every registered kind of resource, in the order of their ids.
+    pub fn infos(&self) -> impl Iterator<Item = &ResourceInfo> {
+        self.entries.iter().map(|e| &e.info)
+    }
+
+    /// Returns `true` if the resource with id `id` currently exists.
+    pub fn contains(&self, id: ResourceId) -> bool {
+        self.entries[id.0 as usize].present
+    }
+
+    /// Moves the value pointed to by `ptr` into the resource with id `id`, dropping the previous
+    /// resource if there was one. Panics if the resource currently is borrowed, or if it is
+    /// non-send and this is not its thread.
+    /// # Safety
+    /// `ptr` must point to a valid value of the kind of resource, which must not be used after
+    /// this call.
+    pub unsafe fn insert_ptr(&mut self, id: ResourceId, ptr: *mut u8) {
+        let entry = self.entry_mut(id);
+        if entry.present {
+            (entry.drop)(entry.ptr.as_ptr());
+        }
+        entry
+            .ptr
+            .as_ptr()
+            .copy_from_nonoverlapping(ptr, entry.info.layout.size());
+        entry.present = true;
+    }
+
+    /// Moves the resource with id `id` to `dst`, removing it. Returns `false` if it did not exist.
+    /// Panics the same way as `insert_ptr`.
+    /// # Safety
+    /// `dst` must be valid for writes of the kind of resource.
+    pub unsafe fn remove_into(&mut self, id: ResourceId, dst: *mut u8) -> bool {
+        let entry = self.entry_mut(id);
+        if !entry.present {
+            return false;
+        }
+        dst.copy_from_nonoverlapping(entry.ptr.as_ptr(), entry.info.layout.size());
+        entry.present = false;
+        true
+    }
+
+    /// Moves `value` into the resource with id `id`, see `insert_ptr`.
+    /// # Safety
+    /// The resource must be of type `T`.
+    pub(crate) unsafe fn insert<T>(&mut self, id: ResourceId, value: T) {
+        let mut value = ManuallyDrop::new(value);
+        self.insert_ptr(id, (&mut *value as *mut T).cast());
+    }
+
+    /// Takes the resource with id `id` out, see `remove_into`.
+    /// # Safety
+    /// The resource must be of type `T`.
+    pub(crate) unsafe fn remove<T>(&mut self, id: ResourceId) -> Option<T> {
+        let mut value = MaybeUninit::<T>::uninit();
+        self.remove_into(id, value.as_mut_ptr().cast())
+            .then(|| value.assume_init())
+    }
+
+    /// Returns a pointer to the resource with id `id`, or `None` if it does not exist. Panics if
+    /// the resource currently is borrowed, or if it is non-send and this is not its thread. Use
+    /// `borrow` for shared access.
+    pub fn get_mut_ptr(&mut self, id: ResourceId) -> Option<NonNull<u8>> {
+        let entry = self.entry_mut(id);
+        entry.present.then_some(entry.ptr)
+    }
+
+    /// Borrows the resource with id `id` until the returned borrow is dropped, or returns `None`
+    /// if it does not exist. Panics if the resource already is borrowed in a way incompatible with
+    /// the requested borrow, or if it is non-send and this is not its thread.
+    pub fn borrow(&self, id: ResourceId, mutable: bool) -> Option<ResourceBorrow<'_>> {
+        let entry = &self.entries[id.0 as usize];
+        entry.check_thread();
+        if !entry.present {
+            return None;
+        }
+        if entry.borrowed.add_borrow(mutable).is_err() {
+            panic!(
+                "Tried to borrow the resource {} more than once and at least once mutably",
+                entry.info.name
+            );
+        }
+        Some(ResourceBorrow { entry, mutable })
+    }
+
+    fn entry_mut(&mut self, id: ResourceId) -> &mut ResourceEntry {
+        let entry = &mut self.entries[id.0 as usize];
+        entry.check_thread();
+        assert!(
+            entry.borrowed.is_free(),
+            "Tried to access the resource {} while it is borrowed",
+            entry.info.name
+        );
+        entry
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|e| (&e.info.name, e.present)))
+            .finish()
+    }
+}
+
+/// A borrow of a resource, which is released when this is dropped. See `Resources::borrow`.
+pub struct ResourceBorrow<'r> {
+    entry: &'r ResourceEntry,
+    mutable: bool,
+}
+
+impl<'r> ResourceBorrow<'r> {
+    pub fn id(&self) -> ResourceId {
+        self.entry.info.id
+    }
+
+    pub fn ptr(&self) -> NonNull<u8> {
+        self.entry.ptr
+    }
+
+    pub fn mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+impl Drop for ResourceBorrow<'_> {
+    fn drop(&mut self) {
+        self.entry.borrowed.remove_borrow(self.mutable);
+    }
+}
+
+/// Shared access to a resource which can be held while other resources and components are
+/// borrowed, e.g. in a system. See `World::res`.
+pub struct Res<'w, T> {
+    borrow: ResourceBorrow<'w>,
+    _marker: PhantomData<&'w T>,
+}
+
+impl<'w, T: 'static> Res<'w, T> {
+    /// # Safety
+    /// The borrow must be of a resource of type `T`.
+    pub(crate) unsafe fn new(borrow: ResourceBorrow<'w>) -> Self {
+        Self {
+            borrow,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> ResourceId {
+        self.borrow.id()
+    }
+}
+
+impl<T> Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.borrow.ptr().cast().as_ref() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Res<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+/// Exclusive access to a resource which can be held while other resources and components are
+/// borrowed, e.g. in a system. See `World::res_mut`.
+pub struct ResMut<'w, T> {
+    borrow: ResourceBorrow<'w>,
+    _marker: PhantomData<&'w mut T>,
+}
+
+impl<'w, T: 'static> ResMut<'w, T> {
+    /// # Safety
+    /// The borrow must be a mutable borrow of a resource of type `T`.
+    pub(crate) unsafe fn new(borrow: ResourceBorrow<'w>) -> Self {
+        debug_assert!(borrow.mutable());
+        Self {
+            borrow,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> ResourceId {
+        self.borrow.id()
+    }
+}
+
+impl<T> Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.borrow.ptr().cast().as_ref() }
+    }
+}
+
+impl<T> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.borrow.ptr().cast().as_mut() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ResMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
@@ -0,0 +1,600 @@
+use std::{
+    any::{self, Any, TypeId},
+    collections::{hash_map, HashMap, HashSet},
+    fmt,
+};
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    hierarchy::{Children, Parent},
+    name::Name,
+    Entity, SceneError, World,
+};
+
+type Value = Box<dyn Any + Send + Sync>;
+
+/// Maps the entities of a `Scene` to the entities spawned for them in a `World`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityMap(HashMap<Entity, Entity>);
+
+impl EntityMap {
+    pub fn insert(&mut self, from: Entity, to: Entity) -> Option<Entity> {
+        self.0.insert(from, to)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<Entity> {
+        self.0.get(&entity).copied()
+    }
+
+    /// Returns the entity `entity` is mapped to, or `entity` itself if it is not mapped, e.g.
+    /// since it was not part of the scene.
+    pub fn map(&self, entity: Entity) -> Entity {
+        self.get(entity).unwrap_or(entity)
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, Entity, Entity> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A component or resource which refers to other entities. The references have to be updated
+/// when a `Scene` is spawned, since the entities get spawned as new entities. See
+/// `SceneRegistry::register_mapped`.
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0 = map.map(self.0);
+    }
+}
+
+impl MapEntities for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for entity in &mut self.0 {
+            *entity = map.map(*entity);
+        }
+    }
+}
+
+/// A kind of component or resource registered in a `SceneRegistry`, with the type erased
+/// functions needed to move it between a `World` and a `Scene`.
+#[derive(Clone, Copy)]
+struct SceneType {
+    name: &'static str,
+    type_id: TypeId,
+    get: fn(&World, Entity) -> Option<Value>,
+    resource: fn(&World) -> Option<Value>,
+    add: fn(&mut World, Entity, Value),
+    add_resource: fn(&mut World, Value),
+    clone: fn(&(dyn Any + Send + Sync)) -> Value,
+    serialize: fn(&(dyn Any + Send + Sync)) -> &dyn erased_serde::Serialize,
+    deserialize: fn(&mut dyn erased_serde::Deserializer<'_>) -> Result<Value, erased_serde::Error>,
+    map_entities: Option<fn(&mut (dyn Any + Send + Sync), &EntityMap)>,
+}
+
+impl SceneType {
+    fn new<T>(name: &'static str) -> Self
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            type_id: TypeId::of::<T>(),
+            get: |world, entity| world.get::<T>(entity).map(|c| Box::new(c.clone()) as Value),
+            resource: |world| world.resource::<T>().map(|r| Box::new(r.clone()) as Value),
+            add: |world, entity, value| {
+                world.add(entity, *value.downcast::<T>().unwrap());
+            },
+            add_resource: |world, value| {
+                world.add_resource(*value.downcast::<T>().unwrap());
+            },
+            clone: |value| Box::new(value.downcast_ref::<T>().unwrap().clone()),
+            serialize: |value| value.downcast_ref::<T>().unwrap(),
+            deserialize: |deserializer| Ok(Box::new(erased_serde::deserialize::<T>(deserializer)?)),
+            map_entities: None,
+        }
+    }
+}
+
+impl fmt::Debug for SceneType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// The kinds of components and resources which are saved in `Scene`s, together with the names
+/// they are saved under. `Parent`, `Children` and `Name` are registered by default.
+#[derive(Debug)]
+pub struct SceneRegistry {
+    // In the order they were registered
+    types: Vec<SceneType>,
+    names: HashMap<&'static str, usize>,
+}
+
+impl Default for SceneRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            types: Vec::new(),
+            names: HashMap::new(),
+        };
+        registry
+            .register_mapped_named::<Parent>("Parent")
+            .register_mapped_named::<Children>("Children")
+            .register_named::<Name>("Name");
+        registry
+    }
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` under its type name, see `register_named`.
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.register_named::<T>(any::type_name::<T>())
+    }
+
+    /// Registers `T` to be saved in scenes under `name`. Registering a type again replaces its
+    /// name. Panics if another type is already registered under `name`.
+    pub fn register_named<T>(&mut self, name: &'static str) -> &mut Self
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.insert(SceneType::new::<T>(name))
+    }
+
+    /// Same as `register` for components and resources which refer to other entities, which are
+    /// mapped to the spawned entities when a scene is spawned.
+    pub fn register_mapped<T>(&mut self) -> &mut Self
+    where
+        T: MapEntities + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.register_mapped_named::<T>(any::type_name::<T>())
+    }
+
+    /// Same as `register_named` for components and resources which refer to other entities.
+    pub fn register_mapped_named<T>(&mut self, name: &'static str) -> &mut Self
+    where
+        T: MapEntities + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.insert(SceneType {
+            map_entities: Some(|value, map| {
+                value.downcast_mut::<T>().unwrap().map_entities(map);
+            }),
+            ..SceneType::new::<T>(name)
+        })
+    }
+
+    fn insert(&mut self, ty: SceneType) -> &mut Self {
+        if let Some(&i) = self.names.get(ty.name) {
+            assert!(
+                self.types[i].type_id == ty.type_id,
+                "Another type is already registered under the name {:?}",
+                ty.name
+            );
+        }
+        match self.types.iter().position(|t| t.type_id == ty.type_id) {
+            Some(i) => {
+                self.names.remove(self.types[i].name);
+                self.names.insert(ty.name, i);
+                self.types[i] = ty;
+            }
+            None => {
+                self.names.insert(ty.name, self.types.len());
+                self.types.push(ty);
+            }
+        }
+        self
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.types.iter().any(|t| t.type_id == TypeId::of::<T>())
+    }
+
+    /// Returns the name `T` is saved under.
+    pub fn name<T: 'static>(&self) -> Option<&'static str> {
+        self.types
+            .iter()
+            .find(|t| t.type_id == TypeId::of::<T>())
+            .map(|t| t.name)
+    }
+
+    fn get(&self, name: &str) -> Option<SceneType> {
+        self.names.get(name).map(|&i| self.types[i])
+    }
+}
+
+/// A component or resource in a `Scene`.
+struct SceneValue {
+    ty: SceneType,
+    value: Value,
+}
+
+impl Clone for SceneValue {
+    fn clone(&self) -> Self {
+        Self {
+            ty: self.ty,
+            value: (self.ty.clone)(&*self.value),
+        }
+    }
+}
+
+impl fmt::Debug for SceneValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.ty.fmt(f)
+    }
+}
+
+/// An entity in a `Scene` together with its components.
+#[derive(Debug, Clone)]
+pub struct SceneEntity {
+    entity: Entity,
+    components: Vec<SceneValue>,
+}
+
+impl SceneEntity {
+    /// The entity in the world the scene was created from, or the entity it was saved as.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.components
+            .iter()
+            .find_map(|c| c.value.downcast_ref::<T>())
+    }
+
+    /// The names of the components of the entity, see `SceneRegistry::register_named`.
+    pub fn component_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.components.iter().map(|c| c.ty.name)
+    }
+}
+
+/// A copy of the entities of a `World` together with their components and the resources of the
+/// world, limited to the kinds of components and resources registered in a `SceneRegistry`.
+/// Scenes can be saved as RON (or a binary format with the `binary` feature), or any other format
+/// supported by serde using `SceneDeserializer`, and spawned into a world.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    entities: Vec<SceneEntity>,
+    resources: Vec<SceneValue>,
+}
+
+impl Scene {
+    /// Copies every entity in `world`, with the components and resources registered in
+    /// `registry`.
+    pub fn from_world(world: &World, registry: &SceneRegistry) -> Self {
+        let values = |get: &dyn Fn(&SceneType) -> Option<Value>| {
+            registry
+                .types
+                .iter()
+                .filter_map(|ty| get(ty).map(|value| SceneValue { ty: *ty, value }))
+                .collect()
+        };
+        Self {
+            entities: world
+                .entities()
+                .iter()
+                .map(|entity| SceneEntity {
+                    entity,
+                    components: values(&|ty| (ty.get)(world, entity)),
+                })
+                .collect(),
+            resources: values(&|ty| (ty.resource)(world)),
+        }
+    }
+
+    pub fn entities(&self) -> &[SceneEntity] {
+        &self.entities
+    }
+
+    pub fn resource<T: 'static>(&self) -> Option<&T> {
+        self.resources
+            .iter()
+            .find_map(|r| r.value.downcast_ref::<T>())
+    }
+
+    /// Spawns a new entity in `world` for every entity in the scene and adds copies of their
+    /// components, with references to entities in the scene mapped to the spawned entities. The
+    /// resources of the scene replace those already in `world`. Returns which entity was spawned
+    /// for which entity in the scene.
+    pub fn spawn(&self, world: &mut World) -> EntityMap {
+        let mut map = EntityMap::default();
+        let spawned = world.entities().reserve(self.entities.len());
+        for (entity, spawned) in self.entities.iter().zip(spawned) {
+            map.insert(entity.entity, spawned);
+        }
+        let mapped = |value: &SceneValue| {
+            let mut copy = (value.ty.clone)(&*value.value);
+            if let Some(map_entities) = value.ty.map_entities {
+                map_entities(&mut *copy, &map);
+            }
+            copy
+        };
+        for entity in &self.entities {
+            for component in &entity.components {
+                (component.ty.add)(world, map.map(entity.entity), mapped(component));
+            }
+        }
+        for resource in &self.resources {
+            (resource.ty.add_resource)(world, mapped(resource));
+        }
+        map
+    }
+
+    /// Writes the scene as pretty printed RON.
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(SceneError::Ron)
+    }
+
+    /// Reads a scene written by `to_ron`. Every component and resource in it must be registered
+    /// in `registry` under the name it was saved with.
+    pub fn from_ron(ron: &str, registry: &SceneRegistry) -> Result<Self, SceneError> {
+        ron::Options::default()
+            .from_str_seed(ron, SceneDeserializer::new(registry))
+            .map_err(SceneError::Parse)
+    }
+
+    /// Writes the scene in a compact binary format.
+    #[cfg(feature = "binary")]
+    pub fn to_binary(&self) -> Result<Vec<u8>, SceneError> {
+        use bincode::Options;
+        bincode::DefaultOptions::new()
+            .serialize(self)
+            .map_err(SceneError::Binary)
+    }
+
+    /// Reads a scene written by `to_binary`. See `from_ron`.
+    #[cfg(feature = "binary")]
+    pub fn from_binary(bytes: &[u8], registry: &SceneRegistry) -> Result<Self, SceneError> {
+        use bincode::Options;
+        bincode::DefaultOptions::new()
+            .deserialize_seed(SceneDeserializer::new(registry), bytes)
+            .map_err(SceneError::Binary)
+    }
+}
+
+/// The components or resources of a scene, written as a map from their names to their values.
+struct Values<'a>(&'a [SceneValue]);
+
+impl Serialize for Values<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for value in self.0 {
+            map.serialize_entry(value.ty.name, (value.ty.serialize)(&*value.value))?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for SceneEntity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SceneEntity", 2)?;
+        state.serialize_field("entity", &self.entity)?;
+        state.serialize_field("components", &Values(&self.components))?;
+        state.end()
+    }
+}
+
+impl Serialize for Scene {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Scene", 2)?;
+        state.serialize_field("resources", &Values(&self.resources))?;
+        state.serialize_field("entities", &self.entities)?;
+        state.end()
+    }
+}
+
+/// Deserializes a `Scene` with the components and resources registered in a `SceneRegistry`, from
+/// any format supported by serde. E.g. for JSON:
+/// `SceneDeserializer::new(&registry).deserialize(&mut serde_json::Deserializer::from_str(json))`
+#[derive(Debug, Clone, Copy)]
+pub struct SceneDeserializer<'a> {
+    registry: &'a SceneRegistry,
+}
+
+impl<'a> SceneDeserializer<'a> {
+    pub fn new(registry: &'a SceneRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum SceneField {
+    Resources,
+    Entities,
+}
+
+impl<'de> DeserializeSeed<'de> for SceneDeserializer<'_> {
+    type Value = Scene;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Scene, D::Error> {
+        deserializer.deserialize_struct("Scene", &["resources", "entities"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for SceneDeserializer<'_> {
+    type Value = Scene;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a scene")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Scene, A::Error> {
+        let resources = seq
+            .next_element_seed(ValuesDeserializer(self.registry))?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let entities = seq
+            .next_element_seed(EntitiesDeserializer(self.registry))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Scene {
+            entities,
+            resources,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Scene, A::Error> {
+        let mut scene = Scene::default();
+        while let Some(field) = map.next_key()? {
+            match field {
+                SceneField::Resources => {
+                    scene.resources = map.next_value_seed(ValuesDeserializer(self.registry))?
+                }
+                SceneField::Entities => {
+                    scene.entities = map.next_value_seed(EntitiesDeserializer(self.registry))?
+                }
+            }
+        }
+        Ok(scene)
+    }
+}
+
+struct EntitiesDeserializer<'a>(&'a SceneRegistry);
+
+impl<'de> DeserializeSeed<'de> for EntitiesDeserializer<'_> {
+    type Value = Vec<SceneEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitiesDeserializer<'_> {
+    type Value = Vec<SceneEntity>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut entities = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        // `Scene::spawn` spawns one entity per scene entity, so a duplicate would leak one
+        let mut seen = HashSet::new();
+        while let Some(entity) = seq.next_element_seed(EntityDeserializer(self.0))? {
+            if !seen.insert(entity.entity) {
+                return Err(de::Error::custom(format!(
+                    "{:?} is in the scene more than once",
+                    entity.entity
+                )));
+            }
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum EntityField {
+    Entity,
+    Components,
+}
+
+struct EntityDeserializer<'a>(&'a SceneRegistry);
+
+impl<'de> DeserializeSeed<'de> for EntityDeserializer<'_> {
+    type Value = SceneEntity;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("SceneEntity", &["entity", "components"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntityDeserializer<'_> {
+    type Value = SceneEntity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an entity with components")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let entity = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let components = seq
+            .next_element_seed(ValuesDeserializer(self.0))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(SceneEntity { entity, components })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entity = None;
+        let mut components = Vec::new();
+        while let Some(field) = map.next_key()? {
+            match field {
+                EntityField::Entity => entity = Some(map.next_value()?),
+                EntityField::Components => {
+                    components = map.next_value_seed(ValuesDeserializer(self.0))?
+                }
+            }
+        }
+        Ok(SceneEntity {
+            entity: entity.ok_or_else(|| de::Error::missing_field("entity"))?,
+            components,
+        })
+    }
+}
+
+struct ValuesDeserializer<'a>(&'a SceneRegistry);
+
+impl<'de> DeserializeSeed<'de> for ValuesDeserializer<'_> {
+    type Value = Vec<SceneValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValuesDeserializer<'_> {
+    type Value = Vec<SceneValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map from names of components or resources to their values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(name) = map.next_key::<String>()? {
+            let ty = self.0.get(&name).ok_or_else(|| {
+                de::Error::custom(format!(
+                    "{:?} is not registered in the scene registry",
+                    name
+                ))
+            })?;
+            values.push(map.next_value_seed(ValueDeserializer(ty))?);
+        }
+        Ok(values)
+    }
+}
+
+struct ValueDeserializer(SceneType);
+
+impl<'de> DeserializeSeed<'de> for ValueDeserializer {
+    type Value = SceneValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        let value = (self.0.deserialize)(&mut deserializer).map_err(de::Error::custom)?;
+        Ok(SceneValue { ty: self.0, value })
+    }
+}
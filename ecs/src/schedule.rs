@@ -0,0 +1,457 @@
+use std::{any::TypeId, borrow::Cow, collections::HashSet, fmt};
+
+use crate::{ScheduleError, World};
+
+/// The stages of a `Schedule`. Every system belongs to one stage and the stages are run in the
+/// order they're declared here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    Physics,
+    PostUpdate,
+    RenderPrep,
+}
+
+impl Stage {
+    /// Every stage, in the order they're run.
+    pub const ALL: [Stage; 5] = [
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::Physics,
+        Stage::PostUpdate,
+        Stage::RenderPrep,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The kinds of components and resources a system reads and writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: HashSet<TypeId>,
+    writes: HashSet<TypeId>,
+    resource_reads: HashSet<TypeId>,
+    resource_writes: HashSet<TypeId>,
+}
+
+impl Access {
+    pub fn reads<T: 'static>(&self) -> bool {
+        self.reads.contains(&TypeId::of::<T>()) || self.writes::<T>()
+    }
+
+    pub fn writes<T: 'static>(&self) -> bool {
+        self.writes.contains(&TypeId::of::<T>())
+    }
+
+    pub fn reads_resource<T: 'static>(&self) -> bool {
+        self.resource_reads.contains(&TypeId::of::<T>()) || self.writes_resource::<T>()
+    }
+
+    pub fn writes_resource<T: 'static>(&self) -> bool {
+        self.resource_writes.contains(&TypeId::of::<T>())
+    }
+
+    /// Returns `true` if a system with this access and one with `other` access the same kind of
+    /// component or resource, at least one of them mutably.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        fn conflicts(
+            writes: &HashSet<TypeId>,
+            other_reads: &HashSet<TypeId>,
+            other_writes: &HashSet<TypeId>,
+        ) -> bool {
+            !writes.is_disjoint(other_reads) || !writes.is_disjoint(other_writes)
+        }
+        conflicts(&self.writes, &other.reads, &other.writes)
+            || conflicts(&other.writes, &self.reads, &self.writes)
+            || conflicts(
+                &self.resource_writes,
+                &other.resource_reads,
+                &other.resource_writes,
+            )
+            || conflicts(
+                &other.resource_writes,
+                &self.resource_reads,
+                &self.resource_writes,
+            )
+    }
+}
+
+enum SystemFn {
+    Shared(Box<dyn FnMut(&World) + Send>),
+    Exclusive(Box<dyn FnMut(&mut World) + Send>),
+}
+
+/// A named function run on the `World` by a `Schedule`, together with the components and
+/// resources it accesses and how it is ordered relative to other systems.
+/// # Examples
+/// ```
+/// # use ecs::{schedule::{Schedule, Stage, System}, World};
+/// struct Position(f32);
+/// struct Velocity(f32);
+///
+/// let mut schedule = Schedule::new();
+/// schedule.add_system(
+///     Stage::Update,
+///     System::new("movement", |world: &World| {
+///         for (p, v) in world.query::<(&mut Position, &Velocity)>().iter() {
+///             p.0 += v.0;
+///         }
+///     })
+///     .writes::<Position>()
+///     .reads::<Velocity>(),
+/// );
+/// schedule.add_system(
+///     Stage::Update,
+///     System::exclusive("spawn", |world: &mut World| {
+///         let e = world.spawn();
+///         world.add(e, Position(0.0));
+///         world.add(e, Velocity(1.0));
+///     })
+///     .before("movement"),
+/// );
+///
+/// let mut world = World::default();
+/// schedule.run(&mut world);
+/// assert_eq!(world.query::<&Position>().iter().next().unwrap().0, 1.0);
+/// ```
+pub struct System {
+    name: Cow<'static, str>,
+    run: SystemFn,
+    access: Access,
+    // Whether the system accesses non-send resources, so it must run on the scheduling thread
+    non_send: bool,
+    before: Vec<Cow<'static, str>>,
+    after: Vec<Cow<'static, str>>,
+}
+
+impl System {
+    /// Creates a system which only needs shared access to the world. The components and resources
+    /// it accesses should be declared with `reads`, `writes`, `reads_resource` and
+    /// `writes_resource`, since systems which don't conflict may be run in parallel.
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        run: impl FnMut(&World) + Send + 'static,
+    ) -> Self {
+        Self::with_fn(name.into(), SystemFn::Shared(Box::new(run)))
+    }
+
+    /// Creates a system with exclusive access to the world, e.g. to spawn entities or add
+    /// components. Exclusive systems are assumed to access everything.
+    pub fn exclusive(
+        name: impl Into<Cow<'static, str>>,
+        run: impl FnMut(&mut World) + Send + 'static,
+    ) -> Self {
+        Self::with_fn(name.into(), SystemFn::Exclusive(Box::new(run)))
+    }
+
+    fn with_fn(name: Cow<'static, str>, run: SystemFn) -> Self {
+        Self {
+            name,
+            run,
+            access: Access::default(),
+            non_send: false,
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    /// Declares that the system reads components of type `T`.
+    pub fn reads<T: 'static>(mut self) -> Self {
+        self.access.reads.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the system reads and writes components of type `T`.
+    pub fn writes<T: 'static>(mut self) -> Self {
+        self.access.writes.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the system reads the resource `T`.
+    pub fn reads_resource<T: 'static>(mut self) -> Self {
+        self.access.resource_reads.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the system reads and writes the resource `T`.
+    pub fn writes_resource<T: 'static>(mut self) -> Self {
+        self.access.resource_writes.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the system reads the non-send resource `T`, see
+    /// `World::add_non_send_resource`. The system then never runs in parallel with others, but
+    /// on the thread running the schedule, which must be the thread the resource was added from.
+    pub fn reads_non_send_resource<T: 'static>(mut self) -> Self {
+        self.non_send = true;
+        self.reads_resource::<T>()
+    }
+
+    /// Declares that the system reads and writes the non-send resource `T`, see
+    /// `reads_non_send_resource`.
+    pub fn writes_non_send_resource<T: 'static>(mut self) -> Self {
+        self.non_send = true;
+        self.writes_resource::<T>()
+    }
+
+    /// Makes the system run before the system named `other`, which must be in the same or a later
+    /// stage.
+    pub fn before(mut self, other: impl Into<Cow<'static, str>>) -> Self {
+        self.before.push(other.into());
+        self
+    }
+
+    /// Makes the system run after the system named `other`, which must be in the same or an
+    /// earlier stage.
+    pub fn after(mut self, other: impl Into<Cow<'static, str>>) -> Self {
+        self.after.push(other.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Returns `true` if the system needs exclusive access to the world.
+    pub fn is_exclusive(&self) -> bool {
+        matches!(self.run, SystemFn::Exclusive(_))
+    }
+
+    /// Returns `true` if the system accesses non-send resources, so it has to run on the thread
+    /// running the schedule.
+    pub fn is_non_send(&self) -> bool {
+        self.non_send
+    }
+
+    /// Returns `true` if this system and `other` can not safely run at the same time. Like
+    /// exclusive systems, systems accessing non-send resources conflict with every other system.
+    pub fn conflicts_with(&self, other: &System) -> bool {
+        self.is_exclusive()
+            || other.is_exclusive()
+            || self.non_send
+            || other.non_send
+            || self.access.conflicts_with(&other.access)
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        match &mut self.run {
+            SystemFn::Shared(run) => run(world),
+            SystemFn::Exclusive(run) => run(world),
+        }
+    }
+
+    fn run_shared(&mut self, world: &World) {
+        match &mut self.run {
+            SystemFn::Shared(run) => run(world),
+            SystemFn::Exclusive(_) => unreachable!("exclusive systems are never run in a batch"),
+        }
+    }
+}
+
+impl fmt::Debug for System {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("System")
+            .field("name", &self.name)
+            .field("exclusive", &self.is_exclusive())
+            .field("non_send", &self.non_send)
+            .field("access", &self.access)
+            .field("before", &self.before)
+            .field("after", &self.after)
+            .finish()
+    }
+}
+
+/// A set of systems to run on a `World`, grouped into `Stage`s. Within a stage the systems run in
+/// the order they were added, unless that would break their `before` and `after` constraints.
+///
+/// Consecutive systems which don't conflict (see `System::conflicts_with`) and aren't ordered
+/// relative to each other are grouped into batches, and the systems of a batch are run in parallel
+/// on the rayon thread pool. Batches of a single system, which include every exclusive and
+/// non-send system, run on the thread calling `run`.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    // Indexed by `Stage::index`
+    stages: [Vec<System>; Stage::ALL.len()],
+    // The batches to run the systems of each stage in, in order, as indices into `stages`. `None`
+    // if systems have been added since it was last sorted.
+    batches: Option<[Vec<Vec<usize>>; Stage::ALL.len()]>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a system to `stage`. Panics if a system with the same name has already been added.
+    pub fn add_system(&mut self, stage: Stage, system: System) -> &mut Self {
+        assert!(
+            self.system(&system.name).is_none(),
+            "A system named {:?} has already been added to the schedule",
+            system.name,
+        );
+        self.stages[stage.index()].push(system);
+        self.batches = None;
+        self
+    }
+
+    /// Returns the system named `name` together with its stage.
+    pub fn system(&self, name: &str) -> Option<(Stage, &System)> {
+        Stage::ALL.iter().find_map(|&stage| {
+            self.stages[stage.index()]
+                .iter()
+                .find(|s| s.name == name)
+                .map(|s| (stage, s))
+        })
+    }
+
+    /// The systems of `stage`, in the order they were added.
+    pub fn systems(&self, stage: Stage) -> &[System] {
+        &self.stages[stage.index()]
+    }
+
+    /// Checks that the ordering constraints of every system can be satisfied and works out the
+    /// order to run the systems in. This is done by `run` if needed, but can be called before to
+    /// handle errors without panicking.
+    pub fn sort(&mut self) -> Result<(), ScheduleError> {
+        if self.batches.is_some() {
+            return Ok(());
+        }
+        let mut batches: [Vec<Vec<usize>>; Stage::ALL.len()] = Default::default();
+        for stage in Stage::ALL {
+            batches[stage.index()] = self.sort_stage(stage)?;
+        }
+        self.batches = Some(batches);
+        Ok(())
+    }
+
+    /// Returns the names of the systems of `stage` in the order they will run. Systems in the same
+    /// batch may actually run at the same time, see `run_batches`.
+    pub fn run_order(&mut self, stage: Stage) -> Result<Vec<&str>, ScheduleError> {
+        Ok(self.run_batches(stage)?.into_iter().flatten().collect())
+    }
+
+    /// Returns the names of the systems of `stage` grouped into the batches they will run in. The
+    /// batches run one after another and the systems of a batch run in parallel.
+    pub fn run_batches(&mut self, stage: Stage) -> Result<Vec<Vec<&str>>, ScheduleError> {
+        self.sort()?;
+        let batches = &self.batches.as_ref().unwrap()[stage.index()];
+        let systems = &self.stages[stage.index()];
+        Ok(batches
+            .iter()
+            .map(|batch| batch.iter().map(|&i| systems[i].name()).collect())
+            .collect())
+    }
+
+    /// Runs every system of every stage once. Panics if the ordering constraints of the systems
+    /// can not be satisfied, see `sort`.
+    pub fn run(&mut self, world: &mut World) {
+        for stage in Stage::ALL {
+            self.run_stage(stage, world);
+        }
+    }
+
+    /// Runs every system of `stage` once. Panics if the ordering constraints of the systems can
+    /// not be satisfied, see `sort`.
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World) {
+        if let Err(err) = self.sort() {
+            panic!("{}", err);
+        }
+        let batches = &self.batches.as_ref().unwrap()[stage.index()];
+        let systems = &mut self.stages[stage.index()];
+        for batch in batches {
+            if let [i] = batch[..] {
+                systems[i].run(world);
+                continue;
+            }
+            let world = &*world;
+            rayon::scope(|scope| {
+                for (i, system) in systems.iter_mut().enumerate() {
+                    if batch.contains(&i) {
+                        scope.spawn(move |_| system.run_shared(world));
+                    }
+                }
+            });
+        }
+    }
+
+    /// Sorts the systems of `stage` topologically by their constraints, keeping the order they
+    /// were added in where possible, and splits them into batches that can run in parallel.
+    fn sort_stage(&self, stage: Stage) -> Result<Vec<Vec<usize>>, ScheduleError> {
+        let systems = &self.stages[stage.index()];
+        // `edges[i]` are the systems which must run after system `i`
+        let mut edges = vec![Vec::new(); systems.len()];
+        let mut in_degrees = vec![0; systems.len()];
+        for (i, system) in systems.iter().enumerate() {
+            let constraints = system
+                .before
+                .iter()
+                .map(|other| (other, true))
+                .chain(system.after.iter().map(|other| (other, false)));
+            for (other, before) in constraints {
+                let (other_stage, _) =
+                    self.system(other)
+                        .ok_or_else(|| ScheduleError::UnknownSystem {
+                            system: system.name.to_string(),
+                            other: other.to_string(),
+                        })?;
+                if other_stage != stage {
+                    if (other_stage > stage) != before {
+                        return Err(ScheduleError::WrongStage {
+                            system: system.name.to_string(),
+                            other: other.to_string(),
+                        });
+                    }
+                    // the stages already run in the right order
+                    continue;
+                }
+                let j = systems.iter().position(|s| &s.name == other).unwrap();
+                let (first, then) = if before { (i, j) } else { (j, i) };
+                edges[first].push(then);
+                in_degrees[then] += 1;
+            }
+        }
+
+        let mut order = Vec::with_capacity(systems.len());
+        let mut done = vec![false; systems.len()];
+        while order.len() < systems.len() {
+            let next = (0..systems.len()).find(|&i| !done[i] && in_degrees[i] == 0);
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    return Err(ScheduleError::Cycle(
+                        (0..systems.len())
+                            .filter(|&i| !done[i])
+                            .map(|i| systems[i].name.to_string())
+                            .collect(),
+                    ))
+                }
+            };
+            done[next] = true;
+            order.push(next);
+            for &then in &edges[next] {
+                in_degrees[then] -= 1;
+            }
+        }
+
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            let fits_last = batches.last().is_some_and(|batch| {
+                batch
+                    .iter()
+                    .all(|&j| !systems[i].conflicts_with(&systems[j]) && !edges[j].contains(&i))
+            });
+            match batches.last_mut() {
+                Some(batch) if fits_last => batch.push(i),
+                _ => batches.push(vec![i]),
+            }
+        }
+        Ok(batches)
+    }
+}
@@ -0,0 +1,226 @@
+use std::{alloc::Layout, any::TypeId, fmt, ptr, sync::Arc};
+
+use crate::{
+    component::{clone_ptr, CloneFn, ComponentBuffer, ComponentId, ComponentRegistry},
+    dynamic::Schema,
+    entity::EntitiesSnapshot,
+    hierarchy::{Children, Parent},
+    relation::Relations,
+    Entity, World,
+};
+
+/// A copy of the entities of a world and of their components, taken with `World::snapshot` and
+/// brought back with `World::restore`, e.g. to step a simulation backward or to roll it back to
+/// the last state confirmed by a server.
+///
+/// Only the components which can be copied are in the snapshot: those of kinds registered with
+/// `ComponentRegistry::register_clone`, dynamic components, which are copied byte by byte, and
+/// the hierarchy. Relations are copied as well, but resources and events are not.
+/// # Examples
+/// ```
+/// # use ecs::World;
+/// #[derive(Clone)]
+/// struct Position(f32);
+///
+/// let mut world = World::default();
+/// world.component_registry_mut().register_clone::<Position>();
+/// let a = world.spawn_with((Position(0.0),));
+///
+/// let snapshot = world.snapshot();
+/// world.get_mut::<Position>(a).unwrap().0 = 5.0;
+/// let b = world.spawn_with((Position(1.0),));
+///
+/// world.restore(&snapshot);
+/// assert_eq!(world.get::<Position>(a).unwrap().0, 0.0);
+/// assert!(!world.entities().exists(b));
+/// ```
+pub struct Snapshot {
+    entities: EntitiesSnapshot,
+    relations: Relations,
+    columns: Vec<Column>,
+    change_tick: u32,
+}
+
+/// The components of one kind in a snapshot, one after the other in `data`.
+pub(crate) struct Column {
+    pub(crate) id: ComponentId,
+    // Identify the kind, to check the snapshot is restored into a world which agrees on its id
+    type_id: Option<TypeId>,
+    schema: Option<Arc<Schema>>,
+    name: String,
+    copy: CopyFn,
+    drop: unsafe fn(*mut u8),
+    pub(crate) layout: Layout,
+    pub(crate) entities: Vec<Entity>,
+    data: ComponentBuffer,
+}
+
+/// How components of a kind are copied.
+#[derive(Clone, Copy)]
+enum CopyFn {
+    Clone(CloneFn),
+    // Dynamic components are plain data
+    Bytes,
+}
+
+impl CopyFn {
+    fn of(registry: &ComponentRegistry, id: ComponentId) -> Option<Self> {
+        let info = &registry[id].info;
+        if let Some(clone) = info.clone_fn() {
+            return Some(CopyFn::Clone(clone));
+        }
+        // The hierarchy is not cloned with entities, so it has no clone function of its own
+        match info.type_id() {
+            Some(t) if t == TypeId::of::<Parent>() => Some(CopyFn::Clone(clone_ptr::<Parent>)),
+            Some(t) if t == TypeId::of::<Children>() => Some(CopyFn::Clone(clone_ptr::<Children>)),
+            Some(_) => None,
+            None => Some(CopyFn::Bytes),
+        }
+    }
+
+    /// Writes a copy of the component at `src` to `dst`.
+    /// # Safety
+    /// `src` must be a component of a kind copied by `self`, with layout `layout`, and `dst` must
+    /// be valid for writes of it.
+    unsafe fn copy(self, src: *const u8, dst: *mut u8, layout: Layout) {
+        match self {
+            CopyFn::Clone(clone) => clone(src, dst),
+            CopyFn::Bytes => ptr::copy_nonoverlapping(src, dst, layout.size()),
+        }
+    }
+}
+
+impl Snapshot {
+    pub(crate) fn new(world: &World, entities: EntitiesSnapshot, relations: Relations) -> Self {
+        let registry = world.component_registry();
+        let alive: Vec<_> = world
+            .entities()
+            .iter()
+            .map(|e| (e, world.archetypes().location(e.get_id_unchecked())))
+            .collect();
+
+        let columns = registry
+            .infos()
+            .filter_map(|info| {
+                let id = info.id();
+                let copy = CopyFn::of(registry, id)?;
+                let storage = &registry[id].storage;
+                let (entities, components): (Vec<Entity>, Vec<*const u8>) = alive
+                    .iter()
+                    .map(|&(e, location)| {
+                        (
+                            e,
+                            storage.get_entity_ptr(e.get_id_unchecked() as usize, location),
+                        )
+                    })
+                    .filter(|(_, ptr)| !ptr.is_null())
+                    .unzip();
+
+                let layout = storage.item_layout();
+                let data = ComponentBuffer::new(components.iter().map(|_| layout));
+                for (i, &component) in components.iter().enumerate() {
+                    // Safety: `component` is a component of kind `id`, and the buffer has room
+                    // for one at `i`
+                    unsafe { copy.copy(component, data.get(i), layout) };
+                }
+                Some(Column {
+                    id,
+                    type_id: info.type_id(),
+                    schema: info.schema().cloned(),
+                    name: info.name().to_owned(),
+                    copy,
+                    drop: storage.drop_fn(),
+                    layout,
+                    entities,
+                    data,
+                })
+            })
+            .collect();
+
+        Self {
+            entities,
+            relations,
+            columns,
+            change_tick: world.change_tick(),
+        }
+    }
+
+    /// The tick (see `World::change_tick`) at which the snapshot was taken.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// Returns `true` if `entity` was alive when the snapshot was taken.
+    pub fn exists(&self, entity: Entity) -> bool {
+        self.entities.exists(entity)
+    }
+
+    pub(crate) fn entities(&self) -> &EntitiesSnapshot {
+        &self.entities
+    }
+
+    pub(crate) fn relations(&self) -> &Relations {
+        &self.relations
+    }
+
+    pub(crate) fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Panics if a kind of component in the snapshot has another id in `registry`, e.g. because
+    /// the snapshot was taken from another world.
+    pub(crate) fn check(&self, registry: &ComponentRegistry) {
+        for column in &self.columns {
+            let same = registry
+                .infos()
+                .find(|info| info.id() == column.id)
+                .is_some_and(|info| {
+                    info.type_id() == column.type_id && info.schema() == column.schema.as_ref()
+                });
+            assert!(
+                same,
+                "The component {} of the snapshot is registered differently in the world",
+                column.name
+            );
+        }
+    }
+}
+
+impl Column {
+    /// Writes a copy of the component at `row` to `dst`.
+    /// # Safety
+    /// `dst` must be valid for writes of the kind of component.
+    pub(crate) unsafe fn copy_to(&self, row: usize, dst: *mut u8) {
+        self.copy.copy(self.data.get(row), dst, self.layout);
+    }
+}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        for row in 0..self.entities.len() {
+            // Safety: the buffer holds a component of the kind at every row
+            unsafe { (self.drop)(self.data.get(row)) };
+        }
+    }
+}
+
+// SAFETY: the snapshot owns its components like a `Vec` would, and only components which are
+// `Send + Sync` can be registered.
+unsafe impl Send for Column {}
+unsafe impl Sync for Column {}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("change_tick", &self.change_tick)
+            .field(
+                "components",
+                &self
+                    .columns
+                    .iter()
+                    .map(|c| (&c.name, c.entities.len()))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
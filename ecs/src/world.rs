@@ -1,30 +1,70 @@
-use crate::component::{ComponentId, ComponentRegistry};
-use crate::query::QueryResponse;
-use crate::{query::Query, BorrowMutError, Entities, Entity};
+use std::{
+    any::{self, Any, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    hash::Hash,
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr::{self, NonNull},
+    slice,
+    sync::{Arc, Mutex},
+    vec,
+};
+
+use crate::bundle::Bundle;
 
-pub struct ResourceId(ComponentId);
+use crate::component::{
+    ArchetypeId, Archetypes, ComponentBuffer, ComponentEntryRef, ComponentId, ComponentRegistry,
+    Location, Ref, StorageType,
+};
+use crate::dynamic::{DynamicComponent, DynamicMut, DynamicQuery, DynamicRef};
+use crate::event::{self, EventWriter, Events};
+use crate::hierarchy::{Children, Parent};
+use crate::index::{Index, IndexRef};
+use crate::name::Name;
+use crate::query::{
+    Added, Changed, ComponentFilter, QueryResponse, TypedQuery, WorldFilter, WorldQuery,
+};
+use crate::reflect::Reflect;
+use crate::relation::Relations;
+use crate::removed::{Removals, RemovedComponents};
+use crate::resource::{Res, ResMut, ResourceId, Resources};
+use crate::scene::EntityMap;
+use crate::snapshot::Snapshot;
+use crate::{query::Query, BorrowMutError, Entities, Entity};
 
 #[derive(Debug)]
 pub struct World {
     entities: Entities,
     component_registry: ComponentRegistry,
-    // The current storage implementation for components waste very little if the only component
-    // that's added to is is the first created entity, so we can simply have one entity that holds
-    // all entities. You could see it as a bit of a hack and there are better ways to implement
-    // resources, but this is at least very simple. NOTE however that iterating through all
-    // entities would also yield this one which is not desirable.
-    resource_holder: Entity,
+    archetypes: Archetypes,
+    resources: Resources,
+    change_tick: u32,
+    removals: Removals,
+    relations: Relations,
+    // The component ids of every kind of bundle used with this world
+    bundles: HashMap<TypeId, Arc<[ComponentId]>>,
+    // Updates the events of every type added with `add_event`
+    event_updates: HashMap<TypeId, fn(&mut World)>,
+    // A `Mutex<Index<T, K>>` for every index added with `add_index`, by the type of the index
+    indices: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
 }
 
 impl Default for World {
     fn default() -> Self {
-        let entities = Entities::default();
-        let resource_holder = entities.spawn();
-        Self {
-            entities,
+        let mut world = Self {
+            entities: Default::default(),
             component_registry: Default::default(),
-            resource_holder,
-        }
+            archetypes: Default::default(),
+            resources: Default::default(),
+            change_tick: 1,
+            removals: Default::default(),
+            relations: Default::default(),
+            bundles: Default::default(),
+            event_updates: Default::default(),
+            indices: Default::default(),
+        };
+        world.add_index(Name::clone);
+        world
     }
 }
 
@@ -34,40 +74,156 @@ impl World {
         self.entities.spawn()
     }
 
+    /// Creates a new entity with the components in `bundle`, e.g.
+    /// `world.spawn_with((Position(0.0, 0.0), Velocity(1.0, 0.0)))`. See `insert_bundle`.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        self.insert_bundle(entity, bundle);
+        entity
+    }
+
     /// Adds the resource to the world. *Resources* are like components, but associated with the
-    /// world, not an entity.
-    pub fn add_resource<T: 'static>(&mut self, resource: T) -> ResourceId {
-        let resource_id = self.component_registry.register::<T>();
-        self.add(self.resource_holder, resource);
-        ResourceId(resource_id)
+    /// world, not an entity. If the world already had a resource of this type, it is dropped and
+    /// replaced. The returned id can be used to look the resource up faster, see
+    /// `resource_by_id`.
+    pub fn add_resource<T: Send + Sync + 'static>(&mut self, resource: T) -> ResourceId {
+        let id = self.resources.register::<T>();
+        // Safety: the id was registered for `T`
+        unsafe { self.resources.insert(id, resource) };
+        id
+    }
+
+    /// Same as `add_resource` for resources which are not `Send` or `Sync`. The resource can only
+    /// be accessed from the current thread, so systems using it must be declared with
+    /// `System::reads_non_send_resource` or `writes_non_send_resource`. The world can still be
+    /// moved to another thread, but dropping it there panics and leaks the resource, so remove it
+    /// first.
+    pub fn add_non_send_resource<T: 'static>(&mut self, resource: T) -> ResourceId {
+        let id = self.resources.register_non_send::<T>();
+        unsafe { self.resources.insert(id, resource) };
+        id
+    }
+
+    /// Removes the resource from the world, returning it or `None` if there was none.
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        let id = self.resources.id::<T>()?;
+        unsafe { self.resources.remove(id) }
     }
 
-    pub fn resource<T: 'static>(&self) -> Option<&T> {
-        self.get(self.resource_holder)
+    /// Same as `res`. Panics if the resource currently is mutably borrowed with `res_mut`, or if
+    /// it is a non-send resource of another thread.
+    pub fn resource<T: 'static>(&self) -> Option<Res<'_, T>> {
+        self.res()
     }
 
+    /// Panics if the resource currently is borrowed with `res` or `res_mut`, or if it is a
+    /// non-send resource of another thread.
     pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
-        self.get_mut(self.resource_holder)
+        let id = self.resources.id::<T>()?;
+        unsafe {
+            self.resources
+                .get_mut_ptr(id)
+                .map(|ptr| ptr.cast().as_mut())
+        }
+    }
+
+    /// Borrows a resource until the returned `Res` is dropped, without needing exclusive access
+    /// to the world. Resources are borrowed separately from components, so this can be held while
+    /// querying. Panics if the resource currently is mutably borrowed, or if it is a non-send
+    /// resource of another thread.
+    pub fn res<T: 'static>(&self) -> Option<Res<'_, T>> {
+        let id = self.resources.id::<T>()?;
+        let borrow = self.resources.borrow(id, false)?;
+        Some(unsafe { Res::new(borrow) })
+    }
+
+    /// Same as `res` but for mutable access, e.g. from systems which only have shared access to
+    /// the world. Panics if the resource currently is borrowed at all.
+    pub fn res_mut<T: 'static>(&self) -> Option<ResMut<'_, T>> {
+        let id = self.resources.id::<T>()?;
+        let borrow = self.resources.borrow(id, true)?;
+        Some(unsafe { ResMut::new(borrow) })
+    }
+
+    pub fn resource_id<T: 'static>(&self) -> Option<ResourceId> {
+        self.resources.id::<T>()
+    }
+
+    /// Same as `res` but looks the resource up by its id. Panics if the resource is not a `T`.
+    pub fn resource_by_id<T: 'static>(&self, id: ResourceId) -> Option<Res<'_, T>> {
+        self.check_resource_type::<T>(id);
+        let borrow = self.resources.borrow(id, false)?;
+        Some(unsafe { Res::new(borrow) })
+    }
+
+    /// Same as `res_mut` but looks the resource up by its id. Panics if the resource is not a
+    /// `T`.
+    pub fn resource_by_id_mut<T: 'static>(&self, id: ResourceId) -> Option<ResMut<'_, T>> {
+        self.check_resource_type::<T>(id);
+        let borrow = self.resources.borrow(id, true)?;
+        Some(unsafe { ResMut::new(borrow) })
+    }
+
+    fn check_resource_type<T: 'static>(&self, id: ResourceId) {
+        let info = self.resources.info(id);
+        assert_eq!(
+            info.type_id(),
+            Some(TypeId::of::<T>()),
+            "The resource {} is not a {}",
+            info.name(),
+            any::type_name::<T>()
+        );
+    }
+
+    /// Adds the `Events<T>` resource to the world, if it has none yet, and makes `update_events`
+    /// update it. See `event::Events`.
+    pub fn add_event<T: Send + Sync + 'static>(&mut self) {
+        self.event_updates
+            .insert(TypeId::of::<T>(), event::update::<T>);
+        if self.resource::<Events<T>>().is_none() {
+            self.add_resource(Events::<T>::new());
+        }
+    }
+
+    /// Updates the events of every type added with `add_event`, dropping the oldest ones. Should
+    /// be called once per update, before the systems sending events run, e.g. by a system in the
+    /// first stage. See `Events::update`.
+    pub fn update_events(&mut self) {
+        let updates: Vec<_> = self.event_updates.values().copied().collect();
+        for update in updates {
+            update(self);
+        }
+    }
+
+    /// Borrows the `Events<T>` resource to send events until the returned writer is dropped.
+    /// Panics if `T` has not been added with `add_event` or the events are currently borrowed.
+    pub fn event_writer<T: 'static>(&self) -> EventWriter<'_, T> {
+        let events = self.res_mut::<Events<T>>().unwrap_or_else(|| {
+            panic!(
+                "The event {} has not been added to the world",
+                any::type_name::<T>()
+            )
+        });
+        EventWriter::new(events)
+    }
+
+    /// Sends a single event, see `event_writer`.
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.event_writer().send(event);
     }
 
     /// Adds a component to an entity. If the type is not registered as a component, it gets
     /// registered automatically. Returns `true` if `entity` did not have this kind of component
     /// before and `entity` exists. If `entity` exists and the component was already present,
     /// the old component is dropped and replaced with the new one.
-    pub fn add<T: 'static>(&mut self, entity: Entity, component: T) -> bool {
+    pub fn add<T: Send + Sync + 'static>(&mut self, entity: Entity, component: T) -> bool {
         let comp_id = self
             .component_registry
             .id::<T>()
             .unwrap_or_else(|| self.component_registry.register::<T>());
 
-        self.entities
-            .id(entity)
-            .map(|id| unsafe {
-                self.component_registry[comp_id]
-                    .storage
-                    .set::<T>(id as usize, component)
-            })
-            .unwrap_or(false)
+        let mut component = ManuallyDrop::new(component);
+        unsafe { self.add_raw(entity, (&mut *component as *mut T).cast(), comp_id) }
     }
 
     /// The component type must already be registered in the component registry.
@@ -82,89 +238,1067 @@ impl World {
         component: *mut u8,
         component_id: ComponentId,
     ) -> bool {
-        self.entities
-            .id(entity)
-            .map(|id| {
+        let id = match self.entities.id(entity) {
+            Some(id) => id,
+            None => {
                 self.component_registry[component_id]
                     .storage
-                    .set_ptr(id as usize, component)
-            })
-            .unwrap_or(false)
+                    .drop_ptr(component);
+                return false;
+            }
+        };
+        match self.component_registry[component_id].storage.storage_type() {
+            StorageType::Archetype => self.add_to_archetype(id, component, component_id),
+            _ => self.component_registry[component_id].storage.set_ptr(
+                id as usize,
+                component,
+                self.change_tick,
+            ),
+        }
+    }
+
+    /// Adds an archetype stored component to the entity with id `id`, moving the entity to the
+    /// archetype with the new set of components if it did not have this kind of component before.
+    /// # Safety
+    /// Same as for `add_raw`.
+    unsafe fn add_to_archetype(
+        &mut self,
+        id: u32,
+        component: *mut u8,
+        component_id: ComponentId,
+    ) -> bool {
+        let from = self.archetypes.location(id);
+        let mut components = match from {
+            Some(from) if self.archetypes[from.archetype].contains(component_id) => {
+                self.component_registry[component_id]
+                    .storage
+                    .replace_ptr_at(from, component, self.change_tick);
+                return false;
+            }
+            Some(from) => self.archetypes[from.archetype].components().to_vec(),
+            None => Vec::new(),
+        };
+        components.push(component_id);
+        let to = self.archetypes.get_or_insert(components);
+        self.move_entity(id, from, Some(to));
+        self.component_registry[component_id]
+            .storage
+            .push_ptr(to, component, self.change_tick);
+        true
+    }
+
+    /// Moves the archetype stored components of the entity with id `id` from `from` to the end of
+    /// `to`. Components not in `to` must already have been taken out of `from` by the caller, and
+    /// components not in `from` must be pushed to `to` by the caller afterwards.
+    fn move_entity(&mut self, id: u32, from: Option<Location>, to: Option<ArchetypeId>) {
+        if let Some(from) = from {
+            if let Some(to) = to {
+                for &c in self.archetypes[from.archetype].components() {
+                    if self.archetypes[to].contains(c) {
+                        self.component_registry[c].storage.move_row(from, to);
+                    }
+                }
+            }
+            self.archetypes.swap_remove(from);
+        }
+        if let Some(to) = to {
+            self.archetypes.push(to, id);
+        }
     }
 
     /// Removes a component from an entity, returning it or `None` if the entity did not exist or
     /// did not have a component of the specified type.
     pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
         let comp_id = self.component_registry.id::<T>()?;
+        let mut component = MaybeUninit::<T>::uninit();
+        // Safety: `comp_id` is the id of `T`
+        unsafe {
+            self.remove_raw(entity, comp_id, component.as_mut_ptr().cast())
+                .then(|| component.assume_init())
+        }
+    }
 
+    /// Moves the component of kind `comp_id` out of `entity` to `dst`, without dropping it.
+    /// Returns `false` if the entity did not exist or did not have such a component.
+    /// # Safety
+    /// `dst` must be valid for writes of the kind of component.
+    pub unsafe fn remove_raw(
+        &mut self,
+        entity: Entity,
+        comp_id: ComponentId,
+        dst: *mut u8,
+    ) -> bool {
+        let Some(id) = self.entities.id(entity) else {
+            return false;
+        };
+        if self.component_registry[comp_id].storage.storage_type() != StorageType::Archetype {
+            let removed = self.component_registry[comp_id]
+                .storage
+                .remove_into(id as usize, dst);
+            if removed {
+                self.removals.component_removed(comp_id, entity);
+            }
+            return removed;
+        }
+
+        let Some(from) = self.archetypes.location(id) else {
+            return false;
+        };
+        if !self.archetypes[from.archetype].contains(comp_id) {
+            return false;
+        }
+        self.component_registry[comp_id]
+            .storage
+            .take_at_into(from, dst);
+        let components: Vec<_> = self.archetypes[from.archetype]
+            .components()
+            .iter()
+            .copied()
+            .filter(|&c| c != comp_id)
+            .collect();
+        let to = (!components.is_empty()).then(|| self.archetypes.get_or_insert(components));
+        self.move_entity(id, Some(from), to);
+        self.removals.component_removed(comp_id, entity);
+        true
+    }
+
+    /// Adds every component in `bundle` to `entity`, registering them if needed. Components of
+    /// kinds the entity already had are dropped and replaced. Unlike adding the components one by
+    /// one, the entity is moved to its new archetype only once. Returns `false` and drops the
+    /// bundle if `entity` does not exist.
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) -> bool {
+        let ids = self.bundle_ids::<B>();
+        let id = match self.entities.id(entity) {
+            Some(id) => id,
+            None => return false,
+        };
+        // Safety: the bundle passes ownership of a component of every kind in `ids`, in order
+        unsafe { self.insert_components(id, &ids, |mut f| bundle.take_components(&mut f)) };
+        true
+    }
+
+    /// Adds components of the kinds in `ids`, which must be registered and distinct, to the
+    /// entity with id `id`, replacing those it already has. The entity is moved to its new
+    /// archetype only once.
+    /// # Safety
+    /// `take` must call its argument with a pointer to a valid component of every kind in `ids`,
+    /// in order, whose ownership is passed to the world.
+    pub(crate) unsafe fn insert_components(
+        &mut self,
+        id: u32,
+        ids: &[ComponentId],
+        take: impl FnOnce(&mut dyn FnMut(*mut u8)),
+    ) {
+        let from = self.archetypes.location(id);
+        let added: Vec<ComponentId> = ids
+            .iter()
+            .copied()
+            .filter(|&c| {
+                self.component_registry[c].storage.storage_type() == StorageType::Archetype
+                    && !from.is_some_and(|from| self.archetypes[from.archetype].contains(c))
+            })
+            .collect();
+        if !added.is_empty() {
+            let mut components = from.map_or_else(Vec::new, |from| {
+                self.archetypes[from.archetype].components().to_vec()
+            });
+            components.extend_from_slice(&added);
+            let to = self.archetypes.get_or_insert(components);
+            self.move_entity(id, from, Some(to));
+        }
+
+        let location = self.archetypes.location(id);
+        let tick = self.change_tick;
+        let registry = &mut self.component_registry;
+        let mut ids = ids.iter();
+        take(&mut |component| {
+            let comp_id = *ids.next().unwrap();
+            let storage = &mut registry[comp_id].storage;
+            // Safety: `take` passes ownership of a component of kind `comp_id`
+            unsafe {
+                match location {
+                    Some(location) if storage.storage_type() == StorageType::Archetype => {
+                        if added.contains(&comp_id) {
+                            storage.push_ptr(location.archetype, component, tick);
+                        } else {
+                            storage.replace_ptr_at(location, component, tick);
+                        }
+                    }
+                    _ => {
+                        storage.set_ptr(id as usize, component, tick);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Spawns a new entity with clones of the components of `entity` whose kinds have a
+    /// registered clone function, see `ComponentRegistry::register_clone`. Other components, the
+    /// hierarchy and relations are not cloned. Returns `None` if `entity` does not exist.
+    pub fn clone_entity(&mut self, entity: Entity) -> Option<Entity> {
         let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let mut ids = Vec::new();
+        let mut sources = Vec::new();
+        for info in self.component_registry.infos() {
+            let Some(clone) = info.clone_fn() else {
+                continue;
+            };
+            let storage = &self.component_registry[info.id()].storage;
+            let ptr = storage.get_entity_ptr(id as usize, location);
+            if !ptr.is_null() {
+                ids.push(info.id());
+                sources.push((clone, ptr, storage.item_layout()));
+            }
+        }
+
+        let buffer = ComponentBuffer::new(sources.iter().map(|&(_, _, layout)| layout));
+        for (i, &(clone, ptr, _)) in sources.iter().enumerate() {
+            // Safety: `clone` clones components of the kind stored where `ptr` points to
+            unsafe { clone(ptr, buffer.get(i)) };
+        }
+        let clone = self.spawn();
+        // Safety: the buffer holds a clone of a component of every kind in `ids`, in order
         unsafe {
-            self.component_registry[comp_id]
-                .storage
-                .remove::<T>(id as usize)
+            self.insert_components(clone.get_id_unchecked(), &ids, |f| {
+                (0..buffer.len()).for_each(|i| f(buffer.get(i)))
+            });
+        }
+        Some(clone)
+    }
+
+    /// Removes the components in `B` from `entity`. Returns them if the entity had every one of
+    /// them, and otherwise drops those it had and returns `None`. Like `insert_bundle`, the entity
+    /// is moved to its new archetype only once.
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) -> Option<B> {
+        let ids = self.bundle_ids::<B>();
+        let id = self.entities.id(entity)?;
+        let from = self.archetypes.location(id);
+        let in_archetype =
+            |c| self.component_registry[c].storage.storage_type() == StorageType::Archetype;
+        let present: Vec<ComponentId> = ids
+            .iter()
+            .copied()
+            .filter(|&c| {
+                if in_archetype(c) {
+                    from.is_some_and(|from| self.archetypes[from.archetype].contains(c))
+                } else {
+                    !self.component_registry[c]
+                        .storage
+                        .get_ptr(id as usize)
+                        .is_null()
+                }
+            })
+            .collect();
+        let removed_from_archetype: Vec<ComponentId> = present
+            .iter()
+            .copied()
+            .filter(|&c| in_archetype(c))
+            .collect();
+
+        let bundle = if present.len() == ids.len() {
+            let registry = &mut self.component_registry;
+            let mut ids = ids.iter();
+            // Safety: every component exists and is moved to where the bundle reads it from
+            Some(unsafe {
+                B::from_components(&mut |dst| {
+                    let storage = &mut registry[*ids.next().unwrap()].storage;
+                    match from {
+                        Some(from) if storage.storage_type() == StorageType::Archetype => {
+                            storage.take_at_into(from, dst)
+                        }
+                        _ => {
+                            storage.remove_into(id as usize, dst);
+                        }
+                    }
+                })
+            })
+        } else {
+            for &c in &present {
+                let storage = &mut self.component_registry[c].storage;
+                match from {
+                    Some(from) if storage.storage_type() == StorageType::Archetype => {
+                        storage.swap_remove_at(from)
+                    }
+                    _ => {
+                        storage.unset(id as usize);
+                    }
+                }
+            }
+            None
+        };
+
+        // Archetype stored components can only have been removed if the entity had a location
+        if let Some(from) = from.filter(|_| !removed_from_archetype.is_empty()) {
+            let components: Vec<_> = self.archetypes[from.archetype]
+                .components()
+                .iter()
+                .copied()
+                .filter(|c| !removed_from_archetype.contains(c))
+                .collect();
+            let to = (!components.is_empty()).then(|| self.archetypes.get_or_insert(components));
+            self.move_entity(id, Some(from), to);
+        }
+        for c in present {
+            self.removals.component_removed(c, entity);
+        }
+        bundle
+    }
+
+    /// The ids of the components in `B`, which are registered and cached the first time `B` is
+    /// used. Panics if `B` contains the same kind of component more than once.
+    pub(crate) fn bundle_ids<B: Bundle>(&mut self) -> Arc<[ComponentId]> {
+        if let Some(ids) = self.bundles.get(&TypeId::of::<B>()) {
+            return ids.clone();
         }
+        let ids = B::component_ids(&mut self.component_registry);
+        let mut unique = ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(
+            unique.len(),
+            ids.len(),
+            "The bundle {} contains the same kind of component more than once",
+            any::type_name::<B>()
+        );
+        let ids: Arc<[ComponentId]> = ids.into();
+        self.bundles.insert(TypeId::of::<B>(), ids.clone());
+        ids
     }
 
     /// Despawns an entity, removing its components (if any). Returns `true` if the entity existed.
     pub fn despawn(&mut self, entity: Entity) -> bool {
-        if entity == self.resource_holder {
-            // This could happen if someone were to query for a resource (they're currently just
-            // components), get the entity, and try to delete it.
+        if self.entities.id(entity).is_none() {
             return false;
         }
+        self.detach(entity);
         self.entities
             .id(entity)
             .map(|id| {
                 // Since we just got the entity id, we know it exists.
                 self.entities.despawn_unchecked(id);
+                let location = self.archetypes.location(id);
                 for component in self.component_registry.entries_mut() {
-                    component.storage.unset(id as usize);
+                    let removed = match (component.storage.storage_type(), location) {
+                        (StorageType::Archetype, Some(location)) => {
+                            let contains =
+                                self.archetypes[location.archetype].contains(component.info.id());
+                            if contains {
+                                component.storage.swap_remove_at(location);
+                            }
+                            contains
+                        }
+                        (StorageType::Archetype, None) => false,
+                        _ => component.storage.unset(id as usize),
+                    };
+                    if removed {
+                        self.removals.component_removed(component.info.id(), entity);
+                    }
+                }
+                if let Some(location) = location {
+                    self.archetypes.swap_remove(location);
                 }
+                self.removals.entity_despawned(entity);
             })
             .is_some()
     }
 
+    /// Removes `entity` from its hierarchy and its relations. To keep the hierarchy consistent,
+    /// the entity is removed from the children of its parent and its children become roots.
+    fn detach(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+        if let Some(children) = self.remove::<Children>(entity) {
+            for child in &children {
+                self.remove::<Parent>(child);
+            }
+        }
+        self.relations.remove_entity(entity);
+    }
+
+    /// Moves `entity` with all of its components to `other`, where it is spawned as a new entity
+    /// which is returned. The kinds of components are matched between the worlds by
+    /// `ComponentRegistry::import`. Like when it is despawned, the entity is removed from its
+    /// hierarchy and its relations, since they are between entities of this world. Returns `None`
+    /// if `entity` does not exist.
+    pub fn move_entity_to(&mut self, other: &mut World, entity: Entity) -> Option<Entity> {
+        self.entities.id(entity)?;
+        self.detach(entity);
+        let (ids, buffer) = self.take_entity(entity)?;
+        let ids: Vec<ComponentId> = ids
+            .iter()
+            .map(|&id| {
+                other
+                    .component_registry
+                    .import(&self.component_registry, id)
+            })
+            .collect();
+        let moved = other.spawn();
+        // Safety: the buffer holds a component of every kind in `ids`, in order
+        unsafe {
+            other.insert_components(moved.get_id_unchecked(), &ids, |f| {
+                (0..buffer.len()).for_each(|i| f(buffer.get(i)))
+            });
+        }
+        Some(moved)
+    }
+
+    /// Moves every entity of `other` to this world, leaving `other` without entities, e.g. to
+    /// merge a scene loaded into a separate world. The entities are spawned as new entities, and
+    /// the hierarchy, the relations and the components which refer to them are mapped to the new
+    /// entities. See `ComponentRegistry::register_map_entities`. The resources stay in `other`.
+    /// Returns which entity was spawned for which entity of `other`.
+    pub fn append(&mut self, other: &mut World) -> EntityMap {
+        let entities: Vec<Entity> = other.entities.iter().collect();
+        let spawned = self.entities.reserve(entities.len());
+        let mut map = EntityMap::default();
+        for (&entity, &spawned) in entities.iter().zip(&spawned) {
+            map.insert(entity, spawned);
+        }
+        // The ids this world has for the kinds of components of `other`
+        let ids: HashMap<ComponentId, ComponentId> = other
+            .component_registry
+            .infos()
+            .map(|info| info.id())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|id| {
+                (
+                    id,
+                    self.component_registry
+                        .import(&other.component_registry, id),
+                )
+            })
+            .collect();
+
+        for (&entity, &spawned) in entities.iter().zip(&spawned) {
+            let (kinds, buffer) = other.take_entity(entity).unwrap();
+            let kinds: Vec<ComponentId> = kinds.iter().map(|id| ids[id]).collect();
+            for (i, &kind) in kinds.iter().enumerate() {
+                if let Some(map_entities) = self.component_registry[kind].info.map_entities_fn() {
+                    // Safety: the buffer holds a component of this kind at `i`
+                    unsafe { map_entities(buffer.get(i), &map) };
+                }
+            }
+            // Safety: the buffer holds a component of every kind in `kinds`, in order
+            unsafe {
+                self.insert_components(spawned.get_id_unchecked(), &kinds, |f| {
+                    (0..buffer.len()).for_each(|i| f(buffer.get(i)))
+                });
+            }
+        }
+        for (kind, source, target) in other.relations.drain() {
+            self.relations
+                .insert(kind, map.map(source), map.map(target));
+        }
+        map
+    }
+
+    /// Despawns `entity` without dropping its components, which are moved to the returned buffer
+    /// in the order of the returned kinds. Leaves the hierarchy and relations as they are.
+    fn take_entity(&mut self, entity: Entity) -> Option<(Vec<ComponentId>, ComponentBuffer)> {
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let registry = &mut self.component_registry;
+        let ids: Vec<ComponentId> = registry
+            .infos()
+            .map(|info| info.id())
+            .filter(|&c| {
+                !registry[c]
+                    .storage
+                    .get_entity_ptr(id as usize, location)
+                    .is_null()
+            })
+            .collect();
+        let buffer = ComponentBuffer::new(ids.iter().map(|&c| registry[c].storage.item_layout()));
+        for (i, &c) in ids.iter().enumerate() {
+            let storage = &mut registry[c].storage;
+            // Safety: the buffer has the layout of the kind at `i`, and the entity has a
+            // component of this kind
+            unsafe {
+                match (storage.storage_type(), location) {
+                    (StorageType::Archetype, Some(location)) => {
+                        storage.take_at_into(location, buffer.get(i))
+                    }
+                    _ => {
+                        storage.remove_into(id as usize, buffer.get(i));
+                    }
+                }
+            }
+            self.removals.component_removed(c, entity);
+        }
+        if let Some(location) = location {
+            self.archetypes.swap_remove(location);
+        }
+        self.entities.despawn_unchecked(id);
+        self.removals.entity_despawned(entity);
+        Some((ids, buffer))
+    }
+
+    /// Copies the entities of the world, their components and their relations, so the world can
+    /// be brought back to its current state with `restore`. See `Snapshot` for which components
+    /// are copied.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self, self.entities.snapshot(), self.relations.clone())
+    }
+
+    /// Brings the world back to the state it was in when `snapshot` was taken. Entities spawned
+    /// since are despawned, and those despawned since are spawned again with the same ids and
+    /// generations, so handles to them which had become stale are valid again. Components of the
+    /// kinds in the snapshot which were added since are removed, and those which were removed or
+    /// changed (see `Changed`) since are replaced by their copies. Components of other kinds are
+    /// kept by the entities which were alive all along. The change tick is not rolled back, so
+    /// every rewritten component is marked as changed. Panics if the snapshot was taken from a
+    /// world with other kinds of components.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        snapshot.check(&self.component_registry);
+        let spawned: Vec<Entity> = self
+            .entities
+            .iter()
+            .filter(|&e| !snapshot.exists(e))
+            .collect();
+        for entity in spawned {
+            self.despawn(entity);
+        }
+        self.entities.restore(snapshot.entities());
+        self.relations.clone_from(snapshot.relations());
+
+        let columns = snapshot.columns();
+        // The column and row of every component of an entity in the snapshot
+        let mut rows: HashMap<Entity, Vec<(usize, usize)>> = HashMap::new();
+        for (c, column) in columns.iter().enumerate() {
+            for (row, &entity) in column.entities.iter().enumerate() {
+                rows.entry(entity).or_default().push((c, row));
+            }
+        }
+        let buffer = ComponentBuffer::new(columns.iter().map(|c| c.layout));
+        let entities: Vec<Entity> = self.entities.iter().collect();
+        for entity in entities {
+            let rows = rows.remove(&entity).unwrap_or_default();
+            // Drop the components added since the snapshot was taken
+            for (c, column) in columns.iter().enumerate() {
+                if rows.iter().all(|&(i, _)| i != c) {
+                    // Safety: the buffer has the layout of the kind at `c`
+                    unsafe {
+                        if self.remove_raw(entity, column.id, buffer.get(c)) {
+                            self.component_registry[column.id]
+                                .storage
+                                .drop_ptr(buffer.get(c));
+                        }
+                    }
+                }
+            }
+
+            // Only rewrite the components which are missing or may have changed. Those changed
+            // during the tick of the snapshot are rewritten too, since they may have changed after
+            // it was taken.
+            let id = entity.get_id_unchecked();
+            let location = self.archetypes.location(id);
+            let rows: Vec<(usize, usize)> = rows
+                .into_iter()
+                .filter(|&(c, _)| {
+                    self.component_registry[columns[c].id]
+                        .storage
+                        .get_entity_ticks(id as usize, location)
+                        .is_none_or(|ticks| ticks.changed >= snapshot.change_tick())
+                })
+                .collect();
+            if rows.is_empty() {
+                continue;
+            }
+            let ids: Vec<ComponentId> = rows.iter().map(|&(c, _)| columns[c].id).collect();
+            // Safety: every component is copied to the slot of its column in the buffer, which
+            // has its layout, and is then passed on in the order of `ids`
+            unsafe {
+                self.insert_components(id, &ids, |f| {
+                    for &(c, row) in &rows {
+                        columns[c].copy_to(row, buffer.get(c));
+                        f(buffer.get(c));
+                    }
+                });
+            }
+        }
+    }
+
+    /// Despawns `entity` together with its children, their children and so on. Returns `false` if
+    /// `entity` did not exist.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        if self.entities.id(entity).is_none() {
+            return false;
+        }
+        self.remove_parent(entity);
+        let mut stack = vec![entity];
+        while let Some(entity) = stack.pop() {
+            if let Some(children) = self.remove::<Children>(entity) {
+                stack.extend(children.0);
+            }
+            self.despawn(entity);
+        }
+        true
+    }
+
+    /// Makes `parent` the parent of `child`, removing `child` from the children of its previous
+    /// parent if it had one. Returns `false` if either entity does not exist, or if `child` is
+    /// `parent` or one of its ancestors since that would create a cycle.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        let alive = |e| self.entities.id(e).is_some();
+        if !alive(child) || !alive(parent) {
+            return false;
+        }
+        let mut ancestor = Some(parent);
+        while let Some(a) = ancestor {
+            if a == child {
+                return false;
+            }
+            ancestor = self.parent(a);
+        }
+        if self.parent(child) == Some(parent) {
+            return true;
+        }
+
+        self.remove_parent(child);
+        self.add(child, Parent(parent));
+        if let Some(children) = self.get_mut::<Children>(parent) {
+            children.0.push(child);
+        } else {
+            self.add(parent, Children(vec![child]));
+        }
+        true
+    }
+
+    /// Removes `child` from the children of its parent, making it a root of the hierarchy.
+    /// Returns the parent, or `None` if `child` did not have one.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let parent = self.remove::<Parent>(child)?.get();
+        if let Some(children) = self.get_mut::<Children>(parent) {
+            children.0.retain(|&c| c != child);
+            if children.is_empty() {
+                self.remove::<Children>(parent);
+            }
+        }
+        Some(parent)
+    }
+
+    /// Returns the parent of `entity`, see `set_parent`.
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get::<Parent>(entity).map(|parent| parent.get())
+    }
+
+    /// Returns the children of `entity`, see `set_parent`. Panics if the children currently are
+    /// mutably borrowed in a query.
+    pub fn children(&self, entity: Entity) -> Ref<'_, [Entity]> {
+        match self.get::<Children>(entity) {
+            Some(children) => Ref::map(children, Children::as_slice),
+            None => Ref::from_static(&[]),
+        }
+    }
+
+    /// Relates `source` to `target` with a relation of kind `R`, e.g.
+    /// `world.relate::<Targets>(turret, enemy)`. `R` is only used to tell kinds of relations
+    /// apart. An entity can be related to any number of others, and relations are removed
+    /// automatically when either entity is despawned. Returns `false` if either entity does not
+    /// exist.
+    pub fn relate<R: 'static>(&mut self, source: Entity, target: Entity) -> bool {
+        let alive = |e| self.entities.id(e).is_some();
+        if !alive(source) || !alive(target) {
+            return false;
+        }
+        self.relations.insert(TypeId::of::<R>(), source, target);
+        true
+    }
+
+    /// Removes the relation of kind `R` from `source` to `target`. Returns `false` if there was
+    /// no such relation.
+    pub fn unrelate<R: 'static>(&mut self, source: Entity, target: Entity) -> bool {
+        self.relations.remove(TypeId::of::<R>(), source, target)
+    }
+
+    /// Returns the entities `source` is related to with relations of kind `R`, in the order the
+    /// relations were added. See `relate`.
+    pub fn related<R: 'static>(&self, source: Entity) -> &[Entity] {
+        self.relations.targets(TypeId::of::<R>(), source)
+    }
+
+    /// Returns the entities which are related to `target` with relations of kind `R`, i.e. the
+    /// reverse of `related`.
+    pub fn related_to<R: 'static>(&self, target: Entity) -> &[Entity] {
+        self.relations.sources(TypeId::of::<R>(), target)
+    }
+
+    /// Returns `true` if `source` is related to `target` with a relation of kind `R`.
+    pub fn is_related<R: 'static>(&self, source: Entity, target: Entity) -> bool {
+        self.related::<R>(source).contains(&target)
+    }
+
+    /// Adds an index of the components of kind `T` by the keys `key` computes for them, replacing
+    /// the index of `T` by `K` if there already is one. See `index::Index`.
+    pub fn add_index<T, K>(&mut self, key: fn(&T) -> K)
+    where
+        T: 'static,
+        K: Hash + Eq + Clone + Send + 'static,
+    {
+        let index = Mutex::new(Index::new(key));
+        self.indices
+            .insert(TypeId::of::<Index<T, K>>(), Box::new(index));
+    }
+
+    /// Returns the index of the components of kind `T` by keys of type `K`, after indexing the
+    /// components changed since it was last looked up. Panics if the index has not been added
+    /// with `add_index` or if the components are currently borrowed mutably in a query.
+    pub fn index<T, K>(&self) -> IndexRef<'_, T, K>
+    where
+        T: 'static,
+        K: Hash + Eq + Clone + Send + 'static,
+    {
+        let index = self
+            .indices
+            .get(&TypeId::of::<Index<T, K>>())
+            .and_then(|index| index.downcast_ref::<Mutex<Index<T, K>>>())
+            .unwrap_or_else(|| {
+                panic!(
+                    "No index of {} by {} has been added to the world",
+                    any::type_name::<T>(),
+                    any::type_name::<K>()
+                )
+            });
+        IndexRef::new(index.lock().unwrap(), self)
+    }
+
+    /// Returns an entity with the `Name` `name`, or `None` if there is none. If several entities
+    /// have the name, the one named first is returned.
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.index::<Name, Name>().get(name).first().copied()
+    }
+
+    /// Queries for the components in `Q`, e.g. `world.query::<(&mut Position, &Velocity)>()`. If
+    /// this tries to borrow access to a component which has already been handed out (unless every
+    /// borrow is immutable), or if `Q` itself accesses a component mutably more than once, a
+    /// `BorrowMutError` indicating one (of the possibly many) components which was inaccessible
+    /// is returned.
+    pub fn try_query<Q: WorldQuery>(&self) -> Result<TypedQuery<'_, Q>, BorrowMutError> {
+        TypedQuery::new(self)
+    }
+
+    /// Queries for the components in `Q`. If thats not possible (see `try_query`) this function
+    /// panics.
+    pub fn query<Q: WorldQuery>(&self) -> TypedQuery<'_, Q> {
+        self.try_query().unwrap()
+    }
+
+    /// Same as `try_query` but only for entities matching the filters in `F`, e.g.
+    /// `world.try_query_filtered::<&mut Position, (With<Player>, Changed<Velocity>)>()`.
+    pub fn try_query_filtered<Q: WorldQuery, F: WorldFilter>(
+        &self,
+    ) -> Result<TypedQuery<'_, Q, F>, BorrowMutError> {
+        TypedQuery::new(self)
+    }
+
+    /// Same as `query` but only for entities matching the filters in `F`.
+    pub fn query_filtered<Q: WorldQuery, F: WorldFilter>(&self) -> TypedQuery<'_, Q, F> {
+        self.try_query_filtered().unwrap()
+    }
+
     /// Tries to query for a set of components. If this tries to borrow access to a component which
     /// has already been handed out (unless every borrow is immutable), a `QueryError` indicating
     /// one (of the possible many) components which was already inaccessible.
-    pub fn try_query<'a, 'q>(
+    pub fn try_query_raw<'a, 'q>(
         &'a self,
         query: &'q Query,
     ) -> Result<QueryResponse<'a, 'q>, BorrowMutError> {
+        self.borrow_query(Cow::Borrowed(query))
+    }
+
+    /// Tries to query for a set of components. If thats not possible (see `try_query_raw`) this
+    /// function panics.
+    pub fn query_raw<'a, 'q>(&'a self, query: &'q Query) -> QueryResponse<'a, 'q> {
+        self.try_query_raw(query).unwrap()
+    }
+
+    pub(crate) fn borrow_query<'q>(
+        &self,
+        query: Cow<'q, Query>,
+    ) -> Result<QueryResponse<'_, 'q>, BorrowMutError> {
         let mut entries = Vec::with_capacity(query.components().len());
+        let mut ids = Vec::with_capacity(query.components().len());
         for c in query.components() {
             match self.component_registry.try_borrow(c.id, c.mutable) {
                 Some(entry) => entries.push(entry),
                 None => return Err(BorrowMutError::new(c.id)),
             }
+            ids.push(c.id);
         }
-        Ok(QueryResponse::new(self, query, entries))
-    }
-
-    /// Tries to query for a set of components. If thats not possible (see `try_query`) this
-    /// function panics.
-    pub fn query<'a, 'q>(&'a self, query: &'q Query) -> QueryResponse<'a, 'q> {
-        self.try_query(query).unwrap()
+        // Filters only need to read the storages, so they share the entries of the components in
+        // the query and of each other.
+        let mut filter_entries = Vec::with_capacity(query.filters().len());
+        for f in query.filters().iter().map(ComponentFilter::id) {
+            let index = match ids.iter().position(|&id| id == f) {
+                Some(index) => index,
+                None => match self.component_registry.try_borrow(f, false) {
+                    Some(entry) => {
+                        entries.push(entry);
+                        ids.push(f);
+                        entries.len() - 1
+                    }
+                    None => return Err(BorrowMutError::new(f)),
+                },
+            };
+            filter_entries.push(index);
+        }
+        Ok(QueryResponse::new(self, query, entries, filter_entries))
     }
 
-    /// Panics if the component currently is mutably borrowed in a query
-    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+    /// Borrows the component of kind `T` of `entity` until the returned `Ref` is dropped. Panics
+    /// if the component currently is mutably borrowed in a query.
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
         let comp_id = self.component_registry.id::<T>()?;
 
-        self.entities
-            .id(entity)
-            .and_then(|id| unsafe { self.component_registry[comp_id].storage.get(id as usize) })
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let entry = self.borrow_component(comp_id);
+        let ptr = entry.get().storage.get_entity_ptr(id as usize, location);
+        let ptr = NonNull::new(ptr as *mut T)?;
+        // Safety: the component is owned by the storage of the entry
+        Some(unsafe { Ref::new(entry, ptr) })
+    }
+
+    /// Borrows the components of kind `comp_id` for reading. Panics if they currently are
+    /// mutably borrowed in a query.
+    fn borrow_component(&self, comp_id: ComponentId) -> ComponentEntryRef {
+        self.component_registry
+            .try_borrow(comp_id, false)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Tried to access the component with id {:?} while it is mutably borrowed",
+                    comp_id
+                )
+            })
     }
 
     /// Panics if the component currently is borrowed in a query
     pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
         let comp_id = self.component_registry.id::<T>()?;
 
-        self.entities.id(entity).and_then(|id| unsafe {
-            self.component_registry[comp_id]
-                .storage
-                .get_mut(id as usize)
-        })
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let storage = &mut self.component_registry[comp_id].storage;
+        storage.set_changed(id as usize, location, self.change_tick);
+        unsafe {
+            storage
+                .get_entity_mut_ptr(id as usize, location)
+                .cast::<T>()
+                .as_mut()
+        }
+    }
+
+    /// Returns the component with id `comp_id` of `entity` as a `&dyn Reflect`, or `None` if the
+    /// entity has no such component or its kind has no registered reflection. See
+    /// `ComponentRegistry::register_reflect`. Panics if the component currently is mutably
+    /// borrowed in a query.
+    pub fn get_reflect(
+        &self,
+        entity: Entity,
+        comp_id: ComponentId,
+    ) -> Option<Ref<'_, dyn Reflect>> {
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let entry = self.borrow_component(comp_id);
+        let reflect = entry.get().info.reflect()?;
+        let ptr = entry.get().storage.get_entity_ptr(id as usize, location);
+        if ptr.is_null() {
+            return None;
+        }
+        let value = NonNull::from(unsafe { reflect.as_reflect(ptr) });
+        // Safety: the component is owned by the storage of the entry
+        Some(unsafe { Ref::new(entry, value) })
+    }
+
+    /// Same as `get_reflect` but for mutable access, which marks the component as changed.
+    /// Panics if the component currently is borrowed in a query.
+    pub fn get_reflect_mut(
+        &mut self,
+        entity: Entity,
+        comp_id: ComponentId,
+    ) -> Option<&mut dyn Reflect> {
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let entry = &mut self.component_registry[comp_id];
+        let reflect = entry.info.reflect()?.clone();
+        let ptr = entry.storage.get_entity_mut_ptr(id as usize, location);
+        if ptr.is_null() {
+            return None;
+        }
+        entry
+            .storage
+            .set_changed(id as usize, location, self.change_tick);
+        Some(unsafe { reflect.as_reflect_mut(ptr) })
+    }
+
+    /// Returns the ids of the components of `entity` whose kinds have registered reflections,
+    /// e.g. to show them in an inspector. See `get_reflect`.
+    pub fn reflect_components(&self, entity: Entity) -> Vec<ComponentId> {
+        self.component_registry
+            .infos()
+            .filter(|info| info.reflect().is_some())
+            .map(|info| info.id())
+            .filter(|&id| self.get_reflect(entity, id).is_some())
+            .collect()
+    }
+
+    /// Adds the dynamic component of kind `comp_id` made of `bytes` to `entity`, see
+    /// `dynamic::Schema`. Returns the same as `add`. Panics if the kind of component is not
+    /// dynamic or `bytes` does not have the size of its components.
+    pub fn add_dynamic(&mut self, entity: Entity, comp_id: ComponentId, bytes: &[u8]) -> bool {
+        let schema = self.component_registry[comp_id]
+            .info
+            .schema()
+            .unwrap_or_else(|| panic!("The component with id {:?} is not dynamic", comp_id));
+        let layout = schema.layout();
+        assert_eq!(
+            bytes.len(),
+            layout.size(),
+            "Wrong size for a {} component",
+            schema.name()
+        );
+        // The bytes may not be aligned like the component
+        let buffer = ComponentBuffer::new([layout]);
+        // Safety: the buffer holds a component of this kind, which is plain data
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.get(0), bytes.len());
+            self.add_raw(entity, buffer.get(0), comp_id)
+        }
+    }
+
+    /// Returns the dynamic component of kind `comp_id` of `entity`, or `None` if the entity has
+    /// no such component or the kind of component is not dynamic. Panics if the component
+    /// currently is mutably borrowed in a query.
+    pub fn get_dynamic(&self, entity: Entity, comp_id: ComponentId) -> Option<DynamicRef<'_>> {
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let entry = self.borrow_component(comp_id);
+        let schema = self.component_registry[comp_id].info.schema()?;
+        let ptr = entry.get().storage.get_entity_ptr(id as usize, location);
+        (!ptr.is_null()).then(|| unsafe { DynamicRef::borrowed(schema, ptr, entry) })
+    }
+
+    /// Same as `get_dynamic` but for mutable access, which marks the component as changed.
+    /// Panics if the component currently is borrowed in a query.
+    pub fn get_dynamic_mut(
+        &mut self,
+        entity: Entity,
+        comp_id: ComponentId,
+    ) -> Option<DynamicMut<'_>> {
+        let id = self.entities.id(entity)?;
+        let location = self.archetypes.location(id);
+        let entry = &mut self.component_registry[comp_id];
+        let schema = entry.info.schema()?;
+        let ptr = entry.storage.get_entity_mut_ptr(id as usize, location);
+        if ptr.is_null() {
+            return None;
+        }
+        entry
+            .storage
+            .set_changed(id as usize, location, self.change_tick);
+        Some(unsafe { DynamicMut::new(schema, ptr) })
+    }
+
+    /// Removes the dynamic component of kind `comp_id` from `entity`, returning it or `None` if
+    /// the entity does not have it. Panics if the kind of component is not dynamic.
+    pub fn remove_dynamic(
+        &mut self,
+        entity: Entity,
+        comp_id: ComponentId,
+    ) -> Option<DynamicComponent> {
+        let schema = self.component_registry[comp_id]
+            .info
+            .schema()
+            .unwrap_or_else(|| panic!("The component with id {:?} is not dynamic", comp_id))
+            .clone();
+        let buffer = ComponentBuffer::new([schema.layout()]);
+        // Safety: the buffer has the layout of the component kind
+        unsafe {
+            if !self.remove_raw(entity, comp_id, buffer.get(0)) {
+                return None;
+            }
+            let bytes = slice::from_raw_parts(buffer.get(0), schema.layout().size()).to_vec();
+            Some(DynamicComponent::from_bytes(schema, bytes).unwrap())
+        }
+    }
+
+    /// Same as `query_raw` but gives access to the dynamic components in the query through
+    /// their schemas, see `DynamicRow`.
+    pub fn query_dynamic<'a, 'q>(&'a self, query: &'q Query) -> DynamicQuery<'a, 'q> {
+        let schemas = query
+            .components()
+            .iter()
+            .map(|c| self.component_registry[c.id].info.schema().cloned())
+            .collect();
+        DynamicQuery::new(self.query_raw(query), schemas)
+    }
+
+    /// The current change tick of the world. Components which are added or accessed mutably are
+    /// marked with the tick at which that happened, which is what `Added` and `Changed` filters
+    /// look at. See `ComponentTicks`.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// Starts a new change tick, e.g. at the start of every frame. Returns the new tick.
+    pub fn increment_change_tick(&mut self) -> u32 {
+        self.change_tick += 1;
+        self.change_tick
+    }
+
+    /// Returns all entities whose `T` was added or accessed mutably after `tick`.
+    pub fn changed_since<T: 'static>(&self, tick: u32) -> Vec<Entity> {
+        self.query_filtered::<Entity, Changed<T>>()
+            .since(tick)
+            .iter()
+            .collect()
+    }
+
+    /// Returns all entities whose `T` was added after `tick`.
+    pub fn added_since<T: 'static>(&self, tick: u32) -> Vec<Entity> {
+        self.query_filtered::<Entity, Added<T>>()
+            .since(tick)
+            .iter()
+            .collect()
+    }
+
+    /// Returns the entities which have had their `T` removed, by `remove` or by being despawned,
+    /// since the removals were last cleared. See `clear_removals`.
+    pub fn removed<T: 'static>(&self) -> RemovedComponents<'_, T> {
+        let entities = match self.component_registry.id::<T>() {
+            Some(id) => self.removals.removed(id),
+            None => &[],
+        };
+        RemovedComponents::new(entities)
+    }
+
+    /// Same as `removed` but takes the entities out of the log, so they will not be returned
+    /// again.
+    pub fn drain_removed<T: 'static>(&mut self) -> impl Iterator<Item = Entity> + '_ {
+        self.component_registry
+            .id::<T>()
+            .map(|id| self.removals.drain_removed(id))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Returns the entities which have been despawned since the removals were last cleared. See
+    /// `clear_removals`.
+    pub fn despawned(&self) -> &[Entity] {
+        self.removals.despawned()
+    }
+
+    /// Same as `despawned` but takes the entities out of the log, so they will not be returned
+    /// again.
+    pub fn drain_despawned(&mut self) -> vec::Drain<'_, Entity> {
+        self.removals.drain_despawned()
+    }
+
+    /// Clears the log of removed components and despawned entities, e.g. at the start of every
+    /// frame. Removals made through a `CommandBuffer` are logged when it is applied.
+    pub fn clear_removals(&mut self) {
+        self.removals.clear();
     }
 
     /// Get a reference to the world's entities.
@@ -172,6 +1306,11 @@ impl World {
         &self.entities
     }
 
+    /// Get a reference to the world's archetypes.
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
     /// Get a reference to the world's component registry.
     pub fn component_registry(&self) -> &ComponentRegistry {
         &self.component_registry
@@ -181,4 +1320,14 @@ impl World {
     pub fn component_registry_mut(&mut self) -> &mut ComponentRegistry {
         &mut self.component_registry
     }
+
+    /// Get a reference to the world's resources.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Get a mutable reference to the world's resources.
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
 }
@@ -41,7 +41,7 @@ impl CameraController {
             }
             DeviceEvent::MouseWheel {
                 delta: MouseScrollDelta::LineDelta(_, dy),
-            } => self.movement_speed *= (-dy as f32 / 100.).exp(),
+            } => self.movement_speed *= (-dy / 100.).exp(),
             DeviceEvent::MouseWheel {
                 delta: MouseScrollDelta::PixelDelta(d),
             } => self.movement_speed *= (-d.y as f32 / 100.).exp(),
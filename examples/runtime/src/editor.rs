@@ -1,4 +1,4 @@
-use std::{ops::ControlFlow, time::Instant};
+use std::{collections::VecDeque, ops::ControlFlow, time::Instant};
 
 use anyhow::Context as _;
 use egui::{Context as EguiContext, Slider};
@@ -10,7 +10,15 @@ use winit::{
 
 use common::{Vec2, Vec3};
 use game_engine::{
-    physics,
+    ecs::{
+        event::{EventReader, Events},
+        name::Name,
+        query::With,
+        schedule::{Stage, System},
+        snapshot::Snapshot,
+        Entity,
+    },
+    physics::{self, Collision, Rigidbody},
     rendering::{Line, Renderer},
     Engine,
 };
@@ -21,6 +29,13 @@ use crate::{
     window::{Window, WindowMode},
 };
 
+/// How many frames can be rewound, about ten seconds at 60 frames per second.
+const HISTORY_LEN: usize = 600;
+
+/// An event sent when an entity is selected in the editor.
+#[derive(Debug, Clone, Copy)]
+pub struct EntitySelected(pub Entity);
+
 pub struct Editor {
     engine: Engine,
     window: Window,
@@ -29,6 +44,12 @@ pub struct Editor {
     camera_controller: CameraController,
     scene: PhysicsScene,
     last_frame: Instant,
+    selected: Option<Entity>,
+    collisions: EventReader<Collision>,
+    collision_count: usize,
+    // Snapshots of the world taken before each of the last frames, the latest at the back
+    history: VecDeque<Snapshot>,
+    rewinding: bool,
 }
 
 impl Editor {
@@ -56,6 +77,22 @@ impl Editor {
             )
             .with_context(|| "failed to create the renderer")?,
         );
+        engine.world.add_event::<EntitySelected>();
+        let mut selections = EventReader::<EntitySelected>::new();
+        engine.add_system(
+            Stage::Update,
+            System::new("log selection", move |world| {
+                let events = world.res::<Events<EntitySelected>>().unwrap();
+                for &EntitySelected(entity) in selections.read(&events) {
+                    match world.get::<Name>(entity) {
+                        Some(name) => log::info!("Selected {} ({:?})", name, entity),
+                        None => log::info!("Selected {:?}", entity),
+                    }
+                }
+            })
+            .reads_resource::<Events<EntitySelected>>()
+            .reads::<Name>(),
+        );
 
         let camera_controller = CameraController::new(
             10.0,
@@ -82,6 +119,11 @@ impl Editor {
                 camera_controller,
                 scene,
                 last_frame: Instant::now(),
+                selected: None,
+                collisions: EventReader::new(),
+                collision_count: 0,
+                history: VecDeque::new(),
+                rewinding: false,
             },
         ))
     }
@@ -162,9 +204,42 @@ impl Editor {
         let dt = now.duration_since(self.last_frame).as_secs_f32();
         self.last_frame = now;
 
-        self.engine.update();
+        if self.rewinding {
+            if let Some(snapshot) = self.history.pop_back() {
+                // Start a new tick so the restored components are seen as changed since the
+                // last upload of the scene
+                self.engine.world.increment_change_tick();
+                self.engine.world.restore(&snapshot);
+            }
+        } else {
+            // Only keep the states the simulation stepped from, so every frame rewinds a step.
+            // A snapshot copies every clonable component (about 1ms for 10k entities, see the
+            // `snapshot_benchmark` test in ecs), so it is only taken when a step is due.
+            let snapshot = self.engine.step_due().then(|| self.engine.world.snapshot());
+            let stepped = self.engine.update();
+            if let Some(snapshot) = snapshot.filter(|_| stepped) {
+                if self.history.len() == HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.history.push_back(snapshot);
+            }
+        }
         self.scene.update(&mut self.engine);
 
+        if let Some(collisions) = self.engine.world.resource::<Events<Collision>>() {
+            self.collision_count = self.collisions.read(&collisions).count();
+        }
+        let bodies: Vec<(Entity, String)> = self
+            .engine
+            .world
+            .query_filtered::<(Entity, Option<&Name>), With<Rigidbody>>()
+            .iter()
+            .map(|(entity, name)| match name {
+                Some(name) => (entity, name.to_string()),
+                None => (entity, format!("{:?}", entity)),
+            })
+            .collect();
+
         self.camera_controller
             .update_camera(dt, &mut self.engine.renderer.camera);
         self.engine.renderer.update_camera();
@@ -198,19 +273,30 @@ impl Editor {
                         ui.label("Gravity");
                         ui.add(Slider::new(&mut gravity.0.y, -20.0..=20.0).text("k_q"));
                     }
+
+                    ui.label(format!("Collisions: {}", self.collision_count));
+                    ui.checkbox(
+                        &mut self.rewinding,
+                        format!("Rewind ({} frames)", self.history.len()),
+                    );
+                    ui.collapsing("Bodies", |ui| {
+                        for (entity, label) in bodies {
+                            let selected = self.selected == Some(entity);
+                            let label = ui.selectable_label(selected, label);
+                            if label.clicked() && !selected {
+                                self.selected = Some(entity);
+                                self.engine.world.send_event(EntitySelected(entity));
+                            }
+                        }
+                    });
                 });
         });
 
-        let lines = self
-            .engine
-            .world
-            .resource::<Vec<Line>>()
-            .map(|v| v.as_slice())
-            .unwrap_or(&[]);
+        let lines = self.engine.world.resource::<Vec<Line>>();
 
         if self.window.inner_size() != (0, 0) {
             match self.engine.renderer.render(
-                lines,
+                lines.as_deref().map_or(&[], Vec::as_slice),
                 &[self.scene.light],
                 &self.egui_context,
                 full_output,
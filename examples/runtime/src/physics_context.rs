@@ -3,7 +3,11 @@ use rand::Rng as _;
 
 use common::{Quaternion, Transform, Vec3};
 use game_engine::{
-    ecs::query_iter,
+    ecs::{
+        name::Name,
+        prefab::Prefab,
+        query::{Changed, With},
+    },
     physics::{self, Collider, CubeCollider, PhysicsMaterial, Rigidbody, SphereCollider},
     rendering::{model::ModelIndex, Light, Line},
     Engine,
@@ -17,8 +21,8 @@ pub struct PhysicsScene {
     pub ball_model: ModelIndex,
     // TODO: move into world
     pub light: Light,
-    // TODO: move into world
-    pub extra_dt: f32,
+    // The change tick at which the transforms were last uploaded to the renderer
+    uploaded_at: Option<u32>,
 }
 
 impl PhysicsScene {
@@ -35,26 +39,21 @@ impl PhysicsScene {
         world.add_resource(physics::Gravity::default());
         world.add_resource::<Vec<Line>>(vec![]);
 
-        let platform = world.spawn();
-        world.add(
-            platform,
+        world.spawn_with((
             Transform {
                 position: Vec3::new(0.0, 0.0, 0.0),
                 rotation: Quaternion::rotation_x(10.0f32.to_radians()),
                 scale: Vec3::new(10.0, 1.0, 10.0),
             },
-        );
-        world.add(platform, Rigidbody::new_static());
-        world.add(
-            platform,
+            Rigidbody::new_static(),
             Collider::Cube(CubeCollider::new(Vec3::one(), physics_material)),
-        );
+            Name::new("Ground"),
+        ));
 
-        for i in 0..40 {
-            let entity = world.spawn();
+        let body = Prefab::new().with(Rigidbody::new(1.));
+        let bodies = (0..40).map(|i| {
             let scale = rng.gen_range(1.0..1.5);
-            world.add(
-                entity,
+            (
                 Transform {
                     position: Vec3::new(
                         rng.gen_range(-10.0..10.0),
@@ -67,17 +66,19 @@ impl PhysicsScene {
                         .rotated_z(rng.gen_range(0.0f32..360.0f32).to_radians()),
                     scale: Vec3::broadcast(scale),
                 },
-            );
-            world.add(entity, Rigidbody::new(1.));
-            world.add(
-                entity,
                 if i < 20 {
                     Collider::Cube(CubeCollider::new(Vec3::one(), physics_material))
                 } else {
                     Collider::Sphere(SphereCollider::new(1., physics_material))
                 },
-            );
-        }
+                if i < 20 {
+                    Name::from(format!("Cube {}", i))
+                } else {
+                    Name::from(format!("Ball {}", i - 20))
+                },
+            )
+        });
+        body.spawn_batch(world, bodies);
 
         Ok(Self {
             cube_model: engine
@@ -95,20 +96,43 @@ impl PhysicsScene {
                 k_linear: 0.0014,
                 k_quadratic: 0.000007,
             },
-            extra_dt: 0.0,
+            uploaded_at: None,
         })
     }
 
     pub fn update(&mut self, engine: &mut Engine) {
+        if let Some(tick) = self.uploaded_at {
+            let changed = engine
+                .world
+                .query_filtered::<(), (With<Collider>, Changed<Transform>)>()
+                .since(tick)
+                .iter()
+                .next()
+                .is_some()
+                || engine
+                    .world
+                    .query_filtered::<(), Changed<Collider>>()
+                    .since(tick)
+                    .iter()
+                    .next()
+                    .is_some()
+                || !engine.world.removed::<Collider>().is_empty();
+            if !changed {
+                return;
+            }
+        }
+        self.uploaded_at = Some(engine.world.change_tick());
+
         let mut mgr = engine.renderer.get_models_mut();
         let mut cube_transforms = vec![];
         let mut ball_transforms = vec![];
-        query_iter!(engine.world, (transform: Transform, collider: Collider) => {
+        for (transform, collider) in engine.world.query::<(&Transform, &Collider)>().iter() {
             match collider {
                 Collider::Cube(_) => &mut cube_transforms,
                 Collider::Sphere(_) => &mut ball_transforms,
-            }.push(*transform);
-        });
+            }
+            .push(*transform);
+        }
         mgr.set_transforms(self.cube_model, cube_transforms);
         mgr.set_transforms(self.ball_model, ball_transforms);
     }
@@ -1,27 +1,101 @@
 use std::time::Instant;
 
-use ecs::World;
-use rendering::Renderer;
+use common::{GlobalTransform, Transform};
+use ecs::{
+    name::Name,
+    scene::SceneRegistry,
+    schedule::{Schedule, Stage, System},
+    World,
+};
+use physics::{Collider, Collision, Gravity, Rigidbody};
+use rendering::{Light, Renderer};
 
-use crate::{physics_systems, time::TIME_STEP, Time};
+use crate::{physics_systems, time::TIME_STEP, transform_systems, Time, TIME_SYSTEM};
+
+/// The name of the system updating the events added to the world, in `Stage::PreUpdate`. Events
+/// sent during a time step can be read until the end of the next one, see `ecs::event::Events`.
+pub const EVENTS_SYSTEM: &str = "events";
 
 pub struct Engine {
     pub renderer: Renderer,
     pub world: World,
+    /// The systems run every time step. Includes `EVENTS_SYSTEM` and `TIME_SYSTEM` in
+    /// `Stage::PreUpdate`, `PHYSICS_SYSTEM` in `Stage::Physics` and `TRANSFORM_SYSTEM` in
+    /// `Stage::PostUpdate`, which other systems can be ordered relative to. The physics system
+    /// sends a `physics::Collision` event for every collision.
+    pub schedule: Schedule,
+    /// The components and resources saved in scenes, see `ecs::scene::Scene`. Includes the
+    /// hierarchy, `Transform`, `Rigidbody`, `Collider`, `Gravity` and `Light`.
+    pub scene_registry: SceneRegistry,
 
     last_update: Option<Instant>,
 }
 
 impl Engine {
     pub fn new(renderer: Renderer) -> Self {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(
+                Stage::PreUpdate,
+                System::exclusive(EVENTS_SYSTEM, World::update_events),
+            )
+            .add_system(
+                Stage::PreUpdate,
+                System::exclusive(TIME_SYSTEM, Time::system),
+            )
+            .add_system(Stage::Physics, physics_systems::system())
+            .add_system(Stage::PostUpdate, transform_systems::system());
+
+        let mut scene_registry = SceneRegistry::new();
+        // Fixed names so saved scenes still load after the types are moved or renamed
+        scene_registry
+            .register_named::<Transform>("Transform")
+            .register_named::<Rigidbody>("Rigidbody")
+            .register_named::<Collider>("Collider")
+            .register_named::<Gravity>("Gravity")
+            .register_named::<Light>("Light");
+
+        // Lets inspectors show and edit the components, see `World::get_reflect`
+        let mut world = World::default();
+        world.add_event::<Collision>();
+        let registry = world.component_registry_mut();
+        registry.register_reflect::<Transform>();
+        registry.register_reflect::<GlobalTransform>();
+        registry.register_reflect::<Rigidbody>();
+        registry.register_reflect::<Collider>();
+        registry.register_reflect::<Name>();
+        // Lets entities be cloned, see `World::clone_entity`
+        registry.register_clone::<Transform>();
+        registry.register_clone::<GlobalTransform>();
+        registry.register_clone::<Rigidbody>();
+        registry.register_clone::<Collider>();
+        registry.register_clone::<Name>();
+
         Self {
             renderer,
-            world: World::default(),
+            world,
+            schedule,
+            scene_registry,
             last_update: None,
         }
     }
 
-    pub fn update(&mut self) {
+    /// Adds a system to be run every time step. See `Schedule::add_system`.
+    pub fn add_system(&mut self, stage: Stage, system: System) -> &mut Self {
+        self.schedule.add_system(stage, system);
+        self
+    }
+
+    /// Whether a time step has elapsed since the last update, so the next `update` will run the
+    /// systems.
+    pub fn step_due(&self) -> bool {
+        self.last_update
+            .is_none_or(|last_update| last_update.elapsed() >= TIME_STEP)
+    }
+
+    /// Runs the systems once for every time step elapsed since the last update, at most twice.
+    /// Returns `false` if no time step has elapsed, so the systems were not run.
+    pub fn update(&mut self) -> bool {
         let now = Instant::now();
         let mut last_update = if let Some(last_update) = self.last_update {
             last_update
@@ -30,16 +104,19 @@ impl Engine {
         };
         let mut delta = now - last_update;
 
+        // Everything changed or removed from here until the next update belongs to this frame
+        self.world.increment_change_tick();
+        self.world.clear_removals();
+
         let mut i = 0;
         while delta >= TIME_STEP && i < 2 {
-            Time::system(&mut self.world);
-
-            physics_systems::update(&mut self.world);
+            self.schedule.run(&mut self.world);
 
             last_update += delta;
             delta -= TIME_STEP;
             i += 1;
         }
         self.last_update = Some(last_update);
+        i > 0
     }
 }
@@ -1,10 +1,13 @@
 mod engine;
 mod physics_systems;
 mod time;
+mod transform_systems;
 
 pub use ecs;
 pub use physics;
 pub use rendering;
 
-pub use engine::Engine;
-pub use time::Time;
+pub use engine::{Engine, EVENTS_SYSTEM};
+pub use physics_systems::PHYSICS_SYSTEM;
+pub use time::{Time, TIME_SYSTEM};
+pub use transform_systems::TRANSFORM_SYSTEM;
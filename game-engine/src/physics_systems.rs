@@ -1,27 +1,47 @@
 use common::{Transform, Vec3};
-use ecs::{query_iter, query_iter_combs, World};
+use ecs::{event::Events, schedule::System, Entity, World};
 
-use physics::{collide, Collider, Gravity, Rigidbody};
+use physics::{collide, Collider, Collision, Gravity, Rigidbody};
 
 use crate::Time;
 
-pub fn update(world: &mut World) {
+/// The name of the system simulating physics, in `Stage::Physics`.
+pub const PHYSICS_SYSTEM: &str = "physics";
+
+pub fn system() -> System {
+    System::new(PHYSICS_SYSTEM, update)
+        .writes::<Transform>()
+        .writes::<Rigidbody>()
+        .reads::<Collider>()
+        .reads_resource::<Gravity>()
+        .reads_resource::<Time>()
+        .writes_resource::<Events<Collision>>()
+}
+
+pub fn update(world: &World) {
     let gravity = world
         .resource::<Gravity>()
         .map(|g| g.0)
         .unwrap_or_else(Vec3::zero);
     let dt = world.resource::<Time>().unwrap().dt().as_secs_f32();
 
-    query_iter!(world, (transform: mut Transform, rb: mut Rigidbody, collider: Option<Collider>) => {
-        rb.add_force(gravity / rb.mass, dt);
+    world
+        .query::<(&mut Transform, &mut Rigidbody, Option<&Collider>)>()
+        .par_for_each(|(transform, rb, collider)| {
+            rb.add_force(gravity / rb.mass, dt);
 
-        // simulate one step in the simulation
-        rb.step(dt, transform, collider);
-    });
+            // simulate one step in the simulation
+            rb.step(dt, transform, collider);
+        });
 
     // TODO: this should apply to pairs of entities where at least one of them has a rigidbody, not
     // necessarily both.
-    query_iter_combs!(world, ((tr1, tr2): mut Transform, (rb1, rb2): mut Rigidbody, (c1, c2): Collider) => {
-        collide(tr1, rb1, c1, tr2, rb2, c2);
-    });
+    let mut collisions = world.event_writer::<Collision>();
+    world
+        .query::<(Entity, &mut Transform, &mut Rigidbody, &Collider)>()
+        .for_each_combination(|(a, tr1, rb1, c1), (b, tr2, rb2, c2)| {
+            if collide(tr1, rb1, c1, tr2, rb2, c2) {
+                collisions.send(Collision { a, b });
+            }
+        });
 }
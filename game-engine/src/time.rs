@@ -4,6 +4,9 @@ use ecs::World;
 
 pub const TIME_STEP: Duration = Duration::from_millis(20);
 
+/// The name of the system advancing `Time`, in `Stage::PreUpdate`.
+pub const TIME_SYSTEM: &str = "time";
+
 #[derive(Default)]
 pub struct Time {
     pub(crate) time_since_startup: Duration,
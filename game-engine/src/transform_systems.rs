@@ -0,0 +1,91 @@
+use common::{GlobalTransform, Transform};
+use ecs::{
+    hierarchy::{Children, Parent},
+    query::{With, Without},
+    schedule::System,
+    Entity, World,
+};
+
+/// The name of the system updating every `GlobalTransform`, in `Stage::PostUpdate`.
+pub const TRANSFORM_SYSTEM: &str = "transform";
+
+pub fn system() -> System {
+    System::exclusive(TRANSFORM_SYSTEM, update)
+}
+
+/// Gives every entity with a `Transform` a `GlobalTransform` and propagates the transforms down
+/// the hierarchy, so the `GlobalTransform` of a child is the `GlobalTransform` of its parent
+/// composed with its own `Transform`. Entities without a `Transform` don't move their children.
+pub fn update(world: &mut World) {
+    let missing: Vec<Entity> = world
+        .query_filtered::<Entity, (With<Transform>, Without<GlobalTransform>)>()
+        .iter()
+        .collect();
+    for entity in missing {
+        world.add(entity, GlobalTransform::default());
+    }
+
+    world
+        .query_filtered::<(&Transform, &mut GlobalTransform), Without<Parent>>()
+        .par_for_each(|(transform, global)| global.0 = *transform);
+
+    let roots: Vec<(Entity, Transform)> = world
+        .query_filtered::<(Entity, Option<&GlobalTransform>), (With<Children>, Without<Parent>)>()
+        .iter()
+        .map(|(entity, global)| (entity, global.map_or_else(Transform::identity, |g| g.0)))
+        .collect();
+    let mut children = world.query::<&Children>();
+    let mut transforms = world.query::<(&Transform, &mut GlobalTransform)>();
+    let mut stack = Vec::new();
+    for (root, global) in roots {
+        stack.push((root, global));
+        while let Some((parent, parent_global)) = stack.pop() {
+            for child in children.get(parent).into_iter().flatten() {
+                let global = match transforms.get(child) {
+                    Some((transform, global)) => {
+                        global.0 = parent_global.mul_transform(transform);
+                        global.0
+                    }
+                    None => parent_global,
+                };
+                stack.push((child, global));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_propagation() {
+    use common::{Quaternion, Vec3};
+
+    let transform = |x: f32| Transform {
+        position: Vec3::new(x, 0.0, 0.0),
+        rotation: Quaternion::rotation_y(std::f32::consts::FRAC_PI_2),
+        scale: Vec3::broadcast(2.0),
+    };
+
+    let mut world = World::default();
+    let root = world.spawn();
+    world.add(root, transform(1.0));
+    let group = world.spawn();
+    world.set_parent(group, root);
+    let child = world.spawn();
+    world.add(child, transform(1.0));
+    world.set_parent(child, group);
+
+    update(&mut world);
+    let global = |e| world.get::<GlobalTransform>(e).unwrap().0;
+    assert!(world.get::<GlobalTransform>(group).is_none());
+    assert_eq!(global(root).position, Vec3::new(1.0, 0.0, 0.0));
+    // Rotated a quarter turn around y and scaled by the root: x becomes -z
+    let expected = Vec3::new(1.0, 0.0, -2.0);
+    assert!((global(child).position - expected).magnitude() < 1e-5);
+    assert_eq!(global(child).scale, Vec3::broadcast(4.0));
+
+    world.remove_parent(group);
+    update(&mut world);
+    assert_eq!(
+        world.get::<GlobalTransform>(child).unwrap().0.position,
+        Vec3::new(1.0, 0.0, 0.0)
+    );
+}
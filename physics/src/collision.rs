@@ -1,4 +1,6 @@
 use common::{Mat3, Transform, Vec3};
+use ecs::Entity;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     cube::collision::collide_cube_vs_cube,
@@ -12,12 +14,19 @@ use crate::{
     PhysicsMaterial, Rigidbody,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Collider {
     Sphere(SphereCollider),
     Cube(CubeCollider),
 }
 
+ecs::impl_reflect!(
+    enum Collider {
+        Sphere,
+        Cube,
+    }
+);
+
 impl Collider {
     pub fn inv_inertia_tensor(&self) -> Mat3 {
         match self {
@@ -27,6 +36,14 @@ impl Collider {
     }
 }
 
+/// An event sent when two entities collide, once for every time step they touch. See
+/// `ecs::event::Events`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Collision {
+    pub a: Entity,
+    pub b: Entity,
+}
+
 /// Returns true if 2 objects are colliding
 pub fn is_colliding(c1: &Collider, t1: &Transform, c2: &Collider, t2: &Transform) -> bool {
     let w1 = get_position(t1, c1);
@@ -51,6 +68,7 @@ pub fn is_colliding(c1: &Collider, t1: &Transform, c2: &Collider, t2: &Transform
     }
 }
 
+#[allow(dead_code)]
 pub fn bounce(input: Vec3, normal: Vec3) -> Vec3 {
     fn proj(on: Vec3, vec: Vec3) -> Vec3 {
         vec.dot(on) * on / on.magnitude_squared()
@@ -219,9 +237,9 @@ pub fn collide(
     t2: &mut Transform,
     rb2: &mut Rigidbody,
     c2: &Collider,
-) {
+) -> bool {
     if rb1.is_static && rb2.is_static {
-        return;
+        return false;
     }
 
     if is_colliding(c1, t1, c2, t2) {
@@ -239,5 +257,7 @@ pub fn collide(
         }
 
         solve_colliding(c1, rb1, t1, c2, rb2, t2);
+        return true;
     }
+    false
 }
@@ -1,4 +1,5 @@
 use common::{Mat3, Quaternion, Vec3};
+use serde::{Deserialize, Serialize};
 
 pub(crate) mod collision;
 pub(crate) mod mesh;
@@ -6,7 +7,7 @@ pub(crate) mod sat;
 
 use crate::{clamp, macros::debug_assert_finite, PhysicsMaterial};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct CubeCollider {
     pub local_position: Vec3,
     pub local_rotation: Quaternion,
@@ -14,6 +15,13 @@ pub struct CubeCollider {
     pub material: PhysicsMaterial,
 }
 
+ecs::impl_reflect!(CubeCollider {
+    local_position,
+    local_rotation,
+    scale,
+    material
+});
+
 impl CubeCollider {
     pub fn new(scale: Vec3, material: PhysicsMaterial) -> Self {
         Self {
@@ -20,6 +20,7 @@ pub fn is_colliding_cube_vs_cube(
     proj_has_overlap(&axis, &a_verts, &b_verts) || proj_has_overlap(&axis, &b_verts, &a_verts)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn collide_cube_vs_cube(
     c1: &CubeCollider,
     rb1: &mut Rigidbody,
@@ -40,6 +41,7 @@ pub fn collide_cube_vs_cube(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn collide_cube_vs_cube_single(
     c1: &CubeCollider,
     rb1: &mut Rigidbody,
@@ -1,4 +1,5 @@
 use common::{Quaternion, Transform, Vec3};
+use serde::{Deserialize, Serialize};
 
 use macros::debug_assert_finite;
 
@@ -10,13 +11,17 @@ mod sphere;
 
 pub use collision::collide;
 pub use collision::Collider;
+pub use collision::Collision;
 pub use cube::CubeCollider;
-pub use raycast::RayCastHit;
+pub use raycast::{raycast, RayCastHit};
 pub use rigidbody::Rigidbody;
 pub use sphere::SphereCollider;
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Gravity(pub Vec3);
 
+ecs::impl_reflect!(Gravity { 0 });
+
 impl std::default::Default for Gravity {
     fn default() -> Self {
         Gravity(Vec3::new(0., -9.81, 0.))
@@ -74,12 +79,17 @@ fn get_position(transform: &Transform, collider: &Collider) -> Vec3 {
     )
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct PhysicsMaterial {
     pub friction: f32,
     pub restfullness: f32, // bounciness
 }
 
+ecs::impl_reflect!(PhysicsMaterial {
+    friction,
+    restfullness
+});
+
 mod macros {
     macro_rules! debug_assert_finite {
         ($vec:expr) => {
@@ -63,7 +63,7 @@ pub fn raycast_cube(t: &Transform, c: &CubeCollider, ray: Ray) -> Option<RayCast
     let mut normal = Vec3::zero();
     for tri in tris {
         if let Some(d) = fixed_ray.triangle_intersection(tri) {
-            if d < std::f32::EPSILON {
+            if d < f32::EPSILON {
                 continue;
             }
 
@@ -1,8 +1,9 @@
 use common::{Mat3, Transform, Vec3};
+use serde::{Deserialize, Serialize};
 
 use crate::{macros::debug_assert_finite, Collider};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Rigidbody {
     pub angular_momentum: Vec3,
     pub linear_momentum: Vec3,
@@ -14,6 +15,13 @@ pub struct Rigidbody {
     pub is_static: bool,
 }
 
+ecs::impl_reflect!(Rigidbody {
+    angular_momentum,
+    linear_momentum,
+    mass,
+    is_static
+});
+
 impl Default for Rigidbody {
     fn default() -> Self {
         Rigidbody::new(1.0)
@@ -1,16 +1,23 @@
 use common::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
 
 pub mod collision;
 
 use crate::PhysicsMaterial;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct SphereCollider {
     pub local_position: Vec3,
     pub radius: f32,
     pub material: PhysicsMaterial,
 }
 
+ecs::impl_reflect!(SphereCollider {
+    local_position,
+    radius,
+    material
+});
+
 impl SphereCollider {
     pub fn new(radius: f32, material: PhysicsMaterial) -> Self {
         Self {
@@ -46,6 +46,7 @@ pub fn is_colliding_sphere_vs_cube(
     closest_point.distance_squared(w1) < r_squared
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn collide_sphere_vs_sphere(
     c1: &SphereCollider,
     rb1: &mut Rigidbody,
@@ -83,6 +84,7 @@ pub fn collide_sphere_vs_sphere(
     pop_colliders(distance_pop * normal, t1, t2, rb1, rb2);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn collide_sphere_vs_cube(
     c1: &SphereCollider,
     rb1: &mut Rigidbody,
@@ -1,6 +1,3 @@
-#[macro_use]
-extern crate wgpu;
-
 pub mod camera;
 pub mod model;
 pub mod texture;
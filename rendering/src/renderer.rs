@@ -1,7 +1,6 @@
 use std::iter;
 use std::mem;
 
-use egui;
 use pollster::FutureExt;
 use raw_window_handle::HasRawWindowHandle;
 
@@ -27,13 +26,15 @@ impl RawTranslationMatrix {
 
         Self {
             model: unsafe {
-                mem::transmute::<Mat4, _>(
+                mem::transmute::<Mat4, [[f32; 4]; 4]>(
                     Mat4::translation_3d(transform.position)
                         * Mat4::from(transform.rotation)
                         * Mat4::with_diagonal(Vec4::new(x, y, z, 1.0)),
                 )
             },
-            rotation: unsafe { mem::transmute::<Mat3, _>(Mat3::from(transform.rotation)) },
+            rotation: unsafe {
+                mem::transmute::<Mat3, [[f32; 3]; 3]>(Mat3::from(transform.rotation))
+            },
         }
     }
 }
@@ -375,12 +376,12 @@ impl Renderer {
         self.worlds[0].load_model(&self.device, &self.queue, path)
     }
 
-    pub fn get_models_mut(&mut self) -> ModelManager {
+    pub fn get_models_mut(&mut self) -> ModelManager<'_> {
         self.worlds[0].get_models_mut(&self.device, &self.queue)
     }
 
     pub fn update_camera(&mut self) {
-        self.worlds[0].camera = self.camera.clone();
+        self.worlds[0].camera = self.camera;
         self.worlds[0].update_camera(&self.queue, self.painter.last_aspect);
     }
 
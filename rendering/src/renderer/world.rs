@@ -7,6 +7,7 @@ use crate::model::{DrawModel, Model, ModelIndex, ModelManager, ModelStorage, Mod
 use crate::renderer::Renderer;
 use crate::texture;
 use common::Vec3;
+use serde::{Deserialize, Serialize};
 
 use std::mem;
 
@@ -155,7 +156,7 @@ impl LightSpheres {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Light {
     pub pos: Vec3,
     pub color: [f32; 3],
@@ -174,11 +175,9 @@ impl Light {
             ..
         } = self;
         let almost_black = 1.0 / (5.0 / 256.0 / 12.92); // convert sRGB to linear
-        let radius = (-linear
+        (-linear
             + f32::sqrt(linear * linear - 4.0 * quadratic * (constant - almost_black * light_max)))
-            / (2.0 * quadratic);
-
-        radius
+            / (2.0 * quadratic)
     }
 }
 
@@ -848,6 +847,9 @@ impl World {
         );
     }
 
+    // Renders into a pass owned by the caller, not used since `render` and `render_deferred`
+    // make their own passes
+    #[allow(dead_code)]
     pub fn render_rpass<'a>(
         &'a mut self,
         device: &wgpu::Device,
@@ -928,7 +930,7 @@ impl World {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &render_target,
+                    view: render_target,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a: 1.0 }),
@@ -1036,7 +1038,7 @@ impl World {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Line Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &render_target,
+                    view: render_target,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Load,
@@ -263,6 +263,7 @@ impl Painter {
 
     // weird lifetimes, self must outlive renderpass
     // as renderpass borrows some of the buffers in self.
+    #[allow(clippy::too_many_arguments)]
     pub fn paint<'a, 'b: 'a>(
         &'b mut self,
         device: &wgpu::Device,
@@ -507,15 +508,13 @@ impl Painter {
             // the entry API, possibly we could move away from ahash altogether.
             let tex_exists = self.textures.get(&id).is_some();
 
-            let texture;
-
-            if tex_exists {
-                texture = &self.textures.get(&id).unwrap().tex;
+            let texture = if tex_exists {
+                &self.textures.get(&id).unwrap().tex
             } else {
                 let tex = self.make_tex(id, device, texture_size);
                 self.textures.insert(id, tex);
-                texture = &self.textures.get(&id).unwrap().tex;
-            }
+                &self.textures.get(&id).unwrap().tex
+            };
 
             // I think offset is just an index into the image buffer?
             // if it isn't then this is **completely** wrong,